@@ -2,10 +2,15 @@ use env_logger::Env;
 use glam::Vec3A;
 use log::info;
 
-use raytracer::canvas::Canvas;
+use raytracer::antialiasing::{ReconstructionFilter, Supersampling};
+use raytracer::background::Background;
+use raytracer::camera::{view_transform, Camera};
 use raytracer::color::Color;
-use raytracer::ray::Ray;
+use raytracer::integrator::{DebugIntegrator, DebugView};
+use raytracer::material::Material;
+use raytracer::sampler::StratifiedSampler;
 use raytracer::sphere::Sphere;
+use raytracer::world::World;
 
 const CANVAS_PIXELS: u16 = 800;
 const PPM_FILE_PATH: &str = "circle.ppm";
@@ -17,33 +22,38 @@ fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("debug")).init();
     info!("Running circle example");
 
-    let wall_z = 10_f32;
+    // The eye is 5 units in front of the sphere, looking at a 7 units wide wall 10 units behind it
+    let wall_distance = 15_f32;
     let wall_size = 7_f32;
-    let pixel_size = wall_size / CANVAS_PIXELS as f32;
-    let middle = wall_size / 2.0;
-
-    let mut canvas =
-        Canvas::new_with_initial_color(CANVAS_PIXELS, CANVAS_PIXELS, Color::new_white());
-    let ray_origin = Vec3A::new(0.0, 0.0, -5.0);
-    let sphere_color = Color::new_red();
-    let sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
-
-    for y in 0..CANVAS_PIXELS {
-        let world_y = middle - pixel_size * y as f32;
-
-        for x in 0..CANVAS_PIXELS {
-            let world_x = -middle + pixel_size * x as f32;
-
-            // describe the point on the wall that the ray will target
-            let position = Vec3A::new(world_x, world_y, wall_z);
-            let ray = Ray::new(ray_origin, (position - ray_origin).normalize());
-            let intersection = sphere.intersect(ray);
-
-            if intersection.hit().is_some() {
-                canvas.write_pixel(x, y, sphere_color);
-            }
-        }
-    }
+    let field_of_view = 2.0 * (wall_size / 2.0 / wall_distance).atan();
+
+    let mut sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+    sphere.set_material(Material {
+        color: Color::new_red(),
+        ..Default::default()
+    });
+    let mut world = World::new_empty();
+    world.add_object(sphere);
+    world.set_background(Background::SolidColor(Color::new_white()));
+
+    let mut camera = Camera::new(CANVAS_PIXELS, CANVAS_PIXELS, field_of_view);
+    camera.set_transform(view_transform(
+        Vec3A::new(0.0, 0.0, -5.0),
+        Vec3A::new(0.0, 0.0, 0.0),
+        Vec3A::new(0.0, 1.0, 0.0),
+    ));
+    // Jittered samples combined with a Mitchell-Netravali filter smooth out the edges of the circle
+    camera.set_supersampling(Supersampling::new(
+        StratifiedSampler::new(4, 4, true, 0),
+        ReconstructionFilter::MitchellNetravali {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        },
+    ));
+
+    // Unlit material color shows just the silhouette of the sphere
+    let canvas = camera.render_with(&world, &DebugIntegrator::new(DebugView::Albedo));
 
     canvas.export_as_ppm_and_save_to_file(PPM_FILE_PATH);
     canvas.export_to_desired_format_based_on_extension(PNG_FILE_PATH);
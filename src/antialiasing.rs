@@ -0,0 +1,526 @@
+use glam::Vec2;
+
+use crate::{
+    color::Color,
+    sampler::{Sampler, StratifiedSampler},
+};
+
+/// Filter used to weight samples when combining them into a pixel. Radius is in pixels,
+/// the filter is separable, so the 2D weight is a product of the weights along x and y.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ReconstructionFilter {
+    Box { radius: f32 },
+    Tent { radius: f32 },
+    Gaussian { radius: f32, alpha: f32 },
+    MitchellNetravali { radius: f32, b: f32, c: f32 },
+}
+
+impl ReconstructionFilter {
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Self::Box { radius }
+            | Self::Tent { radius }
+            | Self::Gaussian { radius, .. }
+            | Self::MitchellNetravali { radius, .. } => radius,
+        }
+    }
+
+    /// Weight of a sample at the offset from the pixel center, in pixels.
+    pub fn weight(&self, offset: Vec2) -> f32 {
+        self.weight_1d(offset.x) * self.weight_1d(offset.y)
+    }
+
+    fn weight_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+
+        match *self {
+            Self::Box { .. } => 1.0,
+            Self::Tent { radius } => radius - x,
+            Self::Gaussian { radius, alpha } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+            Self::MitchellNetravali { radius, b, c } => {
+                // The cubic is defined over -2.0 - 2.0, so the offset is remapped to that range
+                let x = 2.0 * x / radius;
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x.powi(2)
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+        }
+    }
+}
+
+/// Stops sampling a pixel once the estimated error of its color is below the threshold. The error is
+/// the standard error of the mean luminance of the samples taken so far. The sampler's samples per pixel
+/// is the upper limit, flat areas (e.g. the background) stop at the minimum.
+///
+/// The error is only checked after a power of two samples, where prefixes of the Sobol and Halton sequences
+/// are stratified. Samplers whose prefixes are not (`StratifiedSampler`) can not be used for adaptive sampling.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct AdaptiveSampling {
+    min_samples: u32,
+    error_threshold: f32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: u32, error_threshold: f32) -> Self {
+        if min_samples < 2 {
+            panic!(
+                "Adaptive sampling needs at least two samples to estimate variance, min samples: {}",
+                min_samples
+            );
+        }
+
+        Self {
+            min_samples,
+            error_threshold,
+        }
+    }
+
+    pub fn min_samples(&self) -> u32 {
+        self.min_samples
+    }
+
+    pub fn error_threshold(&self) -> f32 {
+        self.error_threshold
+    }
+}
+
+/// Running mean and variance of the values, updated one value at a time (Welford's algorithm).
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct RunningStatistics {
+    count: u32,
+    mean: f32,
+    squared_deviations: f32,
+}
+
+impl RunningStatistics {
+    pub fn add(&mut self, value: f32) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f32;
+        self.squared_deviations += delta * (value - self.mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.mean
+    }
+
+    /// Unbiased sample variance, zero until there are at least two values.
+    pub fn variance(&self) -> f32 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.squared_deviations / (self.count - 1) as f32
+    }
+
+    /// Estimated error of the mean.
+    pub fn standard_error(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        (self.variance() / self.count as f32).sqrt()
+    }
+}
+
+/// Settings for rendering multiple samples per pixel and combining them with a reconstruction filter.
+#[derive(Debug, Clone)]
+pub struct Supersampling {
+    /// Decides the number of samples per pixel and where within the filter footprint they are taken
+    sampler: Box<dyn Sampler>,
+    pub filter: ReconstructionFilter,
+    /// Takes fewer samples in pixels that converge quickly, `None` always takes all of the sampler's samples
+    adaptive: Option<AdaptiveSampling>,
+}
+
+impl Default for Supersampling {
+    /// Single sample in the pixel center, the same as rendering without anti-aliasing.
+    fn default() -> Self {
+        Self {
+            sampler: Box::new(StratifiedSampler::new(1, 1, false, 0)),
+            filter: ReconstructionFilter::Box { radius: 0.5 },
+            adaptive: None,
+        }
+    }
+}
+
+impl Supersampling {
+    pub fn new(sampler: impl Sampler + 'static, filter: ReconstructionFilter) -> Self {
+        Self {
+            sampler: Box::new(sampler),
+            filter,
+            adaptive: None,
+        }
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.sampler.samples_per_pixel()
+    }
+
+    pub fn sampler(&self) -> &dyn Sampler {
+        self.sampler.as_ref()
+    }
+
+    /// Replaces the sampler, adaptive sampling that is already set has to work with the new one.
+    pub fn set_sampler(&mut self, sampler: impl Sampler + 'static) {
+        if let Some(adaptive) = &self.adaptive {
+            check_adaptive_sampler(adaptive, &sampler);
+        }
+        self.sampler = Box::new(sampler);
+    }
+
+    pub fn adaptive(&self) -> Option<AdaptiveSampling> {
+        self.adaptive
+    }
+
+    pub fn set_adaptive(&mut self, adaptive: AdaptiveSampling) {
+        check_adaptive_sampler(&adaptive, self.sampler.as_ref());
+        self.adaptive = Some(adaptive)
+    }
+
+    pub fn pixel_color(
+        &self,
+        x: u16,
+        y: u16,
+        radiance: impl FnMut(f32, f32, &mut dyn Sampler) -> Color,
+    ) -> Color {
+        self.pixel_color_with_sample_count(x, y, radiance).0
+    }
+
+    /// Computes the pixel color from radiance samples and returns it with the number of samples taken.
+    /// The radiance function gets film coordinates, where pixel (x, y) spans from (x, y) to (x + 1, y + 1),
+    /// and the sampler for the rest of the sample's dimensions. Samples cover the whole filter footprint, so filters wider than a pixel also gather
+    /// samples from the neighbouring pixels' area.
+    pub fn pixel_color_with_sample_count(
+        &self,
+        x: u16,
+        y: u16,
+        mut radiance: impl FnMut(f32, f32, &mut dyn Sampler) -> Color,
+    ) -> (Color, u32) {
+        let mut sampler = self.sampler.clone();
+        let pixel_center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+        let footprint = self.filter.radius() * 2.0;
+        let samples_per_pixel = sampler.samples_per_pixel();
+
+        let mut weighted_sum = Color::new_black();
+        let mut weight_sum = 0.0;
+        let mut plain_sum = Color::new_black();
+        let mut statistics = RunningStatistics::default();
+        for sample_index in 0..samples_per_pixel {
+            sampler.start_pixel_sample(x, y, sample_index);
+            let offset = (sampler.next_2d() - 0.5) * footprint;
+            let film_point = pixel_center + offset;
+            let color = radiance(film_point.x, film_point.y, sampler.as_mut());
+            let weight = self.filter.weight(offset);
+            weighted_sum += color * weight;
+            weight_sum += weight;
+            plain_sum += color;
+            statistics.add(color.luminance());
+
+            if let Some(adaptive) = self.adaptive {
+                if statistics.count() >= adaptive.min_samples
+                    && statistics.count().is_power_of_two()
+                    && statistics.standard_error() <= adaptive.error_threshold
+                {
+                    break;
+                }
+            }
+        }
+
+        let color = if weight_sum > f32::EPSILON {
+            weighted_sum / weight_sum
+        } else {
+            // Filters with negative lobes can cancel out, an unweighted average is the best fallback
+            plain_sum / statistics.count() as f32
+        };
+        (color, statistics.count())
+    }
+}
+
+fn check_adaptive_sampler(adaptive: &AdaptiveSampling, sampler: &dyn Sampler) {
+    if adaptive.min_samples > sampler.samples_per_pixel() {
+        panic!(
+            "Adaptive sampling minimum should not exceed the samples per pixel, min samples: {}, samples per pixel: {}",
+            adaptive.min_samples,
+            sampler.samples_per_pixel()
+        );
+    }
+    if !sampler.has_unbiased_prefixes() {
+        panic!(
+            "Adaptive sampling should stop only after samples that cover the pixel evenly, sampler: {:?}",
+            sampler
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use crate::sampler::{HaltonSampler, IndependentSampler, SobolSampler};
+
+    use super::*;
+
+    #[rstest]
+    #[case::box_filter(ReconstructionFilter::Box { radius: 0.5 })]
+    #[case::tent(ReconstructionFilter::Tent { radius: 1.0 })]
+    #[case::gaussian(ReconstructionFilter::Gaussian { radius: 1.5, alpha: 2.0 })]
+    #[case::mitchell_netravali(ReconstructionFilter::MitchellNetravali {
+        radius: 2.0,
+        b: 1.0 / 3.0,
+        c: 1.0 / 3.0
+    })]
+    fn filters_are_zero_outside_of_their_radius(#[case] filter: ReconstructionFilter) {
+        let radius = filter.radius();
+        assert!(filter.weight(Vec2::ZERO) > 0.0);
+        assert_abs_diff_eq!(filter.weight(Vec2::new(radius + 0.01, 0.0)), 0.0);
+        assert_abs_diff_eq!(filter.weight(Vec2::new(0.0, -radius - 0.01)), 0.0);
+    }
+
+    #[test]
+    fn filter_weights_decrease_away_from_the_center() {
+        let tent = ReconstructionFilter::Tent { radius: 1.0 };
+        assert_abs_diff_eq!(tent.weight(Vec2::new(0.5, 0.0)), 0.5);
+        assert_abs_diff_eq!(tent.weight(Vec2::new(0.5, 0.5)), 0.25);
+
+        let gaussian = ReconstructionFilter::Gaussian {
+            radius: 1.5,
+            alpha: 2.0,
+        };
+        assert!(gaussian.weight(Vec2::new(0.5, 0.0)) < gaussian.weight(Vec2::ZERO));
+
+        let mitchell = ReconstructionFilter::MitchellNetravali {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        assert_abs_diff_eq!(
+            mitchell.weight(Vec2::ZERO),
+            (8.0 / 9.0_f32).powi(2),
+            epsilon = 1e-6
+        );
+        // Negative lobe
+        assert!(mitchell.weight(Vec2::new(1.5, 0.0)) < 0.0);
+    }
+
+    fn film_points(supersampling: &Supersampling, x: u16, y: u16) -> Vec<Vec2> {
+        let mut points = vec![];
+        supersampling.pixel_color(x, y, |film_x, film_y, _| {
+            points.push(Vec2::new(film_x, film_y));
+            Color::new_black()
+        });
+        points
+    }
+
+    #[test]
+    fn default_supersampling_samples_the_pixel_center() {
+        let supersampling = Supersampling::default();
+        assert_eq!(film_points(&supersampling, 3, 4), vec![Vec2::new(3.5, 4.5)]);
+    }
+
+    #[test]
+    fn regular_grid_samples_cover_the_filter_footprint() {
+        let supersampling = Supersampling::new(
+            StratifiedSampler::new(2, 2, false, 0),
+            ReconstructionFilter::Tent { radius: 1.0 },
+        );
+        let mut points: Vec<(f32, f32)> = film_points(&supersampling, 0, 0)
+            .into_iter()
+            .map(Vec2::into)
+            .collect();
+        points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(points, vec![(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)]);
+    }
+
+    #[rstest]
+    #[case::independent(Box::new(IndependentSampler::new(10, 0)))]
+    #[case::stratified(Box::new(StratifiedSampler::new(4, 4, true, 0)))]
+    #[case::halton(Box::new(HaltonSampler::new(16, 0)))]
+    #[case::sobol(Box::new(SobolSampler::new(16, 0)))]
+    fn random_samples_are_reproducible_and_stay_within_the_filter(
+        #[case] sampler: Box<dyn Sampler>,
+    ) {
+        let expected_samples = sampler.samples_per_pixel() as usize;
+        let supersampling = Supersampling {
+            sampler,
+            filter: ReconstructionFilter::Tent { radius: 1.0 },
+            ..Default::default()
+        };
+        let points = film_points(&supersampling, 7, 9);
+        assert_eq!(points.len(), expected_samples);
+        assert_eq!(points, film_points(&supersampling, 7, 9));
+        let center = Vec2::new(7.5, 9.5);
+        assert!(points
+            .iter()
+            .all(|point| (*point - center).abs().max_element() <= 1.0));
+    }
+
+    #[test]
+    fn pixel_color_averages_samples_across_an_edge() {
+        // Left half of the pixel is white, right half is black
+        let supersampling = Supersampling::new(
+            StratifiedSampler::new(4, 4, false, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        );
+        let color = supersampling.pixel_color(2, 0, |film_x, _, _| {
+            if film_x < 2.5 {
+                Color::new_white()
+            } else {
+                Color::new_black()
+            }
+        });
+        assert_abs_diff_eq!(color.get_red_val(), 0.5);
+    }
+
+    #[test]
+    fn radiance_draws_further_dimensions_from_the_same_sampler() {
+        let supersampling = Supersampling::new(
+            StratifiedSampler::new(4, 4, true, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        );
+        // Stratified 1D dimension averages to one half, much closer than random values would
+        let color = supersampling.pixel_color(0, 0, |_, _, sampler| {
+            let value = sampler.next_1d();
+            Color::new_color(value, value, value)
+        });
+        assert_abs_diff_eq!(color.get_red_val(), 0.5, epsilon = 1.0 / 32.0);
+    }
+
+    #[test]
+    fn pixel_color_of_a_constant_image_does_not_depend_on_the_filter() {
+        let gray = Color::new_color(0.25, 0.5, 0.75);
+        for filter in [
+            ReconstructionFilter::Tent { radius: 1.0 },
+            ReconstructionFilter::Gaussian {
+                radius: 1.5,
+                alpha: 2.0,
+            },
+            ReconstructionFilter::MitchellNetravali {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+        ] {
+            let supersampling = Supersampling::new(StratifiedSampler::new(3, 3, true, 0), filter);
+            let color = supersampling.pixel_color(1, 1, |_, _, _| gray);
+            assert_abs_diff_eq!(color.get_red_val(), 0.25, epsilon = 1e-5);
+            assert_abs_diff_eq!(color.get_green_val(), 0.5, epsilon = 1e-5);
+            assert_abs_diff_eq!(color.get_blue_val(), 0.75, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn running_statistics_track_mean_and_variance() {
+        let mut statistics = RunningStatistics::default();
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            statistics.add(value);
+        }
+        assert_eq!(statistics.count(), 8);
+        assert_abs_diff_eq!(statistics.mean(), 5.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(statistics.variance(), 32.0 / 7.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(
+            statistics.standard_error(),
+            (32.0_f32 / 7.0 / 8.0).sqrt(),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_early_in_flat_areas() {
+        let mut supersampling = Supersampling::new(
+            SobolSampler::new(64, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        );
+        supersampling.set_adaptive(AdaptiveSampling::new(4, 0.01));
+
+        let (color, samples) =
+            supersampling.pixel_color_with_sample_count(0, 0, |_, _, _| Color::new_white());
+        assert_eq!(color, Color::new_white());
+        assert_eq!(samples, 4);
+
+        // Half covered pixel keeps sampling until the limit
+        let (color, samples) = supersampling.pixel_color_with_sample_count(0, 0, |film_x, _, _| {
+            if film_x < 0.5 {
+                Color::new_white()
+            } else {
+                Color::new_black()
+            }
+        });
+        assert_eq!(samples, 64);
+        assert_abs_diff_eq!(color.get_red_val(), 0.5);
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_after_a_power_of_two_samples() {
+        let mut supersampling = Supersampling::new(
+            SobolSampler::new(64, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        );
+        supersampling.set_adaptive(AdaptiveSampling::new(5, 0.01));
+        let (_, samples) =
+            supersampling.pixel_color_with_sample_count(0, 0, |_, _, _| Color::new_white());
+        assert_eq!(samples, 8);
+    }
+
+    #[test]
+    #[should_panic]
+    fn adaptive_sampling_needs_at_least_two_samples() {
+        AdaptiveSampling::new(1, 0.01);
+    }
+
+    #[test]
+    #[should_panic(expected = "Adaptive sampling minimum should not exceed the samples per pixel")]
+    fn adaptive_sampling_minimum_is_within_the_samples_per_pixel() {
+        Supersampling::new(
+            SobolSampler::new(4, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        )
+        .set_adaptive(AdaptiveSampling::new(8, 0.01));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Adaptive sampling should stop only after samples that cover the pixel evenly"
+    )]
+    fn adaptive_sampling_rejects_shuffled_strata() {
+        Supersampling::new(
+            StratifiedSampler::new(4, 4, true, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        )
+        .set_adaptive(AdaptiveSampling::new(4, 0.01));
+    }
+
+    #[test]
+    #[should_panic(expected = "Adaptive sampling minimum should not exceed the samples per pixel")]
+    fn swapped_sampler_keeps_adaptive_sampling_valid() {
+        let mut supersampling = Supersampling::new(
+            SobolSampler::new(16, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        );
+        supersampling.set_adaptive(AdaptiveSampling::new(8, 0.01));
+        assert_eq!(
+            supersampling
+                .adaptive()
+                .map(|adaptive| adaptive.min_samples()),
+            Some(8)
+        );
+        supersampling.set_sampler(SobolSampler::new(4, 0));
+    }
+}
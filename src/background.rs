@@ -0,0 +1,264 @@
+use std::f32::consts::PI;
+
+use glam::Vec3A;
+use image::ImageError;
+
+use crate::{color::Color, sky::Sky, texture::ImageTexture};
+
+/// What a ray sees when it does not hit any object in the world.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Background {
+    SolidColor(Color),
+    CubeMap(CubeMap),
+    Equirectangular(ImageTexture),
+    /// Analytic daylight sky with the sun
+    Sky(Sky),
+}
+
+impl Background {
+    /// Loads single equirectangular (latitude-longitude) image, usually an HDR panorama.
+    pub fn new_equirectangular_from_file(image_file_path: &str) -> Result<Self, ImageError> {
+        Ok(Self::Equirectangular(ImageTexture::load_from_file(
+            image_file_path,
+        )?))
+    }
+
+    /// Loads six cube map faces, in the order defined by `CubeFace`: right, left, up, down, front, back.
+    pub fn new_cube_map_from_files(face_file_paths: [&str; 6]) -> Result<Self, ImageError> {
+        let [right, left, up, down, front, back] =
+            face_file_paths.map(ImageTexture::load_from_file);
+        Ok(Self::CubeMap(CubeMap::new([
+            right?, left?, up?, down?, front?, back?,
+        ])))
+    }
+
+    pub fn color_for_direction(&self, direction: Vec3A) -> Color {
+        match self {
+            Self::SolidColor(color) => *color,
+            Self::CubeMap(cube_map) => cube_map.color_for_direction(direction),
+            Self::Equirectangular(texture) => {
+                let (u, v) = direction_to_equirectangular_uv(direction);
+                texture.sample_bilinear(u, v)
+            }
+            Self::Sky(sky) => sky.color_for_direction(direction),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CubeFace {
+    Right,
+    Left,
+    Up,
+    Down,
+    Front,
+    Back,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CubeMap {
+    faces: [ImageTexture; 6],
+}
+
+impl CubeMap {
+    pub fn new(faces: [ImageTexture; 6]) -> Self {
+        Self { faces }
+    }
+
+    pub fn face(&self, face: CubeFace) -> &ImageTexture {
+        &self.faces[face as usize]
+    }
+
+    pub fn color_for_direction(&self, direction: Vec3A) -> Color {
+        // Project the direction onto the surface of the unit cube first
+        let point = direction / direction.abs().max_element();
+        let face = face_from_point(point);
+        let (u, v) = cube_face_uv(face, point);
+        self.face(face).sample_bilinear(u, v)
+    }
+}
+
+pub fn face_from_point(point: Vec3A) -> CubeFace {
+    let abs_x = point.x.abs();
+    let abs_y = point.y.abs();
+    let abs_z = point.z.abs();
+    let coord = abs_x.max(abs_y).max(abs_z);
+
+    if coord == point.x {
+        CubeFace::Right
+    } else if coord == -point.x {
+        CubeFace::Left
+    } else if coord == point.y {
+        CubeFace::Up
+    } else if coord == -point.y {
+        CubeFace::Down
+    } else if coord == point.z {
+        CubeFace::Front
+    } else {
+        CubeFace::Back
+    }
+}
+
+/// Texture coordinates of a point on the surface of the unit cube (-1.0 to 1.0 on every axis).
+pub fn cube_face_uv(face: CubeFace, point: Vec3A) -> (f32, f32) {
+    let (u, v) = match face {
+        CubeFace::Front => (point.x + 1.0, point.y + 1.0),
+        CubeFace::Back => (1.0 - point.x, point.y + 1.0),
+        CubeFace::Left => (point.z + 1.0, point.y + 1.0),
+        CubeFace::Right => (1.0 - point.z, point.y + 1.0),
+        CubeFace::Up => (point.x + 1.0, 1.0 - point.z),
+        CubeFace::Down => (point.x + 1.0, point.z + 1.0),
+    };
+    (u.rem_euclid(2.0) / 2.0, v.rem_euclid(2.0) / 2.0)
+}
+
+/// Spherical mapping of a direction, the same one used for spheres in the book.
+pub fn direction_to_equirectangular_uv(direction: Vec3A) -> (f32, f32) {
+    let direction = direction.normalize();
+    let theta = direction.x.atan2(direction.z);
+    let phi = direction.y.clamp(-1.0, 1.0).acos();
+    let raw_u = theta / (2.0 * PI);
+    let u = 1.0 - (raw_u + 0.5);
+    let v = 1.0 - phi / PI;
+    (u, v)
+}
+
+/// Inverse of `direction_to_equirectangular_uv`, the unit direction seen at the point of the panorama.
+pub fn equirectangular_uv_to_direction(u: f32, v: f32) -> Vec3A {
+    let theta = (0.5 - u) * 2.0 * PI;
+    let phi = (1.0 - v) * PI;
+    Vec3A::new(phi.sin() * theta.sin(), phi.cos(), phi.sin() * theta.cos())
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    fn single_color_texture(color: Color) -> ImageTexture {
+        ImageTexture::new(1, 1, vec![color])
+    }
+
+    #[fixture]
+    pub fn cube_map() -> CubeMap {
+        CubeMap::new([
+            single_color_texture(Color::new_red()),
+            single_color_texture(Color::new_color(1.0, 1.0, 0.0)),
+            single_color_texture(Color::new_color(0.0, 1.0, 1.0)),
+            single_color_texture(Color::new_green()),
+            single_color_texture(Color::new_color(1.0, 0.0, 1.0)),
+            single_color_texture(Color::new_blue()),
+        ])
+    }
+
+    #[rstest]
+    #[case(Vec3A::new(-1.0, 0.5, -0.25), CubeFace::Left)]
+    #[case(Vec3A::new(1.1, -0.75, 0.8), CubeFace::Right)]
+    #[case(Vec3A::new(0.1, 0.6, 0.9), CubeFace::Front)]
+    #[case(Vec3A::new(-0.7, 0.0, -2.0), CubeFace::Back)]
+    #[case(Vec3A::new(0.5, 1.0, 0.9), CubeFace::Up)]
+    #[case(Vec3A::new(-0.2, -1.3, 1.1), CubeFace::Down)]
+    fn can_identify_the_face_of_a_cube_from_a_point(
+        #[case] point: Vec3A,
+        #[case] expected_face: CubeFace,
+    ) {
+        assert_eq!(face_from_point(point), expected_face);
+    }
+
+    #[rstest]
+    #[case(CubeFace::Front, Vec3A::new(-0.5, 0.5, 1.0), 0.25, 0.75)]
+    #[case(CubeFace::Front, Vec3A::new(0.5, -0.5, 1.0), 0.75, 0.25)]
+    #[case(CubeFace::Back, Vec3A::new(0.5, 0.5, -1.0), 0.25, 0.75)]
+    #[case(CubeFace::Left, Vec3A::new(-1.0, 0.5, -0.5), 0.25, 0.75)]
+    #[case(CubeFace::Right, Vec3A::new(1.0, 0.5, 0.5), 0.25, 0.75)]
+    #[case(CubeFace::Up, Vec3A::new(-0.5, 1.0, -0.5), 0.25, 0.75)]
+    #[case(CubeFace::Down, Vec3A::new(-0.5, -1.0, 0.5), 0.25, 0.75)]
+    fn can_map_points_on_cube_faces_to_uv(
+        #[case] face: CubeFace,
+        #[case] point: Vec3A,
+        #[case] expected_u: f32,
+        #[case] expected_v: f32,
+    ) {
+        let (u, v) = cube_face_uv(face, point);
+        assert_abs_diff_eq!(u, expected_u, epsilon = f32::EPSILON);
+        assert_abs_diff_eq!(v, expected_v, epsilon = f32::EPSILON);
+    }
+
+    #[rstest]
+    #[case(Vec3A::new(5.0, 0.1, 0.2), Color::new_red())]
+    #[case(Vec3A::new(0.0, -3.0, 0.5), Color::new_green())]
+    #[case(Vec3A::new(0.1, 0.2, -0.7), Color::new_blue())]
+    fn cube_map_background_is_sampled_by_ray_direction(
+        cube_map: CubeMap,
+        #[case] direction: Vec3A,
+        #[case] expected_color: Color,
+    ) {
+        let background = Background::CubeMap(cube_map);
+        assert_eq!(background.color_for_direction(direction), expected_color);
+    }
+
+    #[rstest]
+    #[case(Vec3A::new(0.0, 0.0, -1.0), 0.0, 0.5)]
+    #[case(Vec3A::new(1.0, 0.0, 0.0), 0.25, 0.5)]
+    #[case(Vec3A::new(0.0, 0.0, 1.0), 0.5, 0.5)]
+    #[case(Vec3A::new(-1.0, 0.0, 0.0), 0.75, 0.5)]
+    #[case(Vec3A::new(0.0, 1.0, 0.0), 0.5, 1.0)]
+    #[case(Vec3A::new(0.0, -1.0, 0.0), 0.5, 0.0)]
+    fn can_map_directions_to_equirectangular_uv(
+        #[case] direction: Vec3A,
+        #[case] expected_u: f32,
+        #[case] expected_v: f32,
+    ) {
+        let (u, v) = direction_to_equirectangular_uv(direction);
+        // -z direction lands on the seam, where u = 0.0 and u = 1.0 are the same column
+        assert_abs_diff_eq!(u.rem_euclid(1.0), expected_u, epsilon = 1e-6);
+        assert_abs_diff_eq!(v, expected_v, epsilon = 1e-6);
+    }
+
+    #[rstest]
+    #[case(0.1, 0.2)]
+    #[case(0.5, 0.5)]
+    #[case(0.75, 0.9)]
+    fn equirectangular_uv_maps_back_to_the_same_direction(#[case] u: f32, #[case] v: f32) {
+        let direction = equirectangular_uv_to_direction(u, v);
+        assert_abs_diff_eq!(direction.length(), 1.0, epsilon = 1e-6);
+        let (mapped_u, mapped_v) = direction_to_equirectangular_uv(direction);
+        assert_abs_diff_eq!(mapped_u, u, epsilon = 1e-5);
+        assert_abs_diff_eq!(mapped_v, v, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn equirectangular_background_is_sampled_by_ray_direction() {
+        // Upper half of the panorama is white sky, lower half is black ground
+        let texture = ImageTexture::new(
+            2,
+            2,
+            vec![
+                Color::new_white(),
+                Color::new_white(),
+                Color::new_black(),
+                Color::new_black(),
+            ],
+        );
+        let background = Background::Equirectangular(texture);
+        assert_eq!(
+            background.color_for_direction(Vec3A::new(0.0, 1.0, 0.0)),
+            Color::new_white()
+        );
+        assert_eq!(
+            background.color_for_direction(Vec3A::new(0.0, -1.0, 0.0)),
+            Color::new_black()
+        );
+    }
+
+    #[test]
+    fn solid_color_background_is_the_same_in_every_direction() {
+        let background = Background::SolidColor(Color::new_blue());
+        assert_eq!(
+            background.color_for_direction(Vec3A::new(0.3, -0.2, 0.9)),
+            Color::new_blue()
+        );
+    }
+}
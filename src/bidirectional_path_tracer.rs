@@ -0,0 +1,891 @@
+use glam::Vec3A;
+
+use crate::{
+    bsdf::Bsdf, bump_map::TangentFrame, color::Color, emitter::Emitter, integrator::Integrator,
+    intersection::Computations, light::Light, path_tracer::closest_area_light, ray::Ray,
+    sampler::Sampler, sampling::MisHeuristic, world::World,
+};
+
+/// Bidirectional path tracer. Every camera ray is extended into a camera subpath, and a light subpath is traced
+/// from an emitter chosen by its power. Each vertex of one subpath is connected to each vertex of the other by
+/// a shadow ray, the paths of all the lengths built that way are weighted with multiple importance sampling.
+/// Light that reaches the visible surfaces only after a few diffuse bounces, like a room lit through a small
+/// opening, is found much more often than by the unidirectional `PathTracer`.
+///
+/// Materials, lights and units are the same as with the `PathTracer`, so the two converge to the same image.
+/// Light subpaths start on area lights, emissive objects, and point and spot lights with inverse square
+/// attenuation. Directional and environment lights, and point and spot lights without attenuation, are only
+/// sampled from the camera subpath. Light subpaths are not connected to the camera itself, the pixel of
+/// a camera ray is not known here. Participating media are ignored and their boundaries
+/// are transparent, media are only rendered by the `PathTracer`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct BidirectionalPathTracer {
+    /// Maximum number of bounces of a path
+    pub max_depth: u32,
+    /// Number of bounces after which subpaths are randomly terminated based on their throughput (Russian roulette)
+    pub russian_roulette_depth: u32,
+    /// Weighting of the paths of all the lengths of the two subpaths
+    pub heuristic: MisHeuristic,
+}
+
+impl Default for BidirectionalPathTracer {
+    fn default() -> Self {
+        Self {
+            max_depth: 16,
+            russian_roulette_depth: 3,
+            heuristic: MisHeuristic::Power,
+        }
+    }
+}
+
+impl BidirectionalPathTracer {
+    pub fn new(max_depth: u32, russian_roulette_depth: u32) -> Self {
+        Self {
+            max_depth,
+            russian_roulette_depth,
+            ..Default::default()
+        }
+    }
+
+    /// Starts a light subpath on an emitter chosen by its power, at the time of the camera subpath. Emitters that
+    /// do not start light subpaths leave it empty.
+    fn trace_light_subpath<'a>(
+        &self,
+        world: &'a World,
+        time: f32,
+        sampler: &mut dyn Sampler,
+        vertices: &mut Vec<Vertex<'a>>,
+    ) {
+        let u_emitter = sampler.next_1d();
+        let u_position = sampler.next_2d();
+        let u_direction = sampler.next_2d();
+        let (emitter, pmf) = match world.sample_emitter(u_emitter) {
+            Some(chosen) => chosen,
+            None => return,
+        };
+        if !starts_light_subpaths(emitter) {
+            return;
+        }
+        let (ray, power) = match emitter.sample_photon(u_position, u_direction) {
+            Some((ray, power)) => (Ray { time, ..ray }, power),
+            None => return,
+        };
+
+        let origin = ray.origin_point;
+        vertices.push(Vertex::new_emitter(
+            emitter,
+            origin,
+            emitter_normal(emitter, origin),
+            pmf * emitter.photon_position_pdf(origin),
+        ));
+        let start = SubpathRay {
+            ray,
+            beta: power / pmf,
+            pdf: emitter.photon_direction_pdf(origin, ray.direction_vector),
+        };
+        self.random_walk(world, start, sampler, vertices, self.max_depth as usize + 1);
+    }
+
+    /// Extends the subpath by following the ray until it has `max_vertices` vertices. Only camera subpaths start
+    /// with a camera vertex, they end on area lights. Returns the ray that left the world, if any.
+    fn random_walk<'a>(
+        &self,
+        world: &'a World,
+        start: SubpathRay,
+        sampler: &mut dyn Sampler,
+        vertices: &mut Vec<Vertex<'a>>,
+        max_vertices: usize,
+    ) -> Option<EscapedRay> {
+        let camera_subpath = matches!(vertices[0].kind, VertexKind::Camera);
+        let initial_throughput = start.beta.max_component();
+        let SubpathRay {
+            mut ray,
+            mut beta,
+            mut pdf,
+        } = start;
+
+        while vertices.len() < max_vertices {
+            let hit = world.hit_computations(ray);
+            let hit_distance = hit.as_ref().map_or(f32::INFINITY, |comps| comps.t);
+            let previous = vertices
+                .last()
+                .expect("subpaths should start with a vertex");
+            if camera_subpath {
+                // Area lights are not part of the world's geometry, camera subpaths end on them
+                if let Some((light, distance)) = closest_area_light(world, ray, hit_distance) {
+                    let emitter = Emitter::Light(light);
+                    let point = ray.position(distance);
+                    let mut vertex = Vertex {
+                        wo: -ray.direction_vector,
+                        beta,
+                        ..Vertex::new_emitter(emitter, point, emitter_normal(emitter, point), 0.0)
+                    };
+                    vertex.pdf_fwd = previous.convert_density(pdf, &vertex);
+                    vertices.push(vertex);
+                    return None;
+                }
+            }
+            let comps = match hit {
+                Some(comps) => comps,
+                None => {
+                    return camera_subpath.then_some(EscapedRay {
+                        ray,
+                        beta,
+                        bsdf_pdf: (pdf > 0.0).then_some(pdf),
+                    })
+                }
+            };
+
+            let object = world
+                .object_by_id(&comps.object_id)
+                .expect("should find the object that was hit in the world");
+            let material = object.material();
+            if material.is_medium_boundary() {
+                ray = ray.spawn(comps.under_point, ray.direction_vector);
+                continue;
+            }
+            let scattering = if material.bsdf.is_none() && sampler.next_1d() < material.reflective {
+                Scattering::Mirror
+            } else {
+                let bsdf = match &material.bsdf {
+                    Some(bsdf) if comps.inside => bsdf.seen_from_inside(),
+                    Some(bsdf) => *bsdf,
+                    None => Bsdf::Lambertian {
+                        albedo: material.color * material.diffuse,
+                    },
+                };
+                Scattering::Bsdf(bsdf, TangentFrame::from_normal(comps.normal_vector))
+            };
+            let emitter =
+                (material.emissive != Color::new_black()).then_some(Emitter::Object(object));
+            let mut vertex = Vertex::new_surface(&comps, scattering, emitter, beta);
+            vertex.pdf_fwd = previous.convert_density(pdf, &vertex);
+            vertices.push(vertex);
+            if vertices.len() == max_vertices {
+                break;
+            }
+
+            // Density of sampling the direction towards the previous vertex from the next one
+            let pdf_rev;
+            let origin;
+            let direction;
+            match scattering {
+                Scattering::Mirror => {
+                    // The whole throughput carries on
+                    direction = comps.reflect_vector;
+                    origin = comps.over_point;
+                    pdf = 0.0;
+                    pdf_rev = 0.0;
+                }
+                Scattering::Bsdf(bsdf, frame) => {
+                    let wo = frame.world_to_local(comps.eye_vector);
+                    let u_lobe = sampler.next_1d();
+                    let sample = match bsdf.sample(wo, u_lobe, sampler.next_2d()) {
+                        Some(sample) => sample,
+                        None => break,
+                    };
+                    direction = frame.local_to_world(sample.wi);
+                    // With shading normals the sampled direction may end up on the other side of the geometric surface
+                    let transmitted = sample.wi.z < 0.0;
+                    if (direction.dot(comps.geometric_normal_vector) < 0.0) != transmitted {
+                        break;
+                    }
+                    origin = if transmitted {
+                        comps.under_point
+                    } else {
+                        comps.over_point
+                    };
+                    if sample.is_specular {
+                        beta = beta * sample.f * sample.wi.z.abs();
+                        pdf = 0.0;
+                        pdf_rev = 0.0;
+                    } else {
+                        beta = beta * sample.f * (sample.wi.z.abs() / sample.pdf);
+                        pdf = sample.pdf;
+                        pdf_rev = bsdf.pdf(sample.wi, wo);
+                    }
+                }
+            }
+            let last = vertices.len() - 1;
+            vertices[last].delta = pdf == 0.0;
+            vertices[last - 1].pdf_rev =
+                vertices[last].convert_density(pdf_rev, &vertices[last - 1]);
+
+            let depth = (vertices.len() - 2) as u32;
+            if depth + 1 >= self.russian_roulette_depth {
+                let survival_probability = (beta.max_component() / initial_throughput).min(0.95);
+                if sampler.next_1d() >= survival_probability {
+                    break;
+                }
+                beta = beta / survival_probability;
+            }
+            ray = ray.spawn(origin, direction);
+        }
+        None
+    }
+
+    /// Radiance carried by the path of the first `s` vertices of the light subpath and the first `t` vertices
+    /// of the camera subpath. A single light vertex is sampled again on an emitter chosen for the camera vertex.
+    fn connect(
+        &self,
+        world: &World,
+        subpaths: &Subpaths,
+        s: usize,
+        t: usize,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let pt = &subpaths.camera[t - 1];
+        if s == 0 {
+            let emitted = pt.emitted();
+            if emitted == Color::new_black() {
+                return Color::new_black();
+            }
+            return pt.beta * emitted * self.mis_weight(world, subpaths, None, s, t);
+        }
+        if !pt.is_connectible() {
+            return Color::new_black();
+        }
+        if s == 1 {
+            return self.connect_to_emitter(world, subpaths, t, sampler);
+        }
+
+        let qs = &subpaths.light[s - 1];
+        if !qs.is_connectible() {
+            return Color::new_black();
+        }
+        let direction = (pt.point - qs.point).normalize();
+        let contribution = qs.beta * qs.f(direction) * pt.f(-direction) * pt.beta;
+        if contribution == Color::new_black() || !is_visible(world, qs, pt, subpaths.time, sampler)
+        {
+            return Color::new_black();
+        }
+        contribution * (geometry_term(qs, pt) * self.mis_weight(world, subpaths, None, s, t))
+    }
+
+    /// Next-event estimation from the last camera vertex, towards an emitter chosen by its power.
+    fn connect_to_emitter(
+        &self,
+        world: &World,
+        subpaths: &Subpaths,
+        t: usize,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let pt = &subpaths.camera[t - 1];
+        let u_emitter = sampler.next_1d();
+        let u = sampler.next_2d();
+        let (emitter, pmf) = match world.sample_emitter(u_emitter) {
+            Some(chosen) => chosen,
+            None => return Color::new_black(),
+        };
+        let sample = match emitter.sample_li(pt.over_point, u) {
+            Some(sample) => sample,
+            None => return Color::new_black(),
+        };
+        let f = pt.f(sample.direction);
+        if f == Color::new_black()
+            || world.transmittance(
+                Ray::new_at_time(
+                    pt.origin_towards(sample.direction),
+                    sample.direction,
+                    subpaths.time,
+                ),
+                sample.distance,
+                None,
+                sampler,
+            ) == Color::new_black()
+        {
+            return Color::new_black();
+        }
+
+        let pdf = sample.pdf * pmf;
+        let weight = if starts_light_subpaths(emitter) {
+            let point = pt.over_point + sample.direction * sample.distance;
+            let sampled = Vertex::new_emitter(
+                emitter,
+                point,
+                emitter_normal(emitter, point),
+                pmf * emitter.photon_position_pdf(point),
+            );
+            self.mis_weight(world, subpaths, Some(&sampled), 1, t)
+        } else if emitter.is_delta() {
+            1.0
+        } else {
+            // Environment light, which is otherwise only found by camera subpaths leaving the world
+            self.heuristic.weight(pdf, pt.bsdf_pdf(sample.direction))
+        };
+        pt.beta * f * sample.radiance * (pt.normal.dot(sample.direction).abs() * weight / pdf)
+    }
+
+    /// Weight of the path of `s` light and `t` camera vertices against the other ways of building it from
+    /// the two subpaths, found from the ratios of the densities of sampling its vertices from either end.
+    /// `sampled` is the light vertex sampled for a single light vertex, which replaces the light subpath.
+    fn mis_weight(
+        &self,
+        world: &World,
+        subpaths: &Subpaths,
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f32 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let camera = &subpaths.camera;
+        let light = &subpaths.light;
+        let pt = &camera[t - 1];
+        let pt_minus = &camera[t - 2];
+        let qs = match s {
+            0 => None,
+            1 => Some(sampled.expect("single light vertex should be sampled")),
+            _ => Some(&light[s - 1]),
+        };
+        let qs_minus = (s >= 2).then(|| &light[s - 2]);
+
+        // Densities of the vertices next to the connection, when sampled from the other end of the path
+        let (pt_rev, pt_minus_rev) = match qs {
+            Some(qs) => (qs.pdf(qs_minus, pt), pt.pdf(Some(qs), pt_minus)),
+            None => (pt.emission_origin_pdf(world), pt.emission_pdf(pt_minus)),
+        };
+        let qs_rev = qs.map_or(0.0, |qs| pt.pdf(Some(pt_minus), qs));
+        let qs_minus_rev = match (qs, qs_minus) {
+            (Some(qs), Some(qs_minus)) => qs.pdf(Some(pt), qs_minus),
+            _ => 0.0,
+        };
+
+        let ratio = |pdf_rev: f32, pdf_fwd: f32| {
+            let remap = |pdf: f32| if pdf == 0.0 { 1.0 } else { pdf };
+            let ratio = remap(pdf_rev) / remap(pdf_fwd);
+            match self.heuristic {
+                MisHeuristic::Balance => ratio,
+                MisHeuristic::Power => ratio * ratio,
+            }
+        };
+        let mut sum = 0.0;
+
+        // Shorter camera subpaths, down to two vertices as nothing can hit the camera
+        let camera_delta = |i: usize| i != t - 1 && camera[i].delta;
+        let mut r = 1.0;
+        for i in (2..t).rev() {
+            let pdf_rev = if i == t - 1 {
+                pt_rev
+            } else if i == t - 2 {
+                pt_minus_rev
+            } else {
+                camera[i].pdf_rev
+            };
+            r *= ratio(pdf_rev, camera[i].pdf_fwd);
+            if !camera_delta(i) && !camera_delta(i - 1) {
+                sum += r;
+            }
+        }
+
+        // Shorter light subpaths
+        let light_vertex = |i: usize| if i == s - 1 { qs.unwrap() } else { &light[i] };
+        let light_delta = |i: usize| i != s - 1 && light[i].delta;
+        let mut r = 1.0;
+        for i in (0..s).rev() {
+            let pdf_rev = if i == s - 1 {
+                qs_rev
+            } else if i == s - 2 {
+                qs_minus_rev
+            } else {
+                light[i].pdf_rev
+            };
+            r *= ratio(pdf_rev, light_vertex(i).pdf_fwd);
+            let delta_before = if i > 0 {
+                light_delta(i - 1)
+            } else {
+                light_vertex(0)
+                    .emitter()
+                    .is_some_and(|emitter| emitter.is_delta())
+            };
+            if !light_delta(i) && !delta_before {
+                sum += r;
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn radiance(&self, world: &World, camera_ray: Ray, sampler: &mut dyn Sampler) -> Color {
+        let mut camera = vec![Vertex::new_camera(camera_ray)];
+        let start = SubpathRay {
+            ray: camera_ray,
+            beta: Color::new_white(),
+            pdf: 0.0,
+        };
+        let escaped = self.random_walk(
+            world,
+            start,
+            sampler,
+            &mut camera,
+            self.max_depth as usize + 2,
+        );
+        let mut light = vec![];
+        self.trace_light_subpath(world, camera_ray.time, sampler, &mut light);
+        let subpaths = Subpaths {
+            camera,
+            light,
+            time: camera_ray.time,
+        };
+
+        let mut radiance = Color::new_black();
+        if let Some(escaped) = escaped {
+            // The background is only sampled directly when it is the environment light
+            let weight = match (world.environment_light(), escaped.bsdf_pdf) {
+                (Some(light), Some(bsdf_pdf)) => self.heuristic.weight(
+                    bsdf_pdf,
+                    world.emitter_pmf(Emitter::Light(light))
+                        * light.pdf_li(escaped.ray.origin_point, escaped.ray.direction_vector),
+                ),
+                _ => 1.0,
+            };
+            radiance += escaped.beta
+                * world
+                    .background()
+                    .color_for_direction(escaped.ray.direction_vector)
+                * weight;
+        }
+        for t in 2..=subpaths.camera.len() {
+            // A single light vertex is sampled for the camera vertex, even without a light subpath
+            for s in 0..=subpaths.light.len().max(1) {
+                if s + t - 2 > self.max_depth as usize {
+                    break;
+                }
+                radiance += self.connect(world, &subpaths, s, t, sampler);
+            }
+        }
+        radiance
+    }
+}
+
+/// Ray that extends a subpath. `beta` is its throughput and `pdf` the density of sampling its direction, with
+/// respect to solid angle, 0.0 if it could not have been sampled by a density.
+#[derive(Debug, Copy, Clone)]
+struct SubpathRay {
+    ray: Ray,
+    beta: Color,
+    pdf: f32,
+}
+
+/// Camera and light subpaths traced for a camera ray, whose vertices are connected to each other.
+struct Subpaths<'a> {
+    camera: Vec<Vertex<'a>>,
+    light: Vec<Vertex<'a>>,
+    /// Time of the camera ray, shadow rays of the connections travel at it
+    time: f32,
+}
+
+/// Ray of a camera subpath that did not hit anything, with its throughput and the density of sampling it from
+/// a BSDF, `None` for camera rays and perfect mirror bounces.
+struct EscapedRay {
+    ray: Ray,
+    beta: Color,
+    bsdf_pdf: Option<f32>,
+}
+
+/// How a surface vertex scatters light.
+#[derive(Debug, Copy, Clone)]
+enum Scattering {
+    /// Perfect mirror bounce of a material without a BSDF
+    Mirror,
+    Bsdf(Bsdf, TangentFrame),
+}
+
+#[derive(Debug, Copy, Clone)]
+enum VertexKind<'a> {
+    Camera,
+    /// Start of a light subpath, or an area light at the end of a camera subpath
+    Emitter(Emitter<'a>),
+    Surface {
+        scattering: Scattering,
+        /// Set for emissive objects
+        emitter: Option<Emitter<'a>>,
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Vertex<'a> {
+    kind: VertexKind<'a>,
+    point: Vec3A,
+    /// Shading normal, zero for the camera and for point and spot lights
+    normal: Vec3A,
+    geometric_normal: Vec3A,
+    over_point: Vec3A,
+    under_point: Vec3A,
+    /// Direction towards the previous vertex of the subpath
+    wo: Vec3A,
+    /// Throughput of the subpath up to the vertex
+    beta: Color,
+    /// Scattered by a perfect mirror or glass, such vertices cannot be connected
+    delta: bool,
+    /// Density of sampling the vertex from the previous vertex of its subpath, with respect to area
+    pdf_fwd: f32,
+    /// Density of sampling the vertex from the next vertex, as if the path was traced from the other end
+    pdf_rev: f32,
+}
+
+impl<'a> Vertex<'a> {
+    fn new_camera(ray: Ray) -> Self {
+        Self {
+            kind: VertexKind::Camera,
+            point: ray.origin_point,
+            normal: Vec3A::ZERO,
+            geometric_normal: Vec3A::ZERO,
+            over_point: ray.origin_point,
+            under_point: ray.origin_point,
+            wo: Vec3A::ZERO,
+            beta: Color::new_white(),
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn new_emitter(emitter: Emitter<'a>, point: Vec3A, normal: Vec3A, pdf_fwd: f32) -> Self {
+        Self {
+            kind: VertexKind::Emitter(emitter),
+            normal,
+            geometric_normal: normal,
+            pdf_fwd,
+            ..Self::new_camera(Ray::new(point, Vec3A::ZERO))
+        }
+    }
+
+    fn new_surface(
+        comps: &Computations,
+        scattering: Scattering,
+        emitter: Option<Emitter<'a>>,
+        beta: Color,
+    ) -> Self {
+        Self {
+            kind: VertexKind::Surface {
+                scattering,
+                emitter,
+            },
+            point: comps.point,
+            normal: comps.normal_vector,
+            geometric_normal: comps.geometric_normal_vector,
+            over_point: comps.over_point,
+            under_point: comps.under_point,
+            wo: comps.eye_vector,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn emitter(&self) -> Option<Emitter<'a>> {
+        match self.kind {
+            VertexKind::Camera => None,
+            VertexKind::Emitter(emitter) => Some(emitter),
+            VertexKind::Surface { emitter, .. } => emitter,
+        }
+    }
+
+    /// Surfaces with a BSDF, the other vertices cannot scatter light arriving from another direction.
+    fn is_connectible(&self) -> bool {
+        matches!(
+            self.kind,
+            VertexKind::Surface {
+                scattering: Scattering::Bsdf(..),
+                ..
+            }
+        )
+    }
+
+    /// Radiance emitted from the vertex, emitters are two-sided.
+    fn emitted(&self) -> Color {
+        match self.emitter() {
+            Some(Emitter::Light(light)) => light.intensity(),
+            Some(Emitter::Object(object)) => object.material().emissive,
+            None => Color::new_black(),
+        }
+    }
+
+    /// Point where rays in the direction start, on the side of the surface they leave from.
+    fn origin_towards(&self, direction: Vec3A) -> Vec3A {
+        if direction.dot(self.geometric_normal) < 0.0 {
+            self.under_point
+        } else {
+            self.over_point
+        }
+    }
+
+    /// BSDF value for light scattered between the direction and the previous vertex.
+    fn f(&self, direction: Vec3A) -> Color {
+        match self.kind {
+            VertexKind::Surface {
+                scattering: Scattering::Bsdf(bsdf, frame),
+                ..
+            } => bsdf.f(
+                frame.world_to_local(self.wo),
+                frame.world_to_local(direction),
+            ),
+            _ => Color::new_black(),
+        }
+    }
+
+    /// Density of the BSDF sampling the direction, with respect to solid angle.
+    fn bsdf_pdf(&self, direction: Vec3A) -> f32 {
+        match self.kind {
+            VertexKind::Surface {
+                scattering: Scattering::Bsdf(bsdf, frame),
+                ..
+            } => bsdf.pdf(
+                frame.world_to_local(self.wo),
+                frame.world_to_local(direction),
+            ),
+            _ => 0.0,
+        }
+    }
+
+    /// Converts the density of sampling the direction towards the next vertex from solid angle to area.
+    fn convert_density(&self, pdf: f32, next: &Vertex) -> f32 {
+        let to_next = next.point - self.point;
+        let distance_squared = to_next.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let cos_theta = if next.normal == Vec3A::ZERO {
+            1.0
+        } else {
+            next.normal.dot(to_next).abs() / distance_squared.sqrt()
+        };
+        pdf * cos_theta / distance_squared
+    }
+
+    /// Density of sampling the next vertex from this one with respect to area, when the path arrived from
+    /// the previous one. Emitters sample the next vertex as a photon.
+    fn pdf(&self, previous: Option<&Vertex>, next: &Vertex) -> f32 {
+        let direction = (next.point - self.point).normalize();
+        let pdf = match self.kind {
+            VertexKind::Emitter(emitter) => emitter.photon_direction_pdf(self.point, direction),
+            VertexKind::Surface {
+                scattering: Scattering::Bsdf(bsdf, frame),
+                ..
+            } => {
+                let previous = previous.expect("surface vertices should have a previous vertex");
+                bsdf.pdf(
+                    frame.world_to_local((previous.point - self.point).normalize()),
+                    frame.world_to_local(direction),
+                )
+            }
+            _ => 0.0,
+        };
+        self.convert_density(pdf, next)
+    }
+
+    /// Density of a light subpath starting at the vertex, with respect to area.
+    fn emission_origin_pdf(&self, world: &World) -> f32 {
+        self.emitter().map_or(0.0, |emitter| {
+            world.emitter_pmf(emitter) * emitter.photon_position_pdf(self.point)
+        })
+    }
+
+    /// Density of a light subpath starting at the vertex sampling the next vertex, with respect to area.
+    fn emission_pdf(&self, next: &Vertex) -> f32 {
+        self.emitter().map_or(0.0, |emitter| {
+            let direction = (next.point - self.point).normalize();
+            self.convert_density(emitter.photon_direction_pdf(self.point, direction), next)
+        })
+    }
+}
+
+/// Lights of the whole world and lights that are not attenuated with distance have no finite power to sample
+/// photons by, light subpaths do not start on them.
+fn starts_light_subpaths(emitter: Emitter) -> bool {
+    match emitter {
+        Emitter::Light(Light::Point(light)) => light.inverse_square_attenuation(),
+        Emitter::Light(Light::Spot(light)) => light.inverse_square_attenuation(),
+        Emitter::Light(Light::Area(_)) | Emitter::Object(_) => true,
+        Emitter::Light(_) => false,
+    }
+}
+
+/// Normal of the emitter's surface at the point, zero for point and spot lights.
+fn emitter_normal(emitter: Emitter, point: Vec3A) -> Vec3A {
+    match emitter {
+        Emitter::Light(Light::Area(light)) => light.normal(),
+        Emitter::Light(_) => Vec3A::ZERO,
+        Emitter::Object(object) => object.normal_at(point),
+    }
+}
+
+fn is_visible(world: &World, a: &Vertex, b: &Vertex, time: f32, sampler: &mut dyn Sampler) -> bool {
+    let direction = (b.point - a.point).normalize();
+    let from = a.origin_towards(direction);
+    let to_b = b.origin_towards(-direction) - from;
+    let distance = to_b.length();
+    let shadow_ray = Ray::new_at_time(from, to_b / distance, time);
+    world.transmittance(shadow_ray, distance, None, sampler) != Color::new_black()
+}
+
+/// Cosines at the two vertices over their squared distance, which converts densities from area to solid angle.
+fn geometry_term(a: &Vertex, b: &Vertex) -> f32 {
+    let to_b = b.point - a.point;
+    let distance_squared = to_b.length_squared();
+    let direction = to_b / distance_squared.sqrt();
+    a.normal.dot(direction).abs() * b.normal.dot(direction).abs() / distance_squared
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_PI;
+
+    use approx::assert_abs_diff_eq;
+    use glam::{Affine3A, Vec3};
+    use rstest::*;
+
+    use crate::{
+        background::Background,
+        light::{AreaLight, EnvironmentLight, PointLight},
+        material::Material,
+        path_tracer::PathTracer,
+        quad::Quad,
+        sphere::Sphere,
+        test_helpers::{average_radiance, floor_world, lambertian},
+    };
+
+    use super::*;
+
+    /// Ray looking at the origin from above and in front of it.
+    fn ray_towards_origin() -> Ray {
+        Ray::new(
+            Vec3A::new(0.0, 0.5, -0.5),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        )
+    }
+
+    #[rstest]
+    #[case::balance(MisHeuristic::Balance)]
+    #[case::power(MisHeuristic::Power)]
+    fn furnace_test_converges_to_the_geometric_series(#[case] heuristic: MisHeuristic) {
+        // Inside a closed sphere of uniform emission and albedo, radiance is emission / (1 - albedo)
+        let mut world = World::new_empty();
+        let mut enclosure = Sphere::new(Vec3A::ZERO);
+        enclosure.set_transform(Affine3A::from_scale(Vec3::splat(10.0)));
+        enclosure.set_material(Material {
+            emissive: Color::new_color(0.5, 0.5, 0.5),
+            ..lambertian(0.5)
+        });
+        world.add_object(enclosure);
+
+        let integrator = BidirectionalPathTracer {
+            heuristic,
+            ..BidirectionalPathTracer::new(32, 3)
+        };
+        let ray = Ray::new(Vec3A::ZERO, Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&integrator, &world, ray, 1000);
+        assert_abs_diff_eq!(radiance.get_red_val(), 1.0, epsilon = 0.05);
+    }
+
+    #[rstest]
+    #[case::attenuated(true, 0.25)]
+    #[case::not_attenuated(false, 1.0)]
+    fn point_lights_light_the_floor(
+        #[from(floor_world)] mut world: World,
+        #[case] attenuated: bool,
+        #[case] irradiance: f32,
+    ) {
+        let mut light = PointLight::new(Vec3A::new(0.0, 2.0, 0.0), Color::new_white());
+        light.set_inverse_square_attenuation(attenuated);
+        world.add_light(light);
+
+        // Only the connection to the light can find the direct light
+        let integrator = BidirectionalPathTracer::new(1, 2);
+        let radiance = average_radiance(&integrator, &world, ray_towards_origin(), 1);
+        assert_abs_diff_eq!(
+            radiance.get_red_val(),
+            0.5 * FRAC_1_PI * irradiance,
+            epsilon = 1e-5
+        );
+    }
+
+    #[rstest]
+    fn environment_light_lights_the_floor(#[from(floor_world)] mut world: World) {
+        world.add_light(EnvironmentLight::new(Background::SolidColor(
+            Color::new_white(),
+        )));
+
+        // The floor sees the white sky over the whole upper hemisphere
+        let integrator = BidirectionalPathTracer::new(1, 2);
+        let radiance = average_radiance(&integrator, &world, ray_towards_origin(), 1000);
+        assert_abs_diff_eq!(radiance.get_red_val(), 0.5, epsilon = 0.02);
+    }
+
+    #[rstest]
+    #[case::small_light(0.1)]
+    #[case::large_light(5.0)]
+    fn direct_light_from_area_lights_is_unbiased(
+        #[from(floor_world)] mut world: World,
+        #[case] half_size: f32,
+    ) {
+        world.add_light(AreaLight::new(
+            Vec3A::new(-half_size, 1.0, -half_size),
+            Vec3A::new(2.0 * half_size, 0.0, 0.0),
+            1,
+            Vec3A::new(0.0, 0.0, 2.0 * half_size),
+            1,
+            Color::new_white(),
+        ));
+
+        let integrator = BidirectionalPathTracer::new(1, 2);
+        let radiance = average_radiance(&integrator, &world, ray_towards_origin(), 2000);
+        // Irradiance of a square light of unit radiance, centered 1 unit above the origin
+        let x = half_size / (1.0 + half_size * half_size).sqrt();
+        let expected = 0.5 * FRAC_1_PI * 4.0 * x * x.atan();
+        assert_abs_diff_eq!(radiance.get_red_val(), expected, epsilon = expected * 0.05);
+    }
+
+    #[rstest]
+    fn light_through_a_small_opening_matches_the_path_tracer(
+        #[from(floor_world)]
+        #[with(lambertian(0.8))]
+        mut world: World,
+    ) {
+        // Floor in the shadow of a ceiling, lit by a small panel above the ceiling through its bounces off a wall
+        let mut ceiling = Quad::new(
+            Vec3A::new(-20.0, 2.0, -20.0),
+            Vec3A::new(40.0, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, 21.0),
+        );
+        ceiling.set_material(lambertian(0.8));
+        world.add_object(ceiling);
+        let mut wall = Quad::new(
+            Vec3A::new(-20.0, 0.0, 2.0),
+            Vec3A::new(40.0, 0.0, 0.0),
+            Vec3A::new(0.0, 4.0, 0.0),
+        );
+        wall.set_material(lambertian(0.8));
+        world.add_object(wall);
+        let mut panel = Quad::new(
+            Vec3A::new(-0.25, 3.0, 1.25),
+            Vec3A::new(0.5, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, 0.5),
+        );
+        panel.set_material(Material {
+            color: Color::new_black(),
+            emissive: Color::new_color(20.0, 20.0, 20.0),
+            ..Default::default()
+        });
+        world.add_object(panel);
+
+        let ray = Ray::new(
+            Vec3A::new(0.0, 1.0, -2.0),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let bidirectional =
+            average_radiance(&BidirectionalPathTracer::new(4, 8), &world, ray, 4000);
+        let unidirectional = average_radiance(&PathTracer::new(4, 8), &world, ray, 16000);
+        assert!(bidirectional.get_red_val() > 0.0);
+        assert_abs_diff_eq!(
+            bidirectional.get_red_val(),
+            unidirectional.get_red_val(),
+            epsilon = unidirectional.get_red_val() * 0.05
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use glam::{Vec2, Vec3A};
+
+use std::f32::consts::FRAC_1_PI;
+
+use crate::{
+    color::Color,
+    microfacet::MicrofacetBsdf,
+    principled::Principled,
+    sampling::{cosine_hemisphere_pdf, cosine_sample_hemisphere},
+};
+
+/// Roughness up to which mirrors and glass are treated as specular by caustic photon mapping.
+pub const NEAR_SPECULAR_ROUGHNESS: f32 = 0.1;
+
+/// Direction sampled from a BSDF with the BSDF value and the probability density of choosing it.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct BsdfSample {
+    pub wi: Vec3A,
+    pub f: Color,
+    /// Probability density with respect to solid angle
+    pub pdf: f32,
+    /// Sampled from a delta distribution (perfect mirror or glass), `pdf` is then not a density
+    pub is_specular: bool,
+}
+
+/// Physically based scattering model of a surface. All directions are in the local shading frame, where
+/// the normal is +z, and point away from the surface: `wo` towards the viewer and `wi` towards the light.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Bsdf {
+    /// Ideal diffuse reflection, the same radiance is reflected in all directions
+    Lambertian {
+        albedo: Color,
+    },
+    Microfacet(MicrofacetBsdf),
+    Principled(Principled),
+}
+
+impl Bsdf {
+    /// Same BSDF for a viewer inside of the object, where the media on the two sides of the surface swap.
+    pub fn seen_from_inside(&self) -> Self {
+        match self {
+            Bsdf::Lambertian { .. } => *self,
+            Bsdf::Microfacet(bsdf) => Bsdf::Microfacet(MicrofacetBsdf {
+                ior: 1.0 / bsdf.ior,
+                ..*bsdf
+            }),
+            Bsdf::Principled(bsdf) => Bsdf::Principled(bsdf.seen_from_inside()),
+        }
+    }
+
+    /// Smooth mirrors and glass without a diffuse lobe, which focus light into caustics.
+    pub fn is_near_specular(&self) -> bool {
+        match self {
+            Bsdf::Lambertian { .. } => false,
+            Bsdf::Microfacet(bsdf) => {
+                bsdf.roughness <= NEAR_SPECULAR_ROUGHNESS
+                    && (bsdf.metalness >= 1.0 || bsdf.conductor.is_some())
+            }
+            Bsdf::Principled(bsdf) => {
+                bsdf.roughness <= NEAR_SPECULAR_ROUGHNESS
+                    && (bsdf.metallic >= 1.0 || bsdf.transmission >= 1.0)
+            }
+        }
+    }
+
+    /// Fraction of radiance arriving from `wi` that is scattered towards `wo`.
+    pub fn f(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        match self {
+            Bsdf::Lambertian { albedo } if wi.z > 0.0 => *albedo * FRAC_1_PI,
+            Bsdf::Lambertian { .. } => Color::new_black(),
+            Bsdf::Microfacet(bsdf) => bsdf.f(wo, wi),
+            Bsdf::Principled(bsdf) => bsdf.f(wo, wi),
+        }
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        match self {
+            Bsdf::Lambertian { .. } => cosine_hemisphere_pdf(wi.z),
+            Bsdf::Microfacet(bsdf) => bsdf.pdf(wo, wi),
+            Bsdf::Principled(bsdf) => bsdf.pdf(wo, wi),
+        }
+    }
+
+    /// Samples an incoming direction, `u_lobe` chooses between lobes and `u` samples the chosen lobe.
+    pub fn sample(&self, wo: Vec3A, u_lobe: f32, u: Vec2) -> Option<BsdfSample> {
+        match self {
+            Bsdf::Lambertian { albedo } => {
+                let wi = cosine_sample_hemisphere(u);
+                (wi.z > 0.0).then(|| BsdfSample {
+                    wi,
+                    f: *albedo * FRAC_1_PI,
+                    pdf: cosine_hemisphere_pdf(wi.z),
+                    is_specular: false,
+                })
+            }
+            Bsdf::Microfacet(bsdf) => bsdf.sample(wo, u_lobe, u),
+            Bsdf::Principled(bsdf) => bsdf.sample(wo, u_lobe, u),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn lambertian_reflection_is_the_same_in_all_directions() {
+        let bsdf = Bsdf::Lambertian {
+            albedo: Color::new_color(0.5, 0.5, 0.5),
+        };
+        let wo = Vec3A::new(0.6, 0.0, 0.8);
+        for wi in [
+            Vec3A::Z,
+            Vec3A::new(0.0, 0.8, 0.6),
+            Vec3A::new(-0.6, 0.0, 0.8),
+        ] {
+            assert_abs_diff_eq!(bsdf.f(wo, wi).get_red_val(), 0.5 * FRAC_1_PI);
+        }
+        assert_eq!(bsdf.f(wo, -Vec3A::Z), Color::new_black());
+
+        let sample = bsdf
+            .sample(wo, 0.5, Vec2::new(0.3, 0.6))
+            .expect("should sample the upper hemisphere");
+        assert_abs_diff_eq!(sample.pdf, bsdf.pdf(wo, sample.wi));
+        // Cosine-weighted sampling cancels out the cosine and 1/π
+        assert_abs_diff_eq!(
+            sample.f.get_red_val() * sample.wi.z / sample.pdf,
+            0.5,
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn only_smooth_mirrors_and_glass_are_near_specular() {
+        let glass = Principled {
+            transmission: 1.0,
+            roughness: 0.0,
+            ..Default::default()
+        };
+        assert!(Bsdf::Principled(glass).is_near_specular());
+        assert!(!Bsdf::Principled(Principled {
+            roughness: 0.5,
+            ..glass
+        })
+        .is_near_specular());
+        assert!(
+            Bsdf::Microfacet(MicrofacetBsdf::new(Color::new_white(), 0.05, 1.0)).is_near_specular()
+        );
+        // Smooth plastic still has a diffuse base
+        assert!(
+            !Bsdf::Microfacet(MicrofacetBsdf::new(Color::new_white(), 0.05, 0.0))
+                .is_near_specular()
+        );
+        assert!(!Bsdf::Lambertian {
+            albedo: Color::new_white()
+        }
+        .is_near_specular());
+    }
+}
@@ -0,0 +1,333 @@
+use std::{fmt, sync::Arc};
+
+use glam::{Affine3A, Vec2, Vec3A};
+use image::ImageError;
+
+use crate::texture::ImageTexture;
+
+/// Step used when finite-differencing height patterns, in object space units.
+const HEIGHT_DIFFERENCE_STEP: f32 = 0.001;
+
+/// Orthonormal shading frame at a point. The tangent follows increasing `u` texture coordinate,
+/// the bitangent follows increasing `v` texture coordinate.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct TangentFrame {
+    pub tangent: Vec3A,
+    pub bitangent: Vec3A,
+    pub normal: Vec3A,
+}
+
+impl TangentFrame {
+    /// Builds orthonormal frame around the normal. The tangent is made perpendicular to the normal,
+    /// the bitangent hint is only used to keep the handedness of the frame.
+    pub fn new(tangent: Vec3A, bitangent_hint: Vec3A, normal: Vec3A) -> Self {
+        let normal = normal.normalize();
+        let tangent = tangent - normal * normal.dot(tangent);
+        let tangent = if tangent.length_squared() > f32::EPSILON {
+            tangent.normalize()
+        } else {
+            normal.any_orthonormal_vector()
+        };
+        let bitangent = tangent.cross(normal);
+        let bitangent = if bitangent.dot(bitangent_hint) < 0.0 {
+            -bitangent
+        } else {
+            bitangent
+        };
+
+        Self {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    /// Frame for surfaces without texture coordinates, the tangent direction is arbitrary.
+    pub fn from_normal(normal: Vec3A) -> Self {
+        let (tangent, bitangent) = normal.normalize().any_orthonormal_pair();
+        Self::new(tangent, bitangent, normal)
+    }
+
+    /// Frame for a triangle, derived from the vertex positions and their texture coordinates.
+    pub fn from_triangle(positions: [Vec3A; 3], uvs: [Vec2; 3], normal: Vec3A) -> Self {
+        let edge1 = positions[1] - positions[0];
+        let edge2 = positions[2] - positions[0];
+        let delta_uv1 = uvs[1] - uvs[0];
+        let delta_uv2 = uvs[2] - uvs[0];
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if determinant.abs() < f32::EPSILON {
+            // Degenerate texture mapping, there is no meaningful tangent direction
+            return Self::from_normal(normal);
+        }
+
+        let r = 1.0 / determinant;
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r;
+        Self::new(tangent, bitangent, normal)
+    }
+
+    /// Transforms object space frame into world space.
+    pub fn transform(&self, transform: &Affine3A) -> Self {
+        let normal_matrix = transform.matrix3.inverse().transpose();
+        Self::new(
+            transform.transform_vector3a(self.tangent),
+            transform.transform_vector3a(self.bitangent),
+            normal_matrix * self.normal,
+        )
+    }
+
+    /// Converts vector from the frame's coordinates (tangent, bitangent, normal) to the frame's space.
+    pub fn local_to_world(&self, v: Vec3A) -> Vec3A {
+        self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+    }
+
+    /// Converts vector from the frame's space to the frame's coordinates (tangent, bitangent, normal).
+    pub fn world_to_local(&self, v: Vec3A) -> Vec3A {
+        Vec3A::new(
+            v.dot(self.tangent),
+            v.dot(self.bitangent),
+            v.dot(self.normal),
+        )
+    }
+
+    fn with_normal(&self, normal: Vec3A) -> Self {
+        Self::new(self.tangent, self.bitangent, normal)
+    }
+}
+
+/// Scalar height field evaluated at object space points.
+#[derive(Clone)]
+pub struct HeightPattern(Arc<dyn Fn(Vec3A) -> f32 + Send + Sync>);
+
+impl HeightPattern {
+    pub fn new(height_fn: impl Fn(Vec3A) -> f32 + Send + Sync + 'static) -> Self {
+        Self(Arc::new(height_fn))
+    }
+
+    pub fn height_at(&self, local_point: Vec3A) -> f32 {
+        (self.0)(local_point)
+    }
+}
+
+impl fmt::Debug for HeightPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HeightPattern")
+    }
+}
+
+impl PartialEq for HeightPattern {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Perturbs the shading normal to add surface detail without additional geometry.
+#[derive(Debug, PartialEq, Clone)]
+pub enum BumpMap {
+    /// Tangent space normal map, RGB values are mapped from 0.0 - 1.0 to -1.0 - 1.0 (green points up, +v).
+    /// Strength scales the tangent space deviation, 1.0 uses the map as it is.
+    NormalMap {
+        texture: Arc<ImageTexture>,
+        strength: f32,
+    },
+    /// Height pattern, the normal is tilted against the height gradient along the surface.
+    HeightPattern { pattern: HeightPattern, scale: f32 },
+}
+
+impl BumpMap {
+    pub fn new_normal_map_from_file(
+        image_file_path: &str,
+        strength: f32,
+    ) -> Result<Self, ImageError> {
+        Ok(Self::NormalMap {
+            texture: Arc::new(ImageTexture::load_from_file(image_file_path)?),
+            strength,
+        })
+    }
+
+    /// Perturbs object space frame at object space point, with texture coordinates of that point.
+    pub fn perturb(&self, frame: &TangentFrame, local_point: Vec3A, uv: Vec2) -> TangentFrame {
+        match self {
+            Self::NormalMap { texture, strength } => {
+                let texel = texture.sample_bilinear(uv.x, uv.y);
+                let tangent_space_normal = Vec3A::new(
+                    (texel.get_red_val() * 2.0 - 1.0) * strength,
+                    (texel.get_green_val() * 2.0 - 1.0) * strength,
+                    texel.get_blue_val() * 2.0 - 1.0,
+                );
+                if tangent_space_normal.z <= 0.0 {
+                    // Invalid texel, the normal would point into the surface
+                    return *frame;
+                }
+                frame.with_normal(frame.local_to_world(tangent_space_normal))
+            }
+            Self::HeightPattern { pattern, scale } => {
+                let height = pattern.height_at(local_point);
+                let height_u =
+                    pattern.height_at(local_point + frame.tangent * HEIGHT_DIFFERENCE_STEP);
+                let height_v =
+                    pattern.height_at(local_point + frame.bitangent * HEIGHT_DIFFERENCE_STEP);
+                let gradient_u = (height_u - height) / HEIGHT_DIFFERENCE_STEP;
+                let gradient_v = (height_v - height) / HEIGHT_DIFFERENCE_STEP;
+                let normal = frame.normal
+                    - (frame.tangent * gradient_u + frame.bitangent * gradient_v) * *scale;
+                frame.with_normal(normal)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+    use rstest::*;
+
+    use crate::color::Color;
+
+    use super::*;
+
+    #[fixture]
+    pub fn frame() -> TangentFrame {
+        TangentFrame::new(
+            Vec3A::new(1.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+            Vec3A::new(0.0, 0.0, -1.0),
+        )
+    }
+
+    #[test]
+    fn tangent_frame_is_orthonormal() {
+        let frame = TangentFrame::new(
+            Vec3A::new(2.0, 0.0, 1.0),
+            Vec3A::new(0.0, 3.0, 0.0),
+            Vec3A::new(0.0, 0.0, -2.0),
+        );
+        assert!(frame.tangent.abs_diff_eq(Vec3A::new(1.0, 0.0, 0.0), 1e-6));
+        assert!(frame.bitangent.abs_diff_eq(Vec3A::new(0.0, 1.0, 0.0), 1e-6));
+        assert!(frame.normal.abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+    }
+
+    #[test]
+    fn frame_from_normal_only_is_orthonormal() {
+        let normal = Vec3A::new(1.0, 2.0, 3.0).normalize();
+        let frame = TangentFrame::from_normal(normal);
+        assert!(frame.normal.abs_diff_eq(normal, 1e-6));
+        assert!(frame.tangent.dot(normal).abs() < 1e-6);
+        assert!(frame.bitangent.dot(normal).abs() < 1e-6);
+        assert!(frame.tangent.dot(frame.bitangent).abs() < 1e-6);
+    }
+
+    #[test]
+    fn frame_from_triangle_follows_texture_coordinates() {
+        // Texture is mapped upside down and mirrored
+        let frame = TangentFrame::from_triangle(
+            [
+                Vec3A::new(0.0, 0.0, 0.0),
+                Vec3A::new(0.0, 2.0, 0.0),
+                Vec3A::new(2.0, 0.0, 0.0),
+            ],
+            [
+                Vec2::new(1.0, 1.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(0.0, 1.0),
+            ],
+            Vec3A::new(0.0, 0.0, -1.0),
+        );
+        assert!(frame.tangent.abs_diff_eq(Vec3A::new(-1.0, 0.0, 0.0), 1e-6));
+        assert!(frame
+            .bitangent
+            .abs_diff_eq(Vec3A::new(0.0, -1.0, 0.0), 1e-6));
+    }
+
+    #[rstest]
+    fn can_convert_vectors_to_and_from_frame_coordinates(frame: TangentFrame) {
+        let v = Vec3A::new(0.3, -0.4, 0.5);
+        assert!(frame
+            .local_to_world(frame.world_to_local(v))
+            .abs_diff_eq(v, 1e-6));
+        assert!(frame
+            .local_to_world(Vec3A::new(0.0, 0.0, 1.0))
+            .abs_diff_eq(frame.normal, 1e-6));
+    }
+
+    #[rstest]
+    fn can_transform_frame(frame: TangentFrame) {
+        let transformed = frame.transform(&Affine3A::from_scale(Vec3::new(1.0, 4.0, 2.0)));
+        assert!(transformed.tangent.abs_diff_eq(frame.tangent, 1e-6));
+        assert!(transformed.bitangent.abs_diff_eq(frame.bitangent, 1e-6));
+        assert!(transformed.normal.abs_diff_eq(frame.normal, 1e-6));
+    }
+
+    #[rstest]
+    fn flat_normal_map_keeps_the_normal(frame: TangentFrame) {
+        let bump_map = BumpMap::NormalMap {
+            texture: Arc::new(ImageTexture::new(
+                1,
+                1,
+                vec![Color::new_color(0.5, 0.5, 1.0)],
+            )),
+            strength: 1.0,
+        };
+        let perturbed = bump_map.perturb(&frame, Vec3A::ZERO, Vec2::ZERO);
+        assert!(perturbed.normal.abs_diff_eq(frame.normal, 1e-6));
+    }
+
+    #[rstest]
+    fn normal_map_tilts_the_normal_in_tangent_space(frame: TangentFrame) {
+        // Tangent space normal (1, 0, 1) is tilted 45 degrees towards the tangent
+        let bump_map = BumpMap::NormalMap {
+            texture: Arc::new(ImageTexture::new(
+                1,
+                1,
+                vec![Color::new_color(1.0, 0.5, 1.0)],
+            )),
+            strength: 1.0,
+        };
+        let perturbed = bump_map.perturb(&frame, Vec3A::ZERO, Vec2::ZERO);
+        assert!(perturbed
+            .normal
+            .abs_diff_eq(Vec3A::new(1.0, 0.0, -1.0).normalize(), 1e-6));
+        assert!(perturbed.tangent.dot(perturbed.normal).abs() < 1e-6);
+
+        let no_strength = BumpMap::NormalMap {
+            texture: Arc::new(ImageTexture::new(
+                1,
+                1,
+                vec![Color::new_color(1.0, 0.5, 1.0)],
+            )),
+            strength: 0.0,
+        };
+        let perturbed = no_strength.perturb(&frame, Vec3A::ZERO, Vec2::ZERO);
+        assert!(perturbed.normal.abs_diff_eq(frame.normal, 1e-6));
+    }
+
+    #[rstest]
+    fn constant_height_pattern_keeps_the_normal(frame: TangentFrame) {
+        let bump_map = BumpMap::HeightPattern {
+            pattern: HeightPattern::new(|_| 0.25),
+            scale: 1.0,
+        };
+        let perturbed = bump_map.perturb(&frame, Vec3A::ZERO, Vec2::ZERO);
+        assert!(perturbed.normal.abs_diff_eq(frame.normal, 1e-6));
+    }
+
+    #[rstest]
+    fn height_pattern_tilts_the_normal_against_the_slope(frame: TangentFrame) {
+        // Height rises along x (the tangent), so the normal leans back towards -x
+        let bump_map = BumpMap::HeightPattern {
+            pattern: HeightPattern::new(|p| p.x),
+            scale: 1.0,
+        };
+        let perturbed = bump_map.perturb(&frame, Vec3A::ZERO, Vec2::ZERO);
+        assert!(perturbed
+            .normal
+            .abs_diff_eq(Vec3A::new(-1.0, 0.0, -1.0).normalize(), 1e-3));
+    }
+
+    #[test]
+    fn height_patterns_are_equal_only_to_their_clones() {
+        let pattern = HeightPattern::new(|p| p.y);
+        assert_eq!(pattern, pattern.clone());
+        assert_ne!(pattern, HeightPattern::new(|p| p.y));
+    }
+}
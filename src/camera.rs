@@ -0,0 +1,677 @@
+use std::sync::Arc;
+
+use glam::{Affine3A, Mat3A, Vec2, Vec3A};
+use indicatif::ParallelProgressIterator;
+use log::info;
+use rayon::prelude::*;
+
+use crate::{
+    antialiasing::Supersampling,
+    canvas::Canvas,
+    color::Color,
+    integrator::{Integrator, WhittedIntegrator},
+    projection::{Perspective, Projection},
+    ray::Ray,
+    sampler::Sampler,
+    sampling::{concentric_sample_disk, uniform_sample_polygon},
+    world::World,
+};
+
+/// Transformation that orients the world relative to the eye. The camera looks from `from` towards `to`,
+/// `up` only needs to point roughly upwards.
+pub fn view_transform(from: Vec3A, to: Vec3A, up: Vec3A) -> Affine3A {
+    let forward = (to - from).normalize();
+    let left = forward.cross(up.normalize());
+    let true_up = left.cross(forward);
+    // Rows of the orientation matrix are left, true_up and -forward
+    let orientation = Mat3A::from_cols(left, true_up, -forward).transpose();
+    Affine3A::from_mat3(orientation.into()) * Affine3A::from_translation((-from).into())
+}
+
+/// Shape of the lens opening, out-of-focus highlights (bokeh) take its shape.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circular,
+    /// Opening formed by straight diaphragm blades, a regular polygon rotated by the angle in radians
+    Polygonal { blades: u32, rotation: f32 },
+}
+
+impl Aperture {
+    /// Point on the aperture of radius 1.0, uniformly distributed over its area.
+    pub fn sample(&self, u: Vec2) -> Vec2 {
+        match self {
+            Aperture::Circular => concentric_sample_disk(u),
+            Aperture::Polygonal { blades, rotation } => {
+                uniform_sample_polygon(u, *blades, *rotation)
+            }
+        }
+    }
+}
+
+/// Thin lens which gives the camera depth of field. Only points at the focal distance are sharp, the larger
+/// the aperture the more blurred everything else is. The plane in focus is in front of the eye, rays of wide
+/// fisheye and panoramic projections that look sideways or backwards never reach it and stay sharp.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct ThinLens {
+    pub aperture_radius: f32,
+    /// Distance from the eye to the plane in focus, along the viewing direction
+    pub focal_distance: f32,
+    pub aperture: Aperture,
+}
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    hsize: u16,
+    vsize: u16,
+    transform: Affine3A,
+    inverse_transform: Affine3A,
+    projection: Arc<dyn Projection>,
+    supersampling: Supersampling,
+    lens: Option<ThinLens>,
+    /// Times the shutter opens and closes, rays are spread over the interval
+    shutter: (f32, f32),
+}
+
+impl Camera {
+    /// Perspective camera with the canvas one unit in front of the eye. Field of view is in radians.
+    pub fn new(hsize: u16, vsize: u16, field_of_view: f32) -> Self {
+        Self::new_with_projection(hsize, vsize, Perspective { field_of_view })
+    }
+
+    pub fn new_with_projection(
+        hsize: u16,
+        vsize: u16,
+        projection: impl Projection + 'static,
+    ) -> Self {
+        Self {
+            hsize,
+            vsize,
+            transform: Affine3A::IDENTITY,
+            inverse_transform: Affine3A::IDENTITY,
+            projection: Arc::new(projection),
+            supersampling: Supersampling::default(),
+            lens: None,
+            shutter: (0.0, 0.0),
+        }
+    }
+
+    pub fn set_transform(&mut self, transform: Affine3A) {
+        self.transform = transform;
+        self.inverse_transform = transform.inverse();
+    }
+
+    pub fn set_projection(&mut self, projection: impl Projection + 'static) {
+        self.projection = Arc::new(projection)
+    }
+
+    pub fn set_supersampling(&mut self, supersampling: Supersampling) {
+        self.supersampling = supersampling
+    }
+
+    /// Without a lens the camera is a pinhole camera, everything is in focus.
+    pub fn set_lens(&mut self, lens: ThinLens) {
+        if lens.aperture_radius < 0.0 {
+            panic!(
+                "Lens aperture radius should not be negative, aperture radius: {}",
+                lens.aperture_radius
+            );
+        }
+        if lens.focal_distance <= 0.0 {
+            panic!(
+                "Lens should focus in front of the eye, focal distance: {}",
+                lens.focal_distance
+            );
+        }
+        if let Aperture::Polygonal { blades, .. } = lens.aperture {
+            if blades < 3 {
+                panic!("Aperture should have at least 3 blades, blades: {}", blades);
+            }
+        }
+        self.lens = Some(lens)
+    }
+
+    pub fn lens(&self) -> Option<&ThinLens> {
+        self.lens.as_ref()
+    }
+
+    /// Objects in motion while the shutter is open are blurred along their path. The shutter is closed
+    /// at the time 0.0 by default, which takes a still image.
+    pub fn set_shutter(&mut self, open: f32, close: f32) {
+        if close < open {
+            panic!(
+                "Shutter should close after it opens, open: {}, close: {}",
+                open, close
+            );
+        }
+        self.shutter = (open, close)
+    }
+
+    pub fn shutter(&self) -> (f32, f32) {
+        self.shutter
+    }
+
+    pub fn hsize(&self) -> u16 {
+        self.hsize
+    }
+
+    pub fn vsize(&self) -> u16 {
+        self.vsize
+    }
+
+    /// Ray through the center of the pixel, `None` if the projection does not see anything there.
+    pub fn ray_for_pixel(&self, px: u16, py: u16) -> Option<Ray> {
+        self.ray_for_film_point(px as f32 + 0.5, py as f32 + 0.5)
+    }
+
+    /// Ray through a point on the film, pixel (x, y) spans from (x, y) to (x + 1, y + 1).
+    pub fn ray_for_film_point(&self, film_x: f32, film_y: f32) -> Option<Ray> {
+        self.ray_through_lens(film_x, film_y, Vec2::ZERO)
+    }
+
+    /// Ray through a point on the film, leaving the lens at a point and at a time within the shutter interval
+    /// sampled from the sampler. Pinhole cameras and instant shutters do not take those samples.
+    pub fn ray_for_film_sample(
+        &self,
+        film_x: f32,
+        film_y: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Ray> {
+        let u_lens = match self.lens {
+            Some(_) => sampler.next_2d(),
+            None => Vec2::ZERO,
+        };
+        let (open, close) = self.shutter;
+        let time = if close > open {
+            open + (close - open) * sampler.next_1d()
+        } else {
+            open
+        };
+        self.ray_through_lens(film_x, film_y, u_lens)
+            .map(|ray| Ray { time, ..ray })
+    }
+
+    fn ray_through_lens(&self, film_x: f32, film_y: f32, u_lens: Vec2) -> Option<Ray> {
+        let film = Vec2::new(film_x / self.hsize as f32, film_y / self.vsize as f32);
+        let aspect = self.hsize as f32 / self.vsize as f32;
+        let ray = self.projection.ray_for_film(film, aspect)?;
+
+        let (origin, direction) = match &self.lens {
+            Some(lens) if lens.aperture_radius > 0.0 && ray.direction_vector.z < 0.0 => {
+                // All rays through the film point meet on the plane in focus
+                let focus = ray.position(lens.focal_distance / -ray.direction_vector.z);
+                let lens_point = lens.aperture.sample(u_lens) * lens.aperture_radius;
+                let origin = ray.origin_point + Vec3A::new(lens_point.x, lens_point.y, 0.0);
+                (origin, focus - origin)
+            }
+            _ => (ray.origin_point, ray.direction_vector),
+        };
+        Some(Ray::new(
+            self.inverse_transform.transform_point3a(origin),
+            self.inverse_transform
+                .transform_vector3a(direction)
+                .normalize(),
+        ))
+    }
+
+    pub fn pixel_color(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        px: u16,
+        py: u16,
+    ) -> Color {
+        self.supersampling
+            .pixel_color(px, py, |film_x, film_y, sampler| {
+                self.radiance(world, integrator, film_x, film_y, sampler)
+            })
+    }
+
+    /// Radiance arriving at the point on the film, black where the projection does not see anything.
+    fn radiance(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        film_x: f32,
+        film_y: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        match self.ray_for_film_sample(film_x, film_y, sampler) {
+            Some(ray) => integrator.radiance(world, ray, sampler),
+            None => Color::new_black(),
+        }
+    }
+
+    /// Renders the world with Whitted-style ray tracing.
+    pub fn render(&self, world: &World) -> Canvas {
+        self.render_with(world, &WhittedIntegrator::default())
+    }
+
+    pub fn render_with(&self, world: &World, integrator: &dyn Integrator) -> Canvas {
+        self.render_with_sample_heatmap(world, integrator).0
+    }
+
+    /// Renders the world and a heatmap of the number of samples taken in each pixel, which shows where
+    /// adaptive sampling spent the sample budget. Blue pixels took the fewest samples, red ones the most.
+    pub fn render_with_sample_heatmap(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+    ) -> (Canvas, Canvas) {
+        info!(
+            "Rendering world, integrator: {:?}, width: {}, height: {}, samples per pixel: {}, adaptive: {:?}",
+            integrator,
+            self.hsize,
+            self.vsize,
+            self.supersampling.samples_per_pixel(),
+            self.supersampling.adaptive()
+        );
+
+        let rows: Vec<Vec<(Color, u32)>> = (0..self.vsize)
+            .into_par_iter()
+            .progress_count(self.vsize as u64)
+            .map(|y| {
+                (0..self.hsize)
+                    .map(|x| {
+                        self.supersampling.pixel_color_with_sample_count(
+                            x,
+                            y,
+                            |film_x, film_y, sampler| {
+                                self.radiance(world, integrator, film_x, film_y, sampler)
+                            },
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let max_samples = self.supersampling.samples_per_pixel();
+        let mut total_samples = 0_u64;
+        let mut canvas = Canvas::new_black_canvas(self.hsize, self.vsize);
+        let mut heatmap = Canvas::new_black_canvas(self.hsize, self.vsize);
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, (color, samples)) in row.into_iter().enumerate() {
+                canvas.write_pixel(x as u16, y as u16, color);
+                heatmap.write_pixel(x as u16, y as u16, heatmap_color(samples, max_samples));
+                total_samples += samples as u64;
+            }
+        }
+        info!(
+            "Average samples per pixel: {:.2}",
+            total_samples as f32 / (self.hsize as f32 * self.vsize as f32)
+        );
+        (canvas, heatmap)
+    }
+}
+
+fn heatmap_color(samples: u32, max_samples: u32) -> Color {
+    let t = samples as f32 / max_samples as f32;
+    Color::new_color(t, 0.0, 1.0 - t)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+    use approx::assert_abs_diff_eq;
+    use glam::{Mat4, Vec3};
+    use rstest::*;
+
+    use crate::{
+        antialiasing::{AdaptiveSampling, ReconstructionFilter},
+        background::Background,
+        material::Material,
+        motion::{AnimatedTransform, MovingShape},
+        path_tracer::PathTracer,
+        projection::{Fisheye, FisheyeMapping, Orthographic},
+        sampler::{IndependentSampler, SobolSampler, StratifiedSampler},
+        sphere::Sphere,
+    };
+
+    use super::*;
+
+    #[test]
+    fn the_transformation_matrix_for_the_default_orientation() {
+        let transform = view_transform(
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, -1.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        );
+        assert!(transform.abs_diff_eq(Affine3A::IDENTITY, 1e-6));
+    }
+
+    #[test]
+    fn a_view_transformation_matrix_looking_in_positive_z_direction() {
+        let transform = view_transform(
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, 1.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        );
+        assert!(transform.abs_diff_eq(Affine3A::from_scale(Vec3::new(-1.0, 1.0, -1.0)), 1e-6));
+    }
+
+    #[test]
+    fn the_view_transformation_moves_the_world() {
+        let transform = view_transform(
+            Vec3A::new(0.0, 0.0, 8.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        );
+        assert!(transform.abs_diff_eq(Affine3A::from_translation(Vec3::new(0.0, 0.0, -8.0)), 1e-6));
+    }
+
+    #[test]
+    fn an_arbitrary_view_transformation() {
+        let transform = view_transform(
+            Vec3A::new(1.0, 3.0, 2.0),
+            Vec3A::new(4.0, -2.0, 8.0),
+            Vec3A::new(1.0, 1.0, 0.0),
+        );
+        let expected = Mat4::from_cols_array_2d(&[
+            [-0.50709, 0.76772, -0.35857, 0.0],
+            [0.50709, 0.60609, 0.59761, 0.0],
+            [0.67612, 0.12122, -0.71714, 0.0],
+            [-2.36643, -2.82843, 0.0, 1.0],
+        ]);
+        assert!(Mat4::from(transform).abs_diff_eq(expected, 1e-4));
+    }
+
+    #[test]
+    fn constructing_a_camera() {
+        let camera = Camera::new(160, 120, PI / 2.0);
+        assert_eq!(camera.hsize(), 160);
+        assert_eq!(camera.vsize(), 120);
+        assert_eq!(camera.transform, Affine3A::IDENTITY);
+    }
+
+    #[test]
+    fn constructing_a_ray_through_the_center_of_the_canvas() {
+        let camera = Camera::new(201, 101, PI / 2.0);
+        let ray = camera.ray_for_pixel(100, 50).expect("should see the pixel");
+        assert!(ray.origin_point.abs_diff_eq(Vec3A::ZERO, 1e-6));
+        assert!(ray
+            .direction_vector
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+    }
+
+    #[test]
+    fn constructing_a_ray_through_a_corner_of_the_canvas() {
+        let camera = Camera::new(201, 101, PI / 2.0);
+        let ray = camera.ray_for_pixel(0, 0).expect("should see the pixel");
+        assert!(ray.origin_point.abs_diff_eq(Vec3A::ZERO, 1e-6));
+        assert!(ray
+            .direction_vector
+            .abs_diff_eq(Vec3A::new(0.66519, 0.33259, -0.66851), 1e-5));
+    }
+
+    #[test]
+    fn constructing_a_ray_when_the_camera_is_transformed() {
+        let mut camera = Camera::new(201, 101, PI / 2.0);
+        camera.set_transform(
+            Affine3A::from_rotation_y(PI / 4.0)
+                * Affine3A::from_translation(Vec3::new(0.0, -2.0, 5.0)),
+        );
+        let ray = camera.ray_for_pixel(100, 50).expect("should see the pixel");
+        assert!(ray
+            .origin_point
+            .abs_diff_eq(Vec3A::new(0.0, 2.0, -5.0), 1e-5));
+        assert!(ray
+            .direction_vector
+            .abs_diff_eq(Vec3A::new(FRAC_1_SQRT_2, 0.0, -FRAC_1_SQRT_2), 1e-5));
+    }
+
+    #[test]
+    fn orthographic_cameras_shoot_parallel_rays_in_the_viewing_direction() {
+        let mut camera = Camera::new(201, 101, PI / 2.0);
+        camera.set_transform(view_transform(
+            Vec3A::new(0.0, 0.0, -5.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        ));
+        camera.set_projection(Orthographic { half_size: 2.0 });
+        let center = camera.ray_for_pixel(100, 50).expect("should see the pixel");
+        let corner = camera
+            .ray_for_film_point(0.0, 0.0)
+            .expect("should see the corner");
+        assert!(center
+            .origin_point
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -5.0), 1e-5));
+        assert!(corner
+            .origin_point
+            .abs_diff_eq(Vec3A::new(-2.0, 2.0 * 101.0 / 201.0, -5.0), 1e-4));
+        assert!(center.direction_vector.abs_diff_eq(Vec3A::Z, 1e-6));
+        assert!(corner.direction_vector.abs_diff_eq(Vec3A::Z, 1e-6));
+    }
+
+    #[test]
+    fn fisheye_cameras_leave_the_film_outside_of_the_image_circle_black() {
+        let mut world = World::new_empty();
+        world.set_background(Background::SolidColor(Color::new_white()));
+        let camera = Camera::new_with_projection(
+            11,
+            11,
+            Fisheye {
+                field_of_view: PI,
+                mapping: FisheyeMapping::Equidistant,
+            },
+        );
+        let mut image = camera.render_with(&world, &PathTracer::default());
+        assert_eq!(*image.pixel_at(5, 5), Color::new_white());
+        assert_eq!(*image.pixel_at(0, 0), Color::new_black());
+        assert_eq!(*image.pixel_at(10, 10), Color::new_black());
+    }
+
+    #[rstest]
+    #[case::circular(Aperture::Circular)]
+    #[case::hexagonal(Aperture::Polygonal { blades: 6, rotation: 0.2 })]
+    fn rays_through_the_lens_meet_on_the_plane_in_focus(#[case] aperture: Aperture) {
+        let mut camera = Camera::new(201, 101, PI / 2.0);
+        camera.set_transform(view_transform(
+            Vec3A::new(1.0, 0.0, -5.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        ));
+        camera.set_lens(ThinLens {
+            aperture_radius: 0.5,
+            focal_distance: 4.0,
+            aperture,
+        });
+        let eye = Vec3A::new(1.0, 0.0, -5.0);
+        let forward = -eye.normalize();
+        let pinhole = camera
+            .ray_through_lens(30.5, 70.5, Vec2::ZERO)
+            .expect("should see the pixel");
+        let focus = pinhole.position(4.0 / pinhole.direction_vector.dot(forward));
+
+        let mut sampler = IndependentSampler::new(16, 0);
+        let mut origins = Vec::new();
+        for sample_index in 0..16 {
+            sampler.start_pixel_sample(30, 70, sample_index);
+            let ray = camera
+                .ray_for_film_sample(30.5, 70.5, &mut sampler)
+                .expect("should see the pixel");
+            let offset = ray.origin_point - eye;
+            assert!(offset.length() <= 0.5 + 1e-5);
+            assert_abs_diff_eq!(offset.dot(forward), 0.0, epsilon = 1e-5);
+            let to_focus = (focus - ray.origin_point).normalize();
+            assert!(ray.direction_vector.abs_diff_eq(to_focus, 1e-4));
+            origins.push(ray.origin_point);
+        }
+        assert!(origins.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[rstest]
+    #[should_panic(expected = "Lens aperture radius should not be negative")]
+    #[case::negative_aperture(-0.1, 1.0, Aperture::Circular)]
+    #[should_panic(expected = "Lens should focus in front of the eye")]
+    #[case::focus_behind_the_eye(0.1, 0.0, Aperture::Circular)]
+    #[should_panic(expected = "Aperture should have at least 3 blades")]
+    #[case::two_blades(0.1, 1.0, Aperture::Polygonal { blades: 2, rotation: 0.0 })]
+    fn invalid_lenses_are_rejected(
+        #[case] aperture_radius: f32,
+        #[case] focal_distance: f32,
+        #[case] aperture: Aperture,
+    ) {
+        Camera::new(11, 11, PI / 2.0).set_lens(ThinLens {
+            aperture_radius,
+            focal_distance,
+            aperture,
+        });
+    }
+
+    #[test]
+    fn pinhole_cameras_do_not_sample_the_lens() {
+        let camera = Camera::new(201, 101, PI / 2.0);
+        let mut sampler = IndependentSampler::new(1, 0);
+        sampler.start_pixel_sample(3, 4, 0);
+        let ray = camera.ray_for_film_sample(3.5, 4.5, &mut sampler);
+        assert_eq!(ray, camera.ray_for_pixel(3, 4));
+        let mut fresh = IndependentSampler::new(1, 0);
+        fresh.start_pixel_sample(3, 4, 0);
+        assert_eq!(sampler.next_1d(), fresh.next_1d());
+    }
+
+    #[test]
+    fn rays_are_spread_over_the_shutter_interval() {
+        let mut camera = Camera::new(11, 11, PI / 2.0);
+        camera.set_shutter(0.25, 0.75);
+        let mut sampler = IndependentSampler::new(64, 0);
+        let times: Vec<f32> = (0..64)
+            .map(|sample_index| {
+                sampler.start_pixel_sample(5, 5, sample_index);
+                camera
+                    .ray_for_film_sample(5.5, 5.5, &mut sampler)
+                    .expect("should see the pixel")
+                    .time
+            })
+            .collect();
+        assert!(times.iter().all(|time| (0.25..=0.75).contains(time)));
+        assert_abs_diff_eq!(times.iter().sum::<f32>() / 64.0, 0.5, epsilon = 0.05);
+    }
+
+    #[test]
+    #[should_panic(expected = "Shutter should close after it opens")]
+    fn shutter_cannot_close_before_it_opens() {
+        Camera::new(11, 11, PI / 2.0).set_shutter(1.0, 0.0);
+    }
+
+    #[test]
+    fn objects_moving_while_the_shutter_is_open_are_blurred() {
+        let mut world = World::new_empty();
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(Material {
+            emissive: Color::new_white(),
+            ..Default::default()
+        });
+        // The sphere covers the center of the view while it moves by its radius, a quarter of the interval
+        let motion = AnimatedTransform::new(vec![
+            (0.0, Affine3A::IDENTITY),
+            (1.0, Affine3A::from_translation(Vec3::new(4.0, 0.0, 0.0))),
+        ]);
+        world.add_object(MovingShape::new(sphere, motion));
+        let mut camera = Camera::new(1, 1, 0.01);
+        camera.set_transform(view_transform(
+            Vec3A::new(0.0, 0.0, -5.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        ));
+        camera.set_supersampling(Supersampling::new(
+            IndependentSampler::new(1000, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        ));
+
+        let mut still = camera.render_with(&world, &PathTracer::default());
+        assert_eq!(*still.pixel_at(0, 0), Color::new_white());
+        camera.set_shutter(0.0, 1.0);
+        let mut blurred = camera.render_with(&world, &PathTracer::default());
+        assert_abs_diff_eq!(blurred.pixel_at(0, 0).get_red_val(), 0.25, epsilon = 0.04);
+    }
+
+    #[test]
+    fn rendering_a_world_with_a_camera() {
+        let world = World::new_default();
+        let mut camera = Camera::new(11, 11, PI / 2.0);
+        camera.set_transform(view_transform(
+            Vec3A::new(0.0, 0.0, -5.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        ));
+        let mut image = camera.render(&world);
+        let color = image.pixel_at(5, 5);
+        assert_abs_diff_eq!(color.get_red_val(), 0.38066, epsilon = 1e-4);
+        assert_abs_diff_eq!(color.get_green_val(), 0.47583, epsilon = 1e-4);
+        assert_abs_diff_eq!(color.get_blue_val(), 0.2855, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn supersampling_blends_colors_along_edges() {
+        let world = World::new_default();
+        let mut camera = Camera::new(11, 11, PI / 2.0);
+        camera.set_transform(view_transform(
+            Vec3A::new(0.0, 0.0, -5.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        ));
+        let mut aliased = camera.render(&world);
+
+        camera.set_supersampling(Supersampling::new(
+            StratifiedSampler::new(4, 4, true, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        ));
+        let mut antialiased = camera.render(&world);
+
+        // Center of the sphere is flat, the silhouette gets blended with the black background
+        let center = *antialiased.pixel_at(5, 5);
+        assert_abs_diff_eq!(center.get_green_val(), 0.47583, epsilon = 0.05);
+        let aliased_pixels: Vec<Color> = (0..11).map(|x| *aliased.pixel_at(x, 5)).collect();
+        let antialiased_pixels: Vec<Color> = (0..11).map(|x| *antialiased.pixel_at(x, 5)).collect();
+        assert_ne!(aliased_pixels, antialiased_pixels);
+    }
+
+    #[test]
+    fn adaptive_sampling_spends_samples_along_edges() {
+        let world = World::new_default();
+        let mut camera = Camera::new(11, 11, PI / 2.0);
+        camera.set_transform(view_transform(
+            Vec3A::new(0.0, 0.0, -5.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        ));
+        let mut supersampling = Supersampling::new(
+            SobolSampler::new(16, 0),
+            ReconstructionFilter::Box { radius: 0.5 },
+        );
+        supersampling.set_adaptive(AdaptiveSampling::new(4, 0.005));
+        camera.set_supersampling(supersampling);
+
+        let (_, mut heatmap) =
+            camera.render_with_sample_heatmap(&world, &WhittedIntegrator::default());
+        // Background in the corner converges right away, the silhouette of the sphere does not
+        assert_eq!(*heatmap.pixel_at(0, 0), heatmap_color(4, 16));
+        let edge_samples = (0..11)
+            .map(|x| heatmap.pixel_at(x, 5).get_red_val())
+            .fold(0.0, f32::max);
+        assert_abs_diff_eq!(edge_samples, 1.0);
+    }
+
+    #[test]
+    fn rendering_with_a_path_tracer() {
+        let mut world = World::new_empty();
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(Material {
+            emissive: Color::new_white(),
+            ..Default::default()
+        });
+        world.add_object(sphere);
+        let mut camera = Camera::new(11, 11, PI / 2.0);
+        camera.set_transform(view_transform(
+            Vec3A::new(0.0, 0.0, -5.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        ));
+
+        let mut image = camera.render_with(&world, &PathTracer::default());
+        assert_eq!(*image.pixel_at(5, 5), Color::new_white());
+        assert_eq!(*image.pixel_at(0, 0), Color::new_black());
+    }
+}
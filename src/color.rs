@@ -1,4 +1,4 @@
-use std::ops::{Add, Mul, Sub};
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
 
 use glam::Vec3A;
 
@@ -56,6 +56,22 @@ impl Color {
         self.values.z
     }
 
+    /// Perceived brightness of the linear RGB color (Rec. 709 weights).
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.values.x + 0.7152 * self.values.y + 0.0722 * self.values.z
+    }
+
+    pub fn max_component(&self) -> f32 {
+        self.values.max_element()
+    }
+
+    /// Creates a color from linear, floating point RGB values, e.g. texels decoded from an HDR image.
+    pub fn from_rgb_slice(rgb: &[f32]) -> Self {
+        Self {
+            values: Vec3A::new(rgb[0], rgb[1], rgb[2]),
+        }
+    }
+
     pub fn get_red_val_as_u8(&self) -> u8 {
         (self.values.x * 255.0).round() as u8
     }
@@ -79,6 +95,12 @@ impl Add for Color {
     }
 }
 
+impl AddAssign for Color {
+    fn add_assign(&mut self, other: Self) {
+        self.values += other.values;
+    }
+}
+
 impl Sub for Color {
     type Output = Self;
 
@@ -109,6 +131,16 @@ impl Mul<f32> for Color {
     }
 }
 
+impl Div<f32> for Color {
+    type Output = Self;
+
+    fn div(self, rhs: f32) -> Self {
+        Self {
+            values: self.values / rhs,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use approx::assert_abs_diff_eq;
@@ -184,6 +216,21 @@ mod tests {
         assert_abs_diff_eq!(result.values.z, 1.5, epsilon = f32::EPSILON);
     }
 
+    #[rstest]
+    fn can_divide_color_by_a_scalar(color: Color) {
+        let result = color / 2.0;
+        assert_abs_diff_eq!(result.values.x, 0.45, epsilon = f32::EPSILON);
+        assert_abs_diff_eq!(result.values.y, 0.3, epsilon = f32::EPSILON);
+        assert_abs_diff_eq!(result.values.z, 0.375, epsilon = f32::EPSILON);
+    }
+
+    #[rstest]
+    fn can_accumulate_colors(color: Color, color2: Color) {
+        let mut result = color;
+        result += color2;
+        assert_eq!(result, color + color2);
+    }
+
     #[rstest]
     #[case::black(Color::new_black(), Color::new_color(0.0, 0.0, 0.0))]
     #[case::white(Color::new_white(), Color::new_color(1.0, 1.0, 1.0))]
@@ -241,4 +288,17 @@ mod tests {
         assert_eq!(input_color.get_green_val_as_u8(), expected_green_value);
         assert_eq!(input_color.get_blue_val_as_u8(), expected_blue_value);
     }
+
+    #[rstest]
+    #[case::black(Color::new_black(), 0.0)]
+    #[case::white(Color::new_white(), 1.0)]
+    #[case::green(Color::new_green(), 0.7152)]
+    fn luminance_weights_green_the_most(#[case] color: Color, #[case] expected_luminance: f32) {
+        assert_abs_diff_eq!(color.luminance(), expected_luminance, epsilon = 1e-6);
+    }
+
+    #[rstest]
+    fn max_component_is_the_brightest_channel(color: Color) {
+        assert_abs_diff_eq!(color.max_component(), 0.9);
+    }
 }
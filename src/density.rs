@@ -0,0 +1,385 @@
+use std::{
+    fmt, fs,
+    io::{self, ErrorKind},
+    sync::Arc,
+};
+
+use glam::Vec3A;
+use log::info;
+
+use crate::hash::hash_values;
+
+/// Spatially varying density of a medium, which scales its coefficients at each point.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum Density {
+    /// Density of 1.0 everywhere
+    #[default]
+    Uniform,
+    Grid(Arc<VoxelGrid>),
+    Noise(NoiseDensity),
+}
+
+impl Density {
+    pub fn at(&self, point: Vec3A) -> f32 {
+        match self {
+            Self::Uniform => 1.0,
+            Self::Grid(grid) => grid.density_at(point),
+            Self::Noise(noise) => noise.density_at(point),
+        }
+    }
+
+    /// Upper bound of the density, which bounds the extinction coefficient for tracking.
+    pub fn max(&self) -> f32 {
+        match self {
+            Self::Uniform | Self::Noise(_) => 1.0,
+            Self::Grid(grid) => grid.max_density(),
+        }
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        matches!(self, Self::Uniform)
+    }
+}
+
+/// Dense grid of density values stored at the centers of voxels spanning the box between `min` and `max`,
+/// interpolated trilinearly. Density outside the box is 0.0.
+#[derive(PartialEq, Clone)]
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    values: Vec<f32>,
+    min: Vec3A,
+    max: Vec3A,
+    max_density: f32,
+}
+
+impl VoxelGrid {
+    /// Values are ordered with x changing fastest, then y, then z, and must not be negative.
+    pub fn new(resolution: [usize; 3], values: Vec<f32>, min: Vec3A, max: Vec3A) -> Self {
+        let [nx, ny, nz] = resolution;
+        if nx == 0 || ny == 0 || nz == 0 || values.len() != nx * ny * nz {
+            panic!(
+                "Voxel grid values do not match its resolution, resolution: {:?}, values: {}",
+                resolution,
+                values.len()
+            );
+        }
+        if let Some(value) = values.iter().find(|value| value.is_nan() || **value < 0.0) {
+            panic!(
+                "Voxel grid density should not be negative, density: {}",
+                value
+            );
+        }
+        if min.cmpge(max).any() {
+            panic!("Voxel grid bounds are empty, min: {}, max: {}", min, max);
+        }
+
+        let max_density = values.iter().fold(0.0_f32, |acc, &value| acc.max(value));
+        Self {
+            resolution,
+            values,
+            min,
+            max,
+            max_density,
+        }
+    }
+
+    /// Loads a grid from a binary file: resolution along x, y and z as little-endian u32, followed by
+    /// the values as little-endian f32 in the order of `new`. Files of the wrong size or with negative
+    /// values are reported as invalid data.
+    pub fn load_from_file(voxel_file_path: &str, min: Vec3A, max: Vec3A) -> io::Result<Self> {
+        info!("Loading voxel grid from file: {}", voxel_file_path);
+        let bytes = fs::read(voxel_file_path)?;
+        let invalid_data = |message: String| io::Error::new(ErrorKind::InvalidData, message);
+        if bytes.len() < 12 {
+            return Err(invalid_data(format!(
+                "Voxel grid header is too short, bytes: {}",
+                bytes.len()
+            )));
+        }
+
+        let words: Vec<[u8; 4]> = bytes
+            .chunks_exact(4)
+            .map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]])
+            .collect();
+        let resolution = [0, 1, 2].map(|i| u32::from_le_bytes(words[i]) as usize);
+        let voxel_count = resolution
+            .iter()
+            .try_fold(1_usize, |acc, &dimension| acc.checked_mul(dimension));
+        if voxel_count == Some(0) || voxel_count.map(|count| count * 4 + 12) != Some(bytes.len()) {
+            return Err(invalid_data(format!(
+                "Voxel grid file size does not match its resolution, resolution: {:?}, bytes: {}",
+                resolution,
+                bytes.len()
+            )));
+        }
+
+        let values: Vec<f32> = words[3..].iter().copied().map(f32::from_le_bytes).collect();
+        if let Some(value) = values.iter().find(|value| value.is_nan() || **value < 0.0) {
+            return Err(invalid_data(format!(
+                "Voxel grid density should not be negative, density: {}",
+                value
+            )));
+        }
+        Ok(Self::new(resolution, values, min, max))
+    }
+
+    pub fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    pub fn max_density(&self) -> f32 {
+        self.max_density
+    }
+
+    pub fn density_at(&self, point: Vec3A) -> f32 {
+        if point.cmplt(self.min).any() || point.cmpgt(self.max).any() {
+            return 0.0;
+        }
+
+        let [nx, ny, nz] = self.resolution;
+        let size = Vec3A::new(nx as f32, ny as f32, nz as f32);
+        // Voxel coordinates relative to the center of the first voxel
+        let p = (point - self.min) / (self.max - self.min) * size - 0.5;
+        let base = p.floor();
+        let t = p - base;
+        let mut density = 0.0;
+        for corner in 0..8 {
+            let offset = Vec3A::new(
+                (corner & 1) as f32,
+                ((corner >> 1) & 1) as f32,
+                ((corner >> 2) & 1) as f32,
+            );
+            let weight = Vec3A::ONE - offset + (offset * 2.0 - 1.0) * t;
+            let index = (base + offset).clamp(Vec3A::ZERO, size - 1.0);
+            density += weight.x * weight.y * weight.z * self.value(index);
+        }
+        density
+    }
+
+    fn value(&self, index: Vec3A) -> f32 {
+        let [nx, ny, _] = self.resolution;
+        let (x, y, z) = (index.x as usize, index.y as usize, index.z as usize);
+        self.values[(z * ny + y) * nx + x]
+    }
+}
+
+impl fmt::Debug for VoxelGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VoxelGrid")
+            .field("resolution", &self.resolution)
+            .field("min", &self.min)
+            .field("max", &self.max)
+            .field("max_density", &self.max_density)
+            .finish()
+    }
+}
+
+/// Cloud-like density from fractal value noise, between 0.0 and 1.0.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct NoiseDensity {
+    /// Lattice cells of the first octave per unit distance
+    pub frequency: f32,
+    /// Number of octaves, each with double the frequency and half the amplitude of the previous one
+    pub octaves: u32,
+    /// Noise below this value is empty space, higher values give smaller, sparser clouds
+    pub coverage: f32,
+    pub seed: u64,
+}
+
+impl Default for NoiseDensity {
+    fn default() -> Self {
+        Self {
+            frequency: 1.0,
+            octaves: 4,
+            coverage: 0.4,
+            seed: 0,
+        }
+    }
+}
+
+impl NoiseDensity {
+    pub fn density_at(&self, point: Vec3A) -> f32 {
+        let mut noise = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.frequency;
+        for octave in 0..self.octaves.max(1) {
+            noise += amplitude * self.value_noise(point * frequency, octave);
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        let noise = noise / total_amplitude;
+        ((noise - self.coverage) / (1.0 - self.coverage).max(1e-6)).clamp(0.0, 1.0)
+    }
+
+    /// Random values at lattice points blended with a smooth step, so the noise has no visible creases.
+    fn value_noise(&self, point: Vec3A, octave: u32) -> f32 {
+        let base = point.floor();
+        let t = point - base;
+        let t = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let offset = Vec3A::new(
+                (corner & 1) as f32,
+                ((corner >> 1) & 1) as f32,
+                ((corner >> 2) & 1) as f32,
+            );
+            let weight = Vec3A::ONE - offset + (offset * 2.0 - 1.0) * t;
+            let lattice = base + offset;
+            let hash = hash_values(
+                self.seed,
+                &[
+                    octave as u64,
+                    lattice.x as i64 as u64,
+                    lattice.y as i64 as u64,
+                    lattice.z as i64 as u64,
+                ],
+            );
+            // Top 24 bits fit the mantissa exactly
+            let random = (hash >> 40) as f32 / (1u64 << 24) as f32;
+            value += weight.x * weight.y * weight.z * random;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    /// 2 x 1 x 1 voxels over the box from (0, 0, 0) to (2, 1, 1), empty on the left and dense on the right.
+    #[fixture]
+    fn ramp() -> VoxelGrid {
+        VoxelGrid::new(
+            [2, 1, 1],
+            vec![0.0, 1.0],
+            Vec3A::ZERO,
+            Vec3A::new(2.0, 1.0, 1.0),
+        )
+    }
+
+    #[rstest]
+    #[case::first_voxel_center(Vec3A::new(0.5, 0.5, 0.5), 0.0)]
+    #[case::between_the_centers(Vec3A::new(1.0, 0.5, 0.5), 0.5)]
+    #[case::last_voxel_center(Vec3A::new(1.5, 0.2, 0.9), 1.0)]
+    #[case::clamped_at_the_edge(Vec3A::new(1.9, 0.5, 0.5), 1.0)]
+    #[case::outside_the_box(Vec3A::new(2.5, 0.5, 0.5), 0.0)]
+    fn voxel_grid_interpolates_between_voxel_centers(
+        ramp: VoxelGrid,
+        #[case] point: Vec3A,
+        #[case] expected: f32,
+    ) {
+        assert_abs_diff_eq!(ramp.density_at(point), expected, epsilon = 1e-5);
+        assert_eq!(ramp.max_density(), 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Voxel grid values do not match its resolution")]
+    fn voxel_grid_values_must_match_the_resolution() {
+        VoxelGrid::new([2, 2, 1], vec![0.0; 3], Vec3A::ZERO, Vec3A::ONE);
+    }
+
+    #[test]
+    #[should_panic(expected = "Voxel grid density should not be negative")]
+    fn voxel_grid_values_must_not_be_negative() {
+        VoxelGrid::new([2, 1, 1], vec![0.5, -0.5], Vec3A::ZERO, Vec3A::ONE);
+    }
+
+    #[rstest]
+    #[case("short_header", &[2, 2], &[])]
+    #[case("missing_values", &[2, 2, 1], &[0.0, 0.5, 1.0])]
+    #[case("trailing_values", &[2, 1, 1], &[0.0, 0.5, 1.0])]
+    #[case("empty_resolution", &[2, 0, 1], &[])]
+    #[case("negative_density", &[2, 1, 1], &[0.0, -0.5])]
+    fn malformed_voxel_grid_files_are_invalid_data(
+        #[case] name: &str,
+        #[case] dimensions: &[u32],
+        #[case] values: &[f32],
+    ) {
+        let mut bytes = Vec::new();
+        for dimension in dimensions {
+            bytes.extend_from_slice(&dimension.to_le_bytes());
+        }
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let path = std::env::temp_dir().join(format!("malformed_voxel_grid_{}.bin", name));
+        fs::write(&path, bytes).expect("should write voxel grid file");
+
+        let result = VoxelGrid::load_from_file(
+            path.to_str().expect("should be a valid path"),
+            Vec3A::ZERO,
+            Vec3A::ONE,
+        );
+        fs::remove_file(path).expect("should remove voxel grid file");
+        let error = result.expect_err("should reject malformed voxel grid file");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn missing_voxel_grid_files_are_not_found() {
+        let result = VoxelGrid::load_from_file("missing_voxel_grid.bin", Vec3A::ZERO, Vec3A::ONE);
+        assert_eq!(
+            result.expect_err("should not find the file").kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn voxel_grid_is_loaded_from_a_file() {
+        let values = [0.0_f32, 0.25, 0.5, 0.75, 1.0, 1.25];
+        let mut bytes = Vec::new();
+        for dimension in [3_u32, 2, 1] {
+            bytes.extend_from_slice(&dimension.to_le_bytes());
+        }
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let path = std::env::temp_dir().join("voxel_grid_is_loaded_from_a_file.bin");
+        fs::write(&path, bytes).expect("should write voxel grid file");
+
+        let grid = VoxelGrid::load_from_file(
+            path.to_str().expect("should be a valid path"),
+            Vec3A::ZERO,
+            Vec3A::new(3.0, 2.0, 1.0),
+        )
+        .expect("should load voxel grid file");
+        assert_eq!(grid.resolution(), [3, 2, 1]);
+        assert_eq!(grid.max_density(), 1.25);
+        // Center of the voxel at (1, 1, 0)
+        assert_abs_diff_eq!(grid.density_at(Vec3A::new(1.5, 1.5, 0.5)), 1.0);
+        fs::remove_file(path).expect("should remove voxel grid file");
+    }
+
+    #[test]
+    fn noise_density_is_bounded_and_coverage_leaves_empty_space() {
+        let sparse = NoiseDensity {
+            coverage: 0.6,
+            ..Default::default()
+        };
+        let dense = NoiseDensity {
+            coverage: 0.0,
+            ..Default::default()
+        };
+        let mut sparse_empty = 0;
+        let mut dense_empty = 0;
+        for i in 0..1000 {
+            let point = Vec3A::new(
+                i as f32 * 0.37,
+                (i % 17) as f32 * 0.53,
+                (i % 7) as f32 * 1.1,
+            );
+            let density = sparse.density_at(point);
+            assert!((0.0..=1.0).contains(&density));
+            assert_eq!(density, sparse.density_at(point));
+            sparse_empty += (density == 0.0) as u32;
+            dense_empty += (dense.density_at(point) == 0.0) as u32;
+        }
+        assert!(sparse_empty > 300);
+        assert!(dense_empty < sparse_empty);
+    }
+}
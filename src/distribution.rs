@@ -0,0 +1,207 @@
+use glam::Vec2;
+
+/// Piecewise-constant function over [0, 1) that can be sampled proportionally to its values.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Distribution1D {
+    function: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    /// Negative values are treated as 0.0. A function that is 0.0 everywhere is sampled uniformly.
+    pub fn new(function: Vec<f32>) -> Self {
+        assert!(!function.is_empty(), "distribution should not be empty");
+        let function: Vec<f32> = function.into_iter().map(|value| value.max(0.0)).collect();
+        let count = function.len() as f32;
+
+        let mut cdf = Vec::with_capacity(function.len() + 1);
+        cdf.push(0.0);
+        for value in &function {
+            cdf.push(cdf[cdf.len() - 1] + value / count);
+        }
+        let integral = cdf[function.len()];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            *value = if integral > 0.0 {
+                *value / integral
+            } else {
+                i as f32 / count
+            };
+        }
+
+        Self {
+            function,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.function.len()
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Samples a point in [0, 1), returns it with its probability density and the index of its segment.
+    pub fn sample_continuous(&self, u: f32) -> (f32, f32, usize) {
+        let offset =
+            (self.cdf.partition_point(|&value| value <= u).max(1) - 1).min(self.count() - 1);
+        let segment = self.cdf[offset + 1] - self.cdf[offset];
+        let mut du = u - self.cdf[offset];
+        if segment > 0.0 {
+            du /= segment;
+        }
+        let x = ((offset as f32 + du) / self.count() as f32).min(1.0 - f32::EPSILON);
+        (x, self.segment_pdf(offset), offset)
+    }
+
+    /// Probability density of `sample_continuous` returning `x`.
+    pub fn pdf(&self, x: f32) -> f32 {
+        self.segment_pdf(self.segment(x))
+    }
+
+    fn segment(&self, x: f32) -> usize {
+        ((x * self.count() as f32) as usize).min(self.count() - 1)
+    }
+
+    fn segment_pdf(&self, offset: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.function[offset] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Piecewise-constant function over the unit square, stored row by row. Rows are chosen by their integrals
+/// first, then the column within the row.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(function: &[f32], width: usize, height: usize) -> Self {
+        assert_eq!(
+            function.len(),
+            width * height,
+            "distribution should have a value for every cell"
+        );
+        let rows: Vec<Distribution1D> = function
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Self { rows, marginal }
+    }
+
+    /// Average of the function over the unit square.
+    pub fn integral(&self) -> f32 {
+        self.marginal.integral()
+    }
+
+    /// Samples a point of the unit square, x is the column and y the row coordinate. Returns it with its
+    /// probability density.
+    pub fn sample_continuous(&self, u: Vec2) -> (Vec2, f32) {
+        let (y, row_pdf, row) = self.marginal.sample_continuous(u.y);
+        let (x, column_pdf, _) = self.rows[row].sample_continuous(u.x);
+        (Vec2::new(x, y), row_pdf * column_pdf)
+    }
+
+    /// Probability density of `sample_continuous` returning the point.
+    pub fn pdf(&self, point: Vec2) -> f32 {
+        let row = self.marginal.segment(point.y);
+        self.marginal.segment_pdf(row) * self.rows[row].pdf(point.x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0.0, 0.0, 0)]
+    #[case(0.05, 0.125, 0)]
+    #[case(0.5, 0.375, 1)]
+    #[case(0.95, 0.875, 3)]
+    fn one_dimensional_samples_follow_the_function(
+        #[case] u: f32,
+        #[case] expected_x: f32,
+        #[case] expected_offset: usize,
+    ) {
+        // Second segment holds 80% of the integral, the others 10% each
+        let distribution = Distribution1D::new(vec![1.0, 8.0, 0.0, 1.0]);
+        assert_abs_diff_eq!(distribution.integral(), 2.5);
+
+        let (x, pdf, offset) = distribution.sample_continuous(u);
+        assert_abs_diff_eq!(x, expected_x, epsilon = 1e-6);
+        assert_eq!(offset, expected_offset);
+        assert_abs_diff_eq!(pdf, distribution.pdf(x));
+    }
+
+    #[test]
+    fn zero_function_is_sampled_uniformly() {
+        let distribution = Distribution1D::new(vec![0.0; 4]);
+        let (x, pdf, offset) = distribution.sample_continuous(0.6);
+        assert_abs_diff_eq!(x, 0.6, epsilon = 1e-6);
+        assert_eq!(offset, 2);
+        assert_abs_diff_eq!(pdf, 1.0);
+    }
+
+    #[test]
+    fn two_dimensional_pdf_integrates_to_one_and_matches_samples() {
+        let function = [0.0, 1.0, 2.0, 3.0, 10.0, 0.5];
+        let distribution = Distribution2D::new(&function, 3, 2);
+
+        let cell_area = 1.0 / 6.0;
+        let total: f32 = (0..6)
+            .map(|i| {
+                let center =
+                    Vec2::new((i % 3) as f32 + 0.5, (i / 3) as f32 + 0.5) / Vec2::new(3.0, 2.0);
+                distribution.pdf(center) * cell_area
+            })
+            .sum();
+        assert_abs_diff_eq!(total, 1.0, epsilon = 1e-5);
+
+        for u in [
+            Vec2::new(0.1, 0.2),
+            Vec2::new(0.7, 0.4),
+            Vec2::new(0.5, 0.9),
+        ] {
+            let (point, pdf) = distribution.sample_continuous(u);
+            assert!(pdf > 0.0);
+            assert_abs_diff_eq!(pdf, distribution.pdf(point), epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn two_dimensional_samples_concentrate_on_the_brightest_cell() {
+        let mut function = vec![1.0; 16];
+        function[9] = 100.0;
+        let distribution = Distribution2D::new(&function, 4, 4);
+
+        let strata = 32;
+        let mut in_bright_cell = 0;
+        for i in 0..strata {
+            for j in 0..strata {
+                let u = Vec2::new(i as f32 + 0.5, j as f32 + 0.5) / strata as f32;
+                let (point, _) = distribution.sample_continuous(u);
+                if (point * 4.0).floor() == Vec2::new(1.0, 2.0) {
+                    in_bright_cell += 1;
+                }
+            }
+        }
+        let expected = 100.0 / 115.0;
+        assert_abs_diff_eq!(
+            in_bright_cell as f32 / (strata * strata) as f32,
+            expected,
+            epsilon = 0.02
+        );
+    }
+}
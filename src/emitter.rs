@@ -0,0 +1,292 @@
+use std::f32::consts::PI;
+
+use glam::{Vec2, Vec3A};
+
+use crate::{
+    bump_map::TangentFrame,
+    color::Color,
+    intersection::EPSILON,
+    light::{Light, LightLiSample},
+    ray::Ray,
+    sampling::{
+        cosine_hemisphere_pdf, cosine_sample_hemisphere, uniform_cone_pdf, uniform_sample_cone,
+        uniform_sample_sphere, uniform_sphere_pdf,
+    },
+    shape::Shape,
+};
+
+/// Anything that emits light into the world: a light, or an object with an emissive material that is
+/// sampled by area and emits from both sides of its surface. Direct lighting samples all of them the same way.
+#[derive(Debug, Clone, Copy)]
+pub enum Emitter<'a> {
+    Light(&'a Light),
+    Object(&'a dyn Shape),
+}
+
+impl<'a> Emitter<'a> {
+    pub fn is_delta(&self) -> bool {
+        match self {
+            Self::Light(light) => light.is_delta(),
+            Self::Object(_) => false,
+        }
+    }
+
+    /// Samples a ray leaving the emitter for photon tracing, returns it with the power the emitter sends along it
+    /// divided by the probability density of sampling it. Point and spot lights are treated as attenuated with
+    /// the inverse square of distance. Directional and environment lights do not emit photons.
+    pub fn sample_photon(&self, u_position: Vec2, u_direction: Vec2) -> Option<(Ray, Color)> {
+        match self {
+            Self::Light(Light::Point(light)) => Some((
+                Ray::new(light.position, uniform_sample_sphere(u_direction)),
+                light.intensity / uniform_sphere_pdf(),
+            )),
+            Self::Light(Light::Spot(light)) => {
+                let cos_outer = light.outer_cone_angle.cos();
+                let direction = TangentFrame::from_normal(light.direction)
+                    .local_to_world(uniform_sample_cone(u_direction, cos_outer));
+                let falloff = light.falloff(light.position + direction);
+                Some((
+                    Ray::new(light.position, direction),
+                    light.intensity * (falloff / uniform_cone_pdf(cos_outer)),
+                ))
+            }
+            Self::Light(area @ Light::Area(light)) => Some(surface_photon(
+                light.point_at(u_position),
+                light.normal(),
+                light.area(),
+                area.intensity(),
+                u_direction,
+            )),
+            Self::Light(_) => None,
+            Self::Object(object) => {
+                let (point, area_pdf) = object.sample_point(u_position);
+                Some(surface_photon(
+                    point,
+                    object.normal_at(point),
+                    1.0 / area_pdf,
+                    object.material().emissive,
+                    u_direction,
+                ))
+            }
+        }
+    }
+
+    /// Probability density of `sample_photon` starting at the point, with respect to area. Point and spot lights
+    /// have a single position, their density is 1.0.
+    pub fn photon_position_pdf(&self, point: Vec3A) -> f32 {
+        match self {
+            Self::Light(Light::Area(light)) => 1.0 / light.area(),
+            Self::Light(Light::Point(_) | Light::Spot(_)) => 1.0,
+            Self::Light(_) => 0.0,
+            Self::Object(object) => object.area_pdf(point),
+        }
+    }
+
+    /// Probability density of `sample_photon` leaving the point in the direction, with respect to solid angle.
+    pub fn photon_direction_pdf(&self, point: Vec3A, direction: Vec3A) -> f32 {
+        match self {
+            Self::Light(Light::Point(_)) => uniform_sphere_pdf(),
+            Self::Light(Light::Spot(light)) => {
+                let cos_outer = light.outer_cone_angle.cos();
+                if direction.dot(light.direction.normalize()) >= cos_outer {
+                    uniform_cone_pdf(cos_outer)
+                } else {
+                    0.0
+                }
+            }
+            // Either side is chosen with the same probability
+            Self::Light(Light::Area(light)) => {
+                0.5 * cosine_hemisphere_pdf(direction.dot(light.normal()).abs())
+            }
+            Self::Light(_) => 0.0,
+            Self::Object(object) => {
+                0.5 * cosine_hemisphere_pdf(direction.dot(object.normal_at(point)).abs())
+            }
+        }
+    }
+
+    /// Emitted power used to choose between emitters, `None` for lights that light the whole world.
+    pub fn power(&self) -> Option<f32> {
+        match self {
+            Self::Light(light) => light.power(),
+            Self::Object(object) => {
+                Some(2.0 * PI * object.area() * object.material().emissive.luminance())
+            }
+        }
+    }
+
+    /// Samples radiance arriving at the point. Distance to a point on an emissive object is shortened
+    /// slightly, so the object itself does not block the shadow ray towards it.
+    pub fn sample_li(&self, shaded_point: Vec3A, u: Vec2) -> Option<LightLiSample> {
+        let object = match self {
+            Self::Light(light) => return light.sample_li(shaded_point, u),
+            Self::Object(object) => object,
+        };
+
+        let (point, area_pdf) = object.sample_point(u);
+        let point_to_emitter = point - shaded_point;
+        let distance = point_to_emitter.length();
+        if distance <= EPSILON {
+            return None;
+        }
+        let direction = point_to_emitter / distance;
+        let pdf = solid_angle_pdf(*object, point, direction, distance, area_pdf);
+        (pdf > 0.0).then(|| LightLiSample {
+            direction,
+            distance: distance - EPSILON,
+            radiance: object.material().emissive,
+            pdf,
+        })
+    }
+
+    /// Probability density of `sample_li` choosing the direction from the point, with respect to solid angle.
+    /// Only the closest point of an emissive object is visible in the direction, so the density is of that point.
+    pub fn pdf_li(&self, shaded_point: Vec3A, direction: Vec3A) -> f32 {
+        let object = match self {
+            Self::Light(light) => return light.pdf_li(shaded_point, direction),
+            Self::Object(object) => object,
+        };
+
+        let ray = Ray::new(shaded_point, direction);
+        match object.intersect(ray).hit() {
+            Some(hit) => {
+                let point = ray.position(hit.t);
+                solid_angle_pdf(*object, point, direction, hit.t, object.area_pdf(point))
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// Ray leaving a two-sided surface emitter at the point, cosine-distributed around the normal of the side chosen
+/// by `u.x`, with the power it carries.
+fn surface_photon(
+    point: Vec3A,
+    normal: Vec3A,
+    area: f32,
+    radiance: Color,
+    u: Vec2,
+) -> (Ray, Color) {
+    let (normal, u) = if u.x < 0.5 {
+        (normal, Vec2::new(u.x * 2.0, u.y))
+    } else {
+        (-normal, Vec2::new(u.x * 2.0 - 1.0, u.y))
+    };
+    let direction = TangentFrame::from_normal(normal).local_to_world(cosine_sample_hemisphere(u));
+    // Each side emits π times its radiance per unit area
+    let power = radiance * (2.0 * PI * area);
+    (Ray::new(point + normal * EPSILON, direction), power)
+}
+
+/// Converts the density with respect to area on the object to solid angle as seen from the shaded point.
+fn solid_angle_pdf(
+    object: &dyn Shape,
+    point: Vec3A,
+    direction: Vec3A,
+    distance: f32,
+    area_pdf: f32,
+) -> f32 {
+    let cos_theta = object.normal_at(point).dot(direction).abs();
+    if cos_theta == 0.0 {
+        return 0.0;
+    }
+    area_pdf * distance * distance / cos_theta
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use glam::{Affine3A, Vec3};
+    use rstest::*;
+
+    use crate::{material::Material, quad::Quad, sphere::Sphere};
+
+    use super::*;
+
+    fn emissive_sphere() -> Sphere {
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_transform(
+            Affine3A::from_translation(Vec3::new(0.0, 4.0, 0.0))
+                * Affine3A::from_scale(Vec3::splat(0.5)),
+        );
+        sphere.set_material(Material {
+            emissive: Color::new_red(),
+            ..Default::default()
+        });
+        sphere
+    }
+
+    #[test]
+    fn sampled_points_on_objects_have_consistent_pdfs() {
+        let sphere = emissive_sphere();
+        let emitter = Emitter::Object(&sphere);
+        assert!(!emitter.is_delta());
+        let steps = 8;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u = Vec2::new(
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                let sample = emitter
+                    .sample_li(Vec3A::ZERO, u)
+                    .expect("should sample the sphere");
+                assert_eq!(sample.radiance, Color::new_red());
+                // Points on the far side are hidden by the near side, which is what the pdf describes
+                let point = sample.direction * (sample.distance + EPSILON);
+                if sphere.normal_at(point).dot(sample.direction) < 0.0 {
+                    assert_abs_diff_eq!(
+                        sample.pdf,
+                        emitter.pdf_li(Vec3A::ZERO, sample.direction),
+                        epsilon = sample.pdf * 1e-2
+                    );
+                }
+            }
+        }
+        assert_abs_diff_eq!(emitter.pdf_li(Vec3A::ZERO, Vec3A::X), 0.0);
+    }
+
+    #[rstest]
+    #[case::facing(Vec3A::new(0.0, -1.0, 0.0), 1.0)]
+    #[case::at_an_angle(Vec3A::new(0.0, -1.0, 1.0).normalize(), 2.0_f32.sqrt() * 2.0)]
+    fn quad_pdf_converts_area_to_solid_angle(#[case] to_point: Vec3A, #[case] distance: f32) {
+        // 2 x 2 panel facing down at the height of 1, the direction points to its center
+        let mut panel = Quad::new(
+            Vec3A::new(-1.0, 1.0, -1.0),
+            Vec3A::new(0.0, 0.0, 2.0),
+            Vec3A::new(2.0, 0.0, 0.0),
+        );
+        panel.set_material(Material {
+            emissive: Color::new_white(),
+            ..Default::default()
+        });
+        let emitter = Emitter::Object(&panel);
+        let shaded_point = -to_point * distance + Vec3A::new(0.0, 1.0, 0.0);
+        let cos_theta = to_point.y.abs();
+        assert_abs_diff_eq!(
+            emitter.pdf_li(shaded_point, to_point),
+            distance * distance / (4.0 * cos_theta),
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn photon_power_is_emitted_radiance_over_the_photon_densities() {
+        let sphere = emissive_sphere();
+        let emitter = Emitter::Object(&sphere);
+        for u in [Vec2::new(0.2, 0.3), Vec2::new(0.7, 0.9)] {
+            let (ray, power) = emitter
+                .sample_photon(u, Vec2::new(u.y, 0.4))
+                .expect("should sample a photon");
+            let normal = sphere.normal_at(ray.origin_point);
+            let cos_theta = normal.dot(ray.direction_vector).abs();
+            let pdf = emitter.photon_position_pdf(ray.origin_point)
+                * emitter.photon_direction_pdf(ray.origin_point, ray.direction_vector);
+            assert_abs_diff_eq!(
+                power.get_red_val(),
+                cos_theta / pdf,
+                epsilon = power.get_red_val() * 1e-3
+            );
+        }
+    }
+}
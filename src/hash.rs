@@ -0,0 +1,31 @@
+/// Mixes the values into the seed (SplitMix64 finalizer after each value), so similar inputs give unrelated
+/// hashes. Used for seeding random number generators deterministically, e.g. per pixel sample.
+pub fn hash_values(seed: u64, values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(seed, |hash, value| mix_bits(hash ^ value))
+}
+
+pub fn mix_bits(value: u64) -> u64 {
+    let mut hash = value.wrapping_add(0x9E3779B97F4A7C15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D049BB133111EB);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashing_is_deterministic() {
+        assert_eq!(hash_values(7, &[1, 2, 3]), hash_values(7, &[1, 2, 3]));
+    }
+
+    #[test]
+    fn similar_inputs_give_different_hashes() {
+        assert_ne!(hash_values(7, &[1, 2, 3]), hash_values(7, &[1, 2, 4]));
+        assert_ne!(hash_values(7, &[1, 2, 3]), hash_values(8, &[1, 2, 3]));
+        assert_ne!(hash_values(0, &[1, 2]), hash_values(0, &[2, 1]));
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt::Debug;
+
+use crate::{
+    bump_map::TangentFrame,
+    color::Color,
+    ray::Ray,
+    sampler::Sampler,
+    sampling::cosine_sample_hemisphere,
+    world::{World, MAX_REFLECTION_DEPTH},
+};
+
+/// Computes the radiance (color) arriving at the camera along a camera ray. The camera's render loop
+/// works with any integrator, so rendering techniques can be swapped without touching it.
+pub trait Integrator: Debug + Send + Sync {
+    fn radiance(&self, world: &World, ray: Ray, sampler: &mut dyn Sampler) -> Color;
+}
+
+/// Whitted-style ray tracing from the book: Phong shading, shadow rays and recursive mirror reflections.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct WhittedIntegrator {
+    pub max_depth: u8,
+}
+
+impl Default for WhittedIntegrator {
+    fn default() -> Self {
+        Self {
+            max_depth: MAX_REFLECTION_DEPTH,
+        }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(&self, world: &World, ray: Ray, sampler: &mut dyn Sampler) -> Color {
+        world.color_at(ray, self.max_depth, sampler)
+    }
+}
+
+/// Ambient occlusion, the fraction of the hemisphere above the primary hit that is not blocked by nearby
+/// geometry, as grayscale. Ignores materials and lights, which makes it a quick clay preview of the geometry.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct AmbientOcclusionIntegrator {
+    /// Number of hemisphere rays per camera ray
+    pub samples: u32,
+    /// Geometry further away than this does not occlude
+    pub max_distance: f32,
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new(samples: u32, max_distance: f32) -> Self {
+        if samples == 0 {
+            panic!("Ambient occlusion needs at least one sample");
+        }
+
+        Self {
+            samples,
+            max_distance,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(&self, world: &World, ray: Ray, sampler: &mut dyn Sampler) -> Color {
+        let comps = match world.hit_computations(ray) {
+            Some(comps) => comps,
+            None => return Color::new_white(),
+        };
+
+        // Cosine-weighted directions, so the unoccluded fraction is the cosine-weighted visibility
+        let frame = TangentFrame::from_normal(comps.normal_vector);
+        let visible = (0..self.samples)
+            .filter(|_| {
+                let direction = frame.local_to_world(cosine_sample_hemisphere(sampler.next_2d()));
+                direction.dot(comps.geometric_normal_vector) > 0.0
+                    && !world.is_occluded(ray.spawn(comps.over_point, direction), self.max_distance)
+            })
+            .count();
+        let value = visible as f32 / self.samples as f32;
+        Color::new_color(value, value, value)
+    }
+}
+
+/// Quantity shown by the debug integrator.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DebugView {
+    /// Shading normal, with components remapped from -1.0 - 1.0 to 0.0 - 1.0
+    Normals,
+    /// Distance to the hit as grayscale, white at the camera and black at `max_distance` or further
+    Depth { max_distance: f32 },
+    /// Texture coordinates in the red and green channels
+    Uv,
+    /// Unlit material color, rays that miss show the background
+    Albedo,
+}
+
+/// Shows a property of the first hit instead of lighting, useful for checking geometry and textures.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DebugIntegrator {
+    pub view: DebugView,
+}
+
+impl DebugIntegrator {
+    pub fn new(view: DebugView) -> Self {
+        Self { view }
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn radiance(&self, world: &World, ray: Ray, _sampler: &mut dyn Sampler) -> Color {
+        let comps = match world.hit_computations(ray) {
+            Some(comps) => comps,
+            None if self.view == DebugView::Albedo => {
+                return world.background().color_for_direction(ray.direction_vector)
+            }
+            None => return Color::new_black(),
+        };
+
+        match self.view {
+            DebugView::Normals => {
+                let normal = comps.normal_vector * 0.5 + 0.5;
+                Color::new_color(normal.x, normal.y, normal.z)
+            }
+            DebugView::Depth { max_distance } => {
+                let value = (1.0 - comps.t / max_distance).clamp(0.0, 1.0);
+                Color::new_color(value, value, value)
+            }
+            DebugView::Uv => Color::new_color(comps.uv.x, comps.uv.y, 0.0),
+            DebugView::Albedo => {
+                world
+                    .object_by_id(&comps.object_id)
+                    .expect("should find the object that was hit in the world")
+                    .material()
+                    .color
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Affine3A, Vec3, Vec3A};
+    use rstest::*;
+
+    use crate::{
+        background::Background,
+        material::Material,
+        sampler::IndependentSampler,
+        sphere::Sphere,
+        test_helpers::{assert_color_eq, floor_world},
+    };
+
+    use super::*;
+
+    #[fixture]
+    pub fn world() -> World {
+        World::new_default()
+    }
+
+    #[fixture]
+    pub fn sampler() -> IndependentSampler {
+        IndependentSampler::new(1, 0)
+    }
+
+    #[fixture]
+    pub fn ray() -> Ray {
+        Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0))
+    }
+
+    #[rstest]
+    fn whitted_integrator_shades_with_the_world(
+        world: World,
+        mut sampler: IndependentSampler,
+        ray: Ray,
+    ) {
+        let color = WhittedIntegrator::default().radiance(&world, ray, &mut sampler);
+        assert_eq!(
+            color,
+            world.color_at(ray, MAX_REFLECTION_DEPTH, &mut sampler)
+        );
+    }
+
+    #[rstest]
+    #[case::normals(DebugView::Normals, Color::new_color(0.5, 0.5, 0.0))]
+    #[case::depth(DebugView::Depth { max_distance: 8.0 }, Color::new_color(0.5, 0.5, 0.5))]
+    #[case::uv(DebugView::Uv, Color::new_color(0.0, 0.5, 0.0))]
+    #[case::albedo(DebugView::Albedo, Color::new_color(0.8, 1.0, 0.6))]
+    fn debug_views_show_properties_of_the_hit(
+        world: World,
+        mut sampler: IndependentSampler,
+        ray: Ray,
+        #[case] view: DebugView,
+        #[case] expected_color: Color,
+    ) {
+        let color = DebugIntegrator::new(view).radiance(&world, ray, &mut sampler);
+        assert_color_eq(color, expected_color);
+    }
+
+    #[rstest]
+    fn debug_views_of_a_miss(mut world: World, mut sampler: IndependentSampler) {
+        world.set_background(Background::SolidColor(Color::new_blue()));
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            DebugIntegrator::new(DebugView::Normals).radiance(&world, ray, &mut sampler),
+            Color::new_black()
+        );
+        assert_eq!(
+            DebugIntegrator::new(DebugView::Albedo).radiance(&world, ray, &mut sampler),
+            Color::new_blue()
+        );
+    }
+
+    #[rstest]
+    #[case::open_floor(Vec3A::new(10.0, 0.0, 0.0), 10.0, 1.0, 1.0)]
+    #[case::next_to_the_sphere(Vec3A::new(1.1, 0.0, 0.0), 10.0, 0.5, 0.9)]
+    #[case::occluder_beyond_max_distance(Vec3A::new(1.1, 0.0, 0.0), 0.01, 1.0, 1.0)]
+    fn ambient_occlusion_darkens_creases(
+        #[from(floor_world)] mut world: World,
+        #[case] floor_point: Vec3A,
+        #[case] max_distance: f32,
+        #[case] min_expected: f32,
+        #[case] max_expected: f32,
+    ) {
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_transform(Affine3A::from_translation(Vec3::new(0.0, 1.0, 0.0)));
+        sphere.set_material(Material {
+            color: Color::new_red(),
+            ..Default::default()
+        });
+        world.add_object(sphere);
+        let eye = floor_point + Vec3A::new(0.0, 5.0, -0.01);
+        let ray = Ray::new(eye, (floor_point - eye).normalize());
+        let mut sampler = IndependentSampler::new(1, 0);
+        let color =
+            AmbientOcclusionIntegrator::new(256, max_distance).radiance(&world, ray, &mut sampler);
+        assert!(color.get_red_val() >= min_expected && color.get_red_val() <= max_expected);
+        // Grayscale, the red sphere does not tint it
+        assert_eq!(color.get_red_val(), color.get_blue_val());
+    }
+
+    #[rstest]
+    fn ambient_occlusion_of_a_miss_is_white(world: World, mut sampler: IndependentSampler) {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            AmbientOcclusionIntegrator::new(4, 1.0).radiance(&world, ray, &mut sampler),
+            Color::new_white()
+        );
+    }
+}
@@ -1,20 +1,91 @@
+use glam::{Vec2, Vec3A};
 use rayon::{
     prelude::{IntoParallelRefIterator, ParallelExtend, ParallelIterator},
     slice::ParallelSliceMut,
 };
 
+use crate::{
+    ray::{reflect, Ray},
+    shape::Shape,
+};
+
+/// Offset used to move points slightly above the surface, prevents self-intersection (acne).
+pub const EPSILON: f32 = 0.0001;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct SingleIntersection<'a> {
     pub t: f32,
     pub object_id: &'a String,
+    /// Surface parameters of the intersection, e.g. barycentric coordinates for triangles
+    pub u: f32,
+    pub v: f32,
+    /// Time of the ray that found the intersection
+    pub time: f32,
 }
 
 impl<'a> SingleIntersection<'a> {
     pub fn new(t: f32, object_id: &'a String) -> Self {
-        Self { t, object_id }
+        Self::new_with_uv(t, object_id, 0.0, 0.0)
+    }
+
+    pub fn new_with_uv(t: f32, object_id: &'a String, u: f32, v: f32) -> Self {
+        Self {
+            t,
+            object_id,
+            u,
+            v,
+            time: 0.0,
+        }
+    }
+
+    /// Precomputes values used for shading the intersection. The object must be the one the intersection belongs to.
+    pub fn prepare_computations(&self, ray: &Ray, object: &dyn Shape) -> Computations {
+        let point = ray.position(self.t);
+        let eye_vector = -ray.direction_vector;
+        let mut geometric_normal_vector = object.normal_at_time(point, self.time);
+        // Shading normal can differ from the geometric one, e.g. with bump maps or smooth triangles
+        let mut normal_vector = object.tangent_frame_at(point, self).normal;
+        let inside = geometric_normal_vector.dot(eye_vector) < 0.0;
+        if inside {
+            geometric_normal_vector = -geometric_normal_vector;
+            normal_vector = -normal_vector;
+        }
+
+        Computations {
+            t: self.t,
+            object_id: self.object_id.clone(),
+            point,
+            over_point: point + geometric_normal_vector * EPSILON,
+            under_point: point - geometric_normal_vector * EPSILON,
+            eye_vector,
+            normal_vector,
+            geometric_normal_vector,
+            reflect_vector: reflect(ray.direction_vector, normal_vector),
+            uv: object.uv_at(point, self),
+            inside,
+            time: ray.time,
+        }
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Computations {
+    pub t: f32,
+    pub object_id: String,
+    pub point: Vec3A,
+    pub over_point: Vec3A,
+    /// Point just below the surface, where refracted rays start
+    pub under_point: Vec3A,
+    pub eye_vector: Vec3A,
+    pub normal_vector: Vec3A,
+    pub geometric_normal_vector: Vec3A,
+    pub reflect_vector: Vec3A,
+    pub uv: Vec2,
+    pub inside: bool,
+    /// Time of the ray, rays leaving the point travel at the same time
+    pub time: f32,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Intersections<'a> {
     pub i: Vec<SingleIntersection<'a>>,
@@ -34,7 +105,7 @@ impl<'a> Intersections<'a> {
     }
 
     /// Hit returns lowest non-negative intersection. Assumes that intersections are sorted.
-    pub fn hit(&self) -> Option<&SingleIntersection> {
+    pub fn hit(&self) -> Option<&SingleIntersection<'a>> {
         self.i.par_iter().find_first(|i| i.t >= 0.0)
     }
 
@@ -48,7 +119,14 @@ mod tests {
     use approx::assert_abs_diff_eq;
     use glam::Vec3A;
 
-    use crate::{ray::Ray, sphere::Sphere};
+    use glam::{Affine3A, Vec3};
+
+    use crate::{
+        bump_map::{BumpMap, HeightPattern},
+        material::Material,
+        ray::Ray,
+        sphere::Sphere,
+    };
 
     use super::*;
 
@@ -133,4 +211,97 @@ mod tests {
         let hit = intersections.hit();
         assert_eq!(*hit.unwrap(), i4);
     }
+
+    #[test]
+    fn precomputing_the_state_of_an_intersection() {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        let intersection = SingleIntersection::new(4.0, &sphere.id);
+        let comps = intersection.prepare_computations(&ray, &sphere);
+        assert_abs_diff_eq!(comps.t, intersection.t);
+        assert_eq!(&comps.object_id, intersection.object_id);
+        assert!(comps.point.abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+        assert!(comps
+            .eye_vector
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+        assert!(comps
+            .normal_vector
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+        assert!(!comps.inside);
+    }
+
+    #[test]
+    fn the_hit_when_an_intersection_occurs_on_the_inside() {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, 0.0), Vec3A::new(0.0, 0.0, 1.0));
+        let sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        let intersection = SingleIntersection::new(1.0, &sphere.id);
+        let comps = intersection.prepare_computations(&ray, &sphere);
+        assert!(comps.point.abs_diff_eq(Vec3A::new(0.0, 0.0, 1.0), 1e-6));
+        assert!(comps
+            .eye_vector
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+        // Normal is inverted, so it points towards the eye
+        assert!(comps
+            .normal_vector
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+        assert!(comps.inside);
+    }
+
+    #[test]
+    fn the_hit_should_offset_the_point() {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let mut sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        sphere.set_transform(Affine3A::from_translation(Vec3::new(0.0, 0.0, 1.0)));
+        let intersection = SingleIntersection::new(5.0, &sphere.id);
+        let comps = intersection.prepare_computations(&ray, &sphere);
+        assert!(comps.over_point.z < -EPSILON / 2.0);
+        assert!(comps.point.z > comps.over_point.z);
+    }
+
+    #[test]
+    fn the_under_point_is_offset_below_the_surface() {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let mut sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        sphere.set_transform(Affine3A::from_translation(Vec3::new(0.0, 0.0, 1.0)));
+        let intersection = SingleIntersection::new(5.0, &sphere.id);
+        let comps = intersection.prepare_computations(&ray, &sphere);
+        assert!(comps.under_point.z > EPSILON / 2.0);
+        assert!(comps.point.z < comps.under_point.z);
+    }
+
+    #[test]
+    fn bump_map_changes_only_the_shading_normal() {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let mut sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        sphere.set_material(Material {
+            bump_map: Some(BumpMap::HeightPattern {
+                pattern: HeightPattern::new(|p| p.x),
+                scale: 1.0,
+            }),
+            ..Default::default()
+        });
+        let intersection = SingleIntersection::new(4.0, &sphere.id);
+        let comps = intersection.prepare_computations(&ray, &sphere);
+        assert!(comps
+            .geometric_normal_vector
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+        assert!(comps
+            .normal_vector
+            .abs_diff_eq(Vec3A::new(-1.0, 0.0, -1.0).normalize(), 1e-3));
+        assert!(comps
+            .over_point
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0 - EPSILON), 1e-6));
+    }
+
+    #[test]
+    fn precomputing_the_reflection_vector() {
+        let ray = Ray::new(Vec3A::new(0.0, 0.5, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        let intersections = sphere.intersect(ray);
+        let hit = intersections.hit().unwrap();
+        let comps = hit.prepare_computations(&ray, &sphere);
+        assert!(comps
+            .reflect_vector
+            .abs_diff_eq(Vec3A::new(0.0, 3_f32.sqrt() / 2.0, -0.5), 1e-5));
+    }
 }
@@ -1,5 +1,37 @@
+pub mod antialiasing;
+pub mod background;
+pub mod bidirectional_path_tracer;
+pub mod bsdf;
+pub mod bump_map;
+pub mod camera;
 pub mod canvas;
 pub mod color;
+pub mod density;
+pub mod distribution;
+pub mod emitter;
+pub mod hash;
+pub mod integrator;
 pub mod intersection;
+pub mod light;
+pub mod light_sampler;
+pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod motion;
+pub mod path_tracer;
+pub mod photon_map;
+pub mod principled;
+pub mod projection;
+pub mod quad;
 pub mod ray;
+pub mod sampler;
+pub mod sampling;
+pub mod shape;
+pub mod sky;
 pub mod sphere;
+pub mod subsurface;
+#[cfg(test)]
+pub mod test_helpers;
+pub mod texture;
+pub mod triangle;
+pub mod world;
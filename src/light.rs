@@ -0,0 +1,1034 @@
+use glam::{Vec2, Vec3A};
+use image::ImageError;
+
+use std::f32::consts::PI;
+
+use crate::{
+    background::{direction_to_equirectangular_uv, equirectangular_uv_to_direction, Background},
+    bump_map::TangentFrame,
+    color::Color,
+    distribution::Distribution2D,
+    ray::Ray,
+    sampler::Sampler,
+    sampling::{uniform_cone_pdf, uniform_sample_cone, uniform_sample_sphere, uniform_sphere_pdf},
+    sky::{sun_solid_angle, Sky},
+    texture::ImageTexture,
+};
+
+/// Light arriving at a shaded point from a single point on (or direction of) a light.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct LightSample {
+    /// Unit vector from the shaded point towards the light
+    pub direction: Vec3A,
+    /// Distance to the sampled point on the light, infinite for directional lights
+    pub distance: f32,
+    /// Intensity arriving at the shaded point, after attenuation and spot light falloff
+    pub intensity: Color,
+}
+
+/// Radiance arriving at a shaded point from a light, sampled for Monte Carlo integration of direct lighting.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct LightLiSample {
+    /// Unit vector from the shaded point towards the light
+    pub direction: Vec3A,
+    /// Distance to the sampled point on the light, infinite for lights at infinity
+    pub distance: f32,
+    /// Radiance emitted towards the shaded point, irradiance at normal incidence for delta lights
+    pub radiance: Color,
+    /// Probability density of the direction with respect to solid angle, 1.0 for delta lights
+    pub pdf: f32,
+}
+
+/// Intensity of point, spot and directional lights is the irradiance delivered at normal incidence, so for path
+/// tracing a white light on a white surface facing it gives radiance of 1/π. Area lights are two-sided emitters
+/// with radiance equal to their intensity.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Light {
+    Point(PointLight),
+    Area(AreaLight),
+    Spot(SpotLight),
+    Directional(DirectionalLight),
+    Environment(Box<EnvironmentLight>),
+}
+
+impl Light {
+    /// Intensity of the light at its source, before any attenuation.
+    pub fn intensity(&self) -> Color {
+        match self {
+            Self::Point(light) => light.intensity,
+            Self::Area(light) => light.intensity,
+            Self::Spot(light) => light.intensity,
+            Self::Directional(light) => light.intensity,
+            Self::Environment(_) => Color::new_black(),
+        }
+    }
+
+    /// Delta lights (point, spot and directional) arrive from a single direction, so they can only be
+    /// sampled, rays sampled from a BSDF never hit them.
+    pub fn is_delta(&self) -> bool {
+        matches!(self, Self::Point(_) | Self::Spot(_) | Self::Directional(_))
+    }
+
+    /// Total emitted power, up to a constant factor shared by all lights and emissive objects. Directional
+    /// and environment lights light the whole world, their power is `None`.
+    pub fn power(&self) -> Option<f32> {
+        let luminance = self.intensity().luminance();
+        match self {
+            Self::Point(_) => Some(4.0 * PI * luminance),
+            Self::Spot(light) => {
+                let cone_angle = (light.inner_cone_angle + light.outer_cone_angle) / 2.0;
+                Some(2.0 * PI * (1.0 - cone_angle.cos()) * luminance)
+            }
+            // Emits from both sides
+            Self::Area(light) => Some(2.0 * PI * light.area() * luminance),
+            Self::Directional(_) | Self::Environment(_) => None,
+        }
+    }
+
+    /// Samples of the light used for shading the given point. Area lights draw the positions within
+    /// their cells from the sampler, other lights do not consume any sample dimensions. Environment lights
+    /// have no samples, they only light path traced scenes.
+    pub fn samples(&self, shaded_point: Vec3A, sampler: &mut dyn Sampler) -> Vec<LightSample> {
+        match self {
+            Self::Point(light) => vec![sample_towards(
+                light.position,
+                shaded_point,
+                light.intensity,
+                light.inverse_square_attenuation,
+            )],
+            Self::Area(light) => light
+                .sample_points(sampler)
+                .into_iter()
+                .map(|point| {
+                    sample_towards(
+                        point,
+                        shaded_point,
+                        light.intensity,
+                        light.inverse_square_attenuation,
+                    )
+                })
+                .collect(),
+            Self::Spot(light) => vec![light.sample(shaded_point)],
+            Self::Directional(light) => vec![LightSample {
+                direction: -light.direction.normalize(),
+                distance: f32::INFINITY,
+                intensity: light.intensity,
+            }],
+            Self::Environment(_) => vec![],
+        }
+    }
+
+    /// Samples radiance arriving at the point with the 2D sample. Area lights are sampled uniformly by
+    /// area as a whole, without their cells.
+    pub fn sample_li(&self, shaded_point: Vec3A, u: Vec2) -> Option<LightLiSample> {
+        let delta = |sample: LightSample| LightLiSample {
+            direction: sample.direction,
+            distance: sample.distance,
+            radiance: sample.intensity,
+            pdf: 1.0,
+        };
+        match self {
+            Self::Point(light) => Some(delta(sample_towards(
+                light.position,
+                shaded_point,
+                light.intensity,
+                light.inverse_square_attenuation,
+            ))),
+            Self::Spot(light) => Some(delta(light.sample(shaded_point))),
+            Self::Directional(light) => Some(LightLiSample {
+                direction: -light.direction.normalize(),
+                distance: f32::INFINITY,
+                radiance: light.intensity,
+                pdf: 1.0,
+            }),
+            Self::Area(light) => light.sample_li(shaded_point, u),
+            Self::Environment(light) => light.sample_li(u),
+        }
+    }
+
+    /// Probability density of `sample_li` choosing the direction from the point, with respect to solid angle.
+    /// It is 0.0 for delta lights and for directions that miss the light.
+    pub fn pdf_li(&self, shaded_point: Vec3A, direction: Vec3A) -> f32 {
+        match self {
+            Self::Point(_) | Self::Spot(_) | Self::Directional(_) => 0.0,
+            Self::Area(light) => light.pdf_li(shaded_point, direction),
+            Self::Environment(light) => light.pdf_li(direction),
+        }
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Self::Point(light)
+    }
+}
+
+impl From<AreaLight> for Light {
+    fn from(light: AreaLight) -> Self {
+        Self::Area(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Self::Spot(light)
+    }
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Self {
+        Self::Directional(light)
+    }
+}
+
+impl From<EnvironmentLight> for Light {
+    fn from(light: EnvironmentLight) -> Self {
+        Self::Environment(Box::new(light))
+    }
+}
+
+fn sample_towards(
+    light_point: Vec3A,
+    shaded_point: Vec3A,
+    intensity: Color,
+    inverse_square_attenuation: bool,
+) -> LightSample {
+    let point_to_light = light_point - shaded_point;
+    let distance = point_to_light.length();
+    let intensity = if inverse_square_attenuation {
+        intensity / distance.powi(2).max(f32::EPSILON)
+    } else {
+        intensity
+    };
+
+    LightSample {
+        direction: point_to_light / distance,
+        distance,
+        intensity,
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct PointLight {
+    pub position: Vec3A,
+    pub intensity: Color,
+    inverse_square_attenuation: bool,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3A, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+            inverse_square_attenuation: false,
+        }
+    }
+
+    /// Attenuates the intensity with the squared distance to the light.
+    pub fn set_inverse_square_attenuation(&mut self, inverse_square_attenuation: bool) {
+        self.inverse_square_attenuation = inverse_square_attenuation
+    }
+
+    pub fn inverse_square_attenuation(&self) -> bool {
+        self.inverse_square_attenuation
+    }
+}
+
+/// Point light that only shines within a cone. Intensity falls off smoothly between the inner and
+/// the outer cone angles, both measured from the cone axis, in radians.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct SpotLight {
+    pub position: Vec3A,
+    pub direction: Vec3A,
+    pub inner_cone_angle: f32,
+    pub outer_cone_angle: f32,
+    pub intensity: Color,
+    inverse_square_attenuation: bool,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Vec3A,
+        direction: Vec3A,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+        intensity: Color,
+    ) -> Self {
+        if inner_cone_angle > outer_cone_angle {
+            panic!(
+                "Spot light inner cone angle cannot be greater than the outer one, inner: {}, outer: {}",
+                inner_cone_angle, outer_cone_angle
+            );
+        }
+
+        Self {
+            position,
+            direction: direction.normalize(),
+            inner_cone_angle,
+            outer_cone_angle,
+            intensity,
+            inverse_square_attenuation: false,
+        }
+    }
+
+    /// Attenuates the intensity with the squared distance to the light.
+    pub fn set_inverse_square_attenuation(&mut self, inverse_square_attenuation: bool) {
+        self.inverse_square_attenuation = inverse_square_attenuation
+    }
+
+    pub fn inverse_square_attenuation(&self) -> bool {
+        self.inverse_square_attenuation
+    }
+
+    /// Falloff factor for the shaded point, 1.0 inside the inner cone and 0.0 outside the outer cone.
+    pub fn falloff(&self, shaded_point: Vec3A) -> f32 {
+        let cos_theta = (shaded_point - self.position)
+            .normalize()
+            .dot(self.direction);
+        let cos_inner = self.inner_cone_angle.cos();
+        let cos_outer = self.outer_cone_angle.cos();
+        if cos_theta >= cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= cos_outer {
+            return 0.0;
+        }
+
+        // Smoothstep between the cones
+        let t = (cos_theta - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    fn sample(&self, shaded_point: Vec3A) -> LightSample {
+        let sample = sample_towards(
+            self.position,
+            shaded_point,
+            self.intensity,
+            self.inverse_square_attenuation,
+        );
+        LightSample {
+            intensity: sample.intensity * self.falloff(shaded_point),
+            ..sample
+        }
+    }
+}
+
+/// Light infinitely far away, all of its rays are parallel (e.g. the sun). It has no position,
+/// so it cannot be attenuated with distance.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DirectionalLight {
+    /// Direction in which the light travels
+    pub direction: Vec3A,
+    pub intensity: Color,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3A, intensity: Color) -> Self {
+        Self {
+            direction: direction.normalize(),
+            intensity,
+        }
+    }
+}
+
+/// Rectangular light divided into `usteps * vsteps` cells, each cell is sampled once per shaded point.
+/// Sampling the cells gives soft shadows with penumbrae instead of the hard edges of point lights.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AreaLight {
+    corner: Vec3A,
+    uvec: Vec3A,
+    usteps: u16,
+    vvec: Vec3A,
+    vsteps: u16,
+    position: Vec3A,
+    intensity: Color,
+    jitter: bool,
+    inverse_square_attenuation: bool,
+}
+
+impl AreaLight {
+    /// Creates jittered area light spanning from the corner along `full_uvec` and `full_vvec`.
+    pub fn new(
+        corner: Vec3A,
+        full_uvec: Vec3A,
+        usteps: u16,
+        full_vvec: Vec3A,
+        vsteps: u16,
+        intensity: Color,
+    ) -> Self {
+        if usteps == 0 || vsteps == 0 {
+            panic!(
+                "Area light needs at least one step in each direction, usteps: {}, vsteps: {}",
+                usteps, vsteps
+            );
+        }
+
+        Self {
+            corner,
+            uvec: full_uvec / usteps as f32,
+            usteps,
+            vvec: full_vvec / vsteps as f32,
+            vsteps,
+            position: corner + full_uvec / 2.0 + full_vvec / 2.0,
+            intensity,
+            jitter: true,
+            inverse_square_attenuation: false,
+        }
+    }
+
+    /// Attenuates each sample with the squared distance to its point on the light.
+    pub fn set_inverse_square_attenuation(&mut self, inverse_square_attenuation: bool) {
+        self.inverse_square_attenuation = inverse_square_attenuation
+    }
+
+    pub fn inverse_square_attenuation(&self) -> bool {
+        self.inverse_square_attenuation
+    }
+
+    /// Disabling jitter always samples the cell centers, without consuming sampler dimensions.
+    pub fn set_jitter(&mut self, jitter: bool) {
+        self.jitter = jitter
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.usteps as u32 * self.vsteps as u32
+    }
+
+    /// Point within the cell (u, v), `u_offset` and `v_offset` are in the 0.0 - 1.0 range.
+    pub fn point_on_light(&self, u: u16, v: u16, u_offset: f32, v_offset: f32) -> Vec3A {
+        self.corner + self.uvec * (u as f32 + u_offset) + self.vvec * (v as f32 + v_offset)
+    }
+
+    /// One point in each cell, jittered within the cell by a 2D sample.
+    pub fn sample_points(&self, sampler: &mut dyn Sampler) -> Vec<Vec3A> {
+        let mut points = Vec::with_capacity(self.samples() as usize);
+        for v in 0..self.vsteps {
+            for u in 0..self.usteps {
+                let offset = if self.jitter {
+                    sampler.next_2d()
+                } else {
+                    Vec2::splat(0.5)
+                };
+                points.push(self.point_on_light(u, v, offset.x, offset.y));
+            }
+        }
+        points
+    }
+
+    /// Point on the whole light, `u` spans it from the corner along both edges.
+    pub fn point_at(&self, u: Vec2) -> Vec3A {
+        self.corner + self.full_uvec() * u.x + self.full_vvec() * u.y
+    }
+
+    fn full_uvec(&self) -> Vec3A {
+        self.uvec * self.usteps as f32
+    }
+
+    fn full_vvec(&self) -> Vec3A {
+        self.vvec * self.vsteps as f32
+    }
+
+    pub fn area(&self) -> f32 {
+        self.full_uvec().cross(self.full_vvec()).length()
+    }
+
+    pub fn normal(&self) -> Vec3A {
+        self.full_uvec().cross(self.full_vvec()).normalize()
+    }
+
+    /// Distance along the ray to the light, if the ray hits it. Path tracing treats area lights as two-sided
+    /// emitters with radiance equal to their intensity, so rays can hit them from both sides.
+    pub fn intersect(&self, ray: Ray) -> Option<f32> {
+        let normal = self.normal();
+        let denominator = ray.direction_vector.dot(normal);
+        if denominator.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = (self.corner - ray.origin_point).dot(normal) / denominator;
+        if t <= 0.0 {
+            return None;
+        }
+
+        // Coordinates of the hit along the edges, the edges do not need to be perpendicular
+        let offset = ray.position(t) - self.corner;
+        let (full_uvec, full_vvec) = (self.full_uvec(), self.full_vvec());
+        let uu = full_uvec.dot(full_uvec);
+        let uv = full_uvec.dot(full_vvec);
+        let vv = full_vvec.dot(full_vvec);
+        let determinant = uu * vv - uv * uv;
+        let a = (offset.dot(full_uvec) * vv - offset.dot(full_vvec) * uv) / determinant;
+        let b = (offset.dot(full_vvec) * uu - offset.dot(full_uvec) * uv) / determinant;
+        ((0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)).then_some(t)
+    }
+
+    /// Converts the uniform density over the light's area to solid angle as seen from the shaded point.
+    fn solid_angle_pdf(&self, direction: Vec3A, distance: f32) -> f32 {
+        let cos_theta = direction.dot(self.normal()).abs();
+        if cos_theta == 0.0 {
+            return 0.0;
+        }
+        distance * distance / (self.area() * cos_theta)
+    }
+
+    fn sample_li(&self, shaded_point: Vec3A, u: Vec2) -> Option<LightLiSample> {
+        let point = self.point_at(u);
+        let point_to_light = point - shaded_point;
+        let distance = point_to_light.length();
+        if distance == 0.0 {
+            return None;
+        }
+        let direction = point_to_light / distance;
+        let pdf = self.solid_angle_pdf(direction, distance);
+        (pdf > 0.0).then_some(LightLiSample {
+            direction,
+            distance,
+            radiance: self.intensity,
+            pdf,
+        })
+    }
+
+    fn pdf_li(&self, shaded_point: Vec3A, direction: Vec3A) -> f32 {
+        match self.intersect(Ray::new(shaded_point, direction)) {
+            Some(distance) => self.solid_angle_pdf(direction, distance),
+            None => 0.0,
+        }
+    }
+}
+
+/// Light arriving from all directions, the background seen as an infinitely distant sphere around the world.
+/// Adding it to a world also makes its background the world's background. Equirectangular panoramas and skies
+/// are importance sampled by luminance, with the sun disk of a sky sampled separately. Other backgrounds are
+/// sampled uniformly over the sphere.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnvironmentLight {
+    background: Background,
+    distribution: Option<Distribution2D>,
+    /// Direction of the sun and the cosine of its angular radius
+    sun: Option<(Vec3A, f32)>,
+    /// Probability of sampling the sun disk instead of the distribution
+    sun_probability: f32,
+}
+
+impl EnvironmentLight {
+    pub fn new(background: Background) -> Self {
+        let (distribution, sun, sun_probability) = match &background {
+            Background::Equirectangular(texture) => {
+                (Some(luminance_distribution(texture)), None, 0.0)
+            }
+            Background::Sky(sky) => {
+                let distribution = sky_distribution(sky);
+                // The sun is sampled as often as its share of the power arriving from the whole sky
+                let sky_power = 2.0 * PI * PI * distribution.integral();
+                let sun_power = sky.sun_radiance().luminance() * sun_solid_angle();
+                let sun = (sky.sun_direction(), sky.sun_cos_angular_radius());
+                let sun_probability = if sun_power > 0.0 {
+                    sun_power / (sun_power + sky_power)
+                } else {
+                    0.0
+                };
+                (Some(distribution), Some(sun), sun_probability)
+            }
+            _ => (None, None, 0.0),
+        };
+        Self {
+            background,
+            distribution,
+            sun,
+            sun_probability,
+        }
+    }
+
+    /// Loads an equirectangular HDR panorama as image-based lighting.
+    pub fn new_equirectangular_from_file(image_file_path: &str) -> Result<Self, ImageError> {
+        Ok(Self::new(Background::new_equirectangular_from_file(
+            image_file_path,
+        )?))
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    fn sample_li(&self, u: Vec2) -> Option<LightLiSample> {
+        let direction = match self.sun {
+            Some((sun_direction, cos_max)) if u.x < self.sun_probability => {
+                let u = Vec2::new(u.x / self.sun_probability, u.y);
+                TangentFrame::from_normal(sun_direction)
+                    .local_to_world(uniform_sample_cone(u, cos_max))
+            }
+            _ => {
+                let u = Vec2::new(
+                    (u.x - self.sun_probability) / (1.0 - self.sun_probability),
+                    u.y,
+                );
+                match &self.distribution {
+                    None => uniform_sample_sphere(u),
+                    Some(distribution) => {
+                        let (point, _) = distribution.sample_continuous(u);
+                        equirectangular_uv_to_direction(point.x, 1.0 - point.y)
+                    }
+                }
+            }
+        };
+        let pdf = self.pdf_li(direction);
+        (pdf > 0.0).then(|| LightLiSample {
+            direction,
+            distance: f32::INFINITY,
+            radiance: self.background.color_for_direction(direction),
+            pdf,
+        })
+    }
+
+    fn pdf_li(&self, direction: Vec3A) -> f32 {
+        let direction = direction.normalize();
+        let background_pdf = match &self.distribution {
+            None => uniform_sphere_pdf(),
+            Some(distribution) => {
+                let (u, v) = direction_to_equirectangular_uv(direction);
+                let point_pdf = distribution.pdf(Vec2::new(u.rem_euclid(1.0), 1.0 - v));
+                panorama_pdf(point_pdf, direction)
+            }
+        };
+        let sun_pdf = match self.sun {
+            Some((sun_direction, cos_max)) if direction.dot(sun_direction) >= cos_max => {
+                uniform_cone_pdf(cos_max)
+            }
+            _ => 0.0,
+        };
+        self.sun_probability * sun_pdf + (1.0 - self.sun_probability) * background_pdf
+    }
+}
+
+/// Distribution over the panorama's texels, row 0 at the top. Each texel is weighted by the brightest
+/// bilinearly filtered radiance within it, so light bleeding from a bright neighbour is still sampled,
+/// and by the solid angle it covers, which shrinks towards the poles.
+fn luminance_distribution(texture: &ImageTexture) -> Distribution2D {
+    let (width, height) = (texture.width() as f32, texture.height() as f32);
+    let mut function = Vec::with_capacity((texture.width() * texture.height()) as usize);
+    for y in 0..texture.height() {
+        let sin_phi = ((y as f32 + 0.5) / height * PI).sin();
+        for x in 0..texture.width() {
+            // Filtered radiance is bilinear between the corners, edge midpoints and the center of a texel
+            let brightest = (0..9)
+                .map(|i| {
+                    let u = (x as f32 + (i % 3) as f32 * 0.5) / width;
+                    let v = 1.0 - (y as f32 + (i / 3) as f32 * 0.5) / height;
+                    texture.sample_bilinear(u, v).luminance()
+                })
+                .fold(0.0, f32::max);
+            function.push(brightest * sin_phi);
+        }
+    }
+    Distribution2D::new(
+        &function,
+        texture.width() as usize,
+        texture.height() as usize,
+    )
+}
+
+/// Distribution over a panorama of the sky without the sun, which is too small to be found on the grid.
+fn sky_distribution(sky: &Sky) -> Distribution2D {
+    let (width, height) = (256, 128);
+    let function: Vec<f32> = (0..width * height)
+        .map(|i| {
+            let u = ((i % width) as f32 + 0.5) / width as f32;
+            let y = ((i / width) as f32 + 0.5) / height as f32;
+            let direction = equirectangular_uv_to_direction(u, 1.0 - y);
+            sky.radiance_without_sun(direction).luminance() * (y * PI).sin()
+        })
+        .collect();
+    Distribution2D::new(&function, width, height)
+}
+
+/// Converts a density over the panorama's unit square to a density with respect to solid angle.
+fn panorama_pdf(point_pdf: f32, direction: Vec3A) -> f32 {
+    let sin_phi = (1.0 - direction.y * direction.y).max(0.0).sqrt();
+    if sin_phi == 0.0 {
+        return 0.0;
+    }
+    point_pdf / (2.0 * PI * PI * sin_phi)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use crate::{
+        sampler::IndependentSampler,
+        test_helpers::{strata, sunny_panorama},
+    };
+
+    use super::*;
+
+    #[fixture]
+    pub fn sampler() -> IndependentSampler {
+        IndependentSampler::new(1, 0)
+    }
+
+    #[fixture]
+    pub fn area_light() -> AreaLight {
+        AreaLight::new(
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(2.0, 0.0, 0.0),
+            4,
+            Vec3A::new(0.0, 0.0, 1.0),
+            2,
+            Color::new_white(),
+        )
+    }
+
+    #[test]
+    fn a_point_light_has_a_position_and_intensity() {
+        let intensity = Color::new_white();
+        let position = Vec3A::new(0.0, 0.0, 0.0);
+        let light = PointLight::new(position, intensity);
+        assert_eq!(light.position, position);
+        assert_eq!(light.intensity, intensity);
+    }
+
+    #[rstest]
+    fn creating_an_area_light(area_light: AreaLight) {
+        assert_eq!(area_light.corner, Vec3A::new(0.0, 0.0, 0.0));
+        assert_eq!(area_light.uvec, Vec3A::new(0.5, 0.0, 0.0));
+        assert_eq!(area_light.usteps, 4);
+        assert_eq!(area_light.vvec, Vec3A::new(0.0, 0.0, 0.5));
+        assert_eq!(area_light.vsteps, 2);
+        assert_eq!(area_light.samples(), 8);
+        assert_eq!(area_light.position, Vec3A::new(1.0, 0.0, 0.5));
+    }
+
+    #[rstest]
+    #[case(0, 0, Vec3A::new(0.25, 0.0, 0.25))]
+    #[case(1, 0, Vec3A::new(0.75, 0.0, 0.25))]
+    #[case(0, 1, Vec3A::new(0.25, 0.0, 0.75))]
+    #[case(2, 0, Vec3A::new(1.25, 0.0, 0.25))]
+    #[case(3, 1, Vec3A::new(1.75, 0.0, 0.75))]
+    fn finding_a_single_point_on_an_area_light(
+        area_light: AreaLight,
+        #[case] u: u16,
+        #[case] v: u16,
+        #[case] expected_point: Vec3A,
+    ) {
+        let point = area_light.point_on_light(u, v, 0.5, 0.5);
+        assert!(point.abs_diff_eq(expected_point, 1e-6));
+    }
+
+    #[rstest]
+    fn sample_points_without_jitter_are_cell_centers(
+        mut area_light: AreaLight,
+        mut sampler: IndependentSampler,
+    ) {
+        area_light.set_jitter(false);
+        let points = area_light.sample_points(&mut sampler);
+        assert_eq!(points.len(), 8);
+        assert!(points[0].abs_diff_eq(Vec3A::new(0.25, 0.0, 0.25), 1e-6));
+        assert!(points[7].abs_diff_eq(Vec3A::new(1.75, 0.0, 0.75), 1e-6));
+    }
+
+    #[rstest]
+    fn jittered_sample_points_stay_in_their_cells(
+        area_light: AreaLight,
+        mut sampler: IndependentSampler,
+    ) {
+        let points = area_light.sample_points(&mut sampler);
+        for (index, point) in points.iter().enumerate() {
+            let u = (index % 4) as f32;
+            let v = (index / 4) as f32;
+            assert!(point.x >= u * 0.5 && point.x <= (u + 1.0) * 0.5);
+            assert!(point.z >= v * 0.5 && point.z <= (v + 1.0) * 0.5);
+            assert_eq!(point.y, 0.0);
+        }
+    }
+
+    #[rstest]
+    fn jittered_sample_points_are_reproducible(
+        area_light: AreaLight,
+        mut sampler: IndependentSampler,
+    ) {
+        sampler.start_pixel_sample(1, 2, 0);
+        let points = area_light.sample_points(&mut sampler);
+        sampler.start_pixel_sample(1, 2, 0);
+        assert_eq!(points, area_light.sample_points(&mut sampler));
+        sampler.start_pixel_sample(1, 2, 1);
+        assert_ne!(points, area_light.sample_points(&mut sampler));
+    }
+
+    #[test]
+    fn point_light_is_sampled_at_its_position() {
+        let mut sampler = sampler();
+        let light = Light::from(PointLight::new(
+            Vec3A::new(0.0, 0.0, -10.0),
+            Color::new_white(),
+        ));
+        let samples = light.samples(Vec3A::new(0.0, 0.0, -2.0), &mut sampler);
+        assert_eq!(samples.len(), 1);
+        assert!(samples[0]
+            .direction
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+        assert_abs_diff_eq!(samples[0].distance, 8.0);
+        assert_eq!(samples[0].intensity, Color::new_white());
+        assert_eq!(light.intensity(), Color::new_white());
+    }
+
+    #[test]
+    fn inverse_square_attenuation_reduces_intensity_with_distance() {
+        let mut sampler = sampler();
+        let mut point_light = PointLight::new(Vec3A::new(0.0, 4.0, 0.0), Color::new_white());
+        point_light.set_inverse_square_attenuation(true);
+        let samples = Light::from(point_light).samples(Vec3A::ZERO, &mut sampler);
+        assert_abs_diff_eq!(samples[0].intensity.get_red_val(), 1.0 / 16.0);
+
+        let mut area_light = AreaLight::new(
+            Vec3A::new(0.0, 2.0, 0.0),
+            Vec3A::new(0.0, 0.0, 0.0),
+            1,
+            Vec3A::new(0.0, 0.0, 0.0),
+            1,
+            Color::new_white(),
+        );
+        area_light.set_inverse_square_attenuation(true);
+        let samples = Light::from(area_light).samples(Vec3A::ZERO, &mut sampler);
+        assert_abs_diff_eq!(samples[0].intensity.get_red_val(), 0.25);
+
+        let mut spot_light = SpotLight::new(
+            Vec3A::new(0.0, 5.0, 0.0),
+            Vec3A::new(0.0, -1.0, 0.0),
+            PI / 8.0,
+            PI / 4.0,
+            Color::new_white(),
+        );
+        assert!(!spot_light.inverse_square_attenuation());
+        spot_light.set_inverse_square_attenuation(true);
+        assert!(spot_light.inverse_square_attenuation());
+        let samples = Light::from(spot_light).samples(Vec3A::ZERO, &mut sampler);
+        assert_abs_diff_eq!(samples[0].intensity.get_red_val(), 1.0 / 25.0);
+    }
+
+    #[fixture]
+    pub fn spot_light() -> SpotLight {
+        SpotLight::new(
+            Vec3A::new(0.0, 10.0, 0.0),
+            Vec3A::new(0.0, -1.0, 0.0),
+            PI / 8.0,
+            PI / 4.0,
+            Color::new_white(),
+        )
+    }
+
+    #[rstest]
+    #[case::on_the_axis(Vec3A::new(0.0, 0.0, 0.0), 1.0)]
+    #[case::inside_the_inner_cone(Vec3A::new(2.0, 0.0, 0.0), 1.0)]
+    #[case::outside_the_outer_cone(Vec3A::new(20.0, 0.0, 0.0), 0.0)]
+    #[case::behind_the_light(Vec3A::new(0.0, 20.0, 0.0), 0.0)]
+    fn spot_light_shines_only_within_its_cone(
+        spot_light: SpotLight,
+        mut sampler: IndependentSampler,
+        #[case] point: Vec3A,
+        #[case] expected_falloff: f32,
+    ) {
+        assert_abs_diff_eq!(spot_light.falloff(point), expected_falloff);
+        let samples = Light::from(spot_light).samples(point, &mut sampler);
+        assert_abs_diff_eq!(samples[0].intensity.get_red_val(), expected_falloff);
+    }
+
+    #[rstest]
+    fn spot_light_falls_off_smoothly_between_cones(spot_light: SpotLight) {
+        // 30 degrees from the axis, between 22.5 and 45 degree cones
+        let point = Vec3A::new(10.0 * (PI / 6.0).tan(), 0.0, 0.0);
+        let falloff = spot_light.falloff(point);
+        assert!(falloff > 0.0 && falloff < 1.0);
+        let further_out = Vec3A::new(10.0 * (PI / 5.0).tan(), 0.0, 0.0);
+        assert!(spot_light.falloff(further_out) < falloff);
+    }
+
+    #[test]
+    fn directional_light_has_parallel_rays_from_infinity() {
+        let mut sampler = sampler();
+        let light = Light::from(DirectionalLight::new(
+            Vec3A::new(0.0, -2.0, 0.0),
+            Color::new_white(),
+        ));
+        for point in [Vec3A::ZERO, Vec3A::new(100.0, -5.0, 42.0)] {
+            let samples = light.samples(point, &mut sampler);
+            assert_eq!(samples.len(), 1);
+            assert!(samples[0]
+                .direction
+                .abs_diff_eq(Vec3A::new(0.0, 1.0, 0.0), 1e-6));
+            assert_eq!(samples[0].distance, f32::INFINITY);
+            assert_eq!(samples[0].intensity, Color::new_white());
+        }
+    }
+
+    #[rstest]
+    #[case::center(Vec3A::new(1.0, -3.0, 0.5), Vec3A::new(0.0, 1.0, 0.0), Some(3.0))]
+    #[case::from_the_back(Vec3A::new(0.5, 2.0, 0.5), Vec3A::new(0.0, -1.0, 0.0), Some(2.0))]
+    #[case::outside_of_the_rectangle(Vec3A::new(2.5, -3.0, 0.5), Vec3A::new(0.0, 1.0, 0.0), None)]
+    #[case::parallel(Vec3A::new(1.0, 0.0, -1.0), Vec3A::new(0.0, 0.0, 1.0), None)]
+    #[case::behind_the_ray(Vec3A::new(1.0, -3.0, 0.5), Vec3A::new(0.0, -1.0, 0.0), None)]
+    fn rays_intersect_area_lights(
+        area_light: AreaLight,
+        #[case] origin: Vec3A,
+        #[case] direction: Vec3A,
+        #[case] expected_distance: Option<f32>,
+    ) {
+        assert_eq!(
+            area_light.intersect(Ray::new(origin, direction)),
+            expected_distance
+        );
+    }
+
+    #[rstest]
+    fn sampled_area_light_directions_have_consistent_pdfs(area_light: AreaLight) {
+        let light = Light::from(area_light);
+        let point = Vec3A::new(0.3, -2.0, 0.1);
+        assert!(!light.is_delta());
+        for u in [
+            Vec2::new(0.1, 0.2),
+            Vec2::new(0.5, 0.5),
+            Vec2::new(0.9, 0.7),
+        ] {
+            let sample = light.sample_li(point, u).expect("should sample the light");
+            assert_eq!(sample.radiance, Color::new_white());
+            assert_abs_diff_eq!(
+                sample.pdf,
+                light.pdf_li(point, sample.direction),
+                epsilon = sample.pdf * 1e-4
+            );
+        }
+        assert_abs_diff_eq!(light.pdf_li(point, Vec3A::new(0.0, -1.0, 0.0)), 0.0);
+    }
+
+    #[rstest]
+    fn area_light_pdf_integrates_to_one(area_light: AreaLight) {
+        // Sum of 1 / pdf over a grid of directions sampled by area is the solid angle of the light
+        let light = Light::from(area_light);
+        let point = Vec3A::new(1.0, -1.0, 0.5);
+        let steps = 64;
+        let mut solid_angle = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u = Vec2::new(
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                solid_angle += 1.0 / light.sample_li(point, u).unwrap().pdf;
+            }
+        }
+        solid_angle /= (steps * steps) as f32;
+        // Solid angle of a 2 x 1 rectangle centered 1 unit above the point
+        let expected = 4.0 * (1.0_f32 * 0.5 / (1.0 + 1.0 + 0.25_f32).sqrt()).atan();
+        assert_abs_diff_eq!(solid_angle, expected, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn delta_lights_are_sampled_with_a_pdf_of_one() {
+        let light = Light::from(PointLight::new(
+            Vec3A::new(0.0, 0.0, -10.0),
+            Color::new_white(),
+        ));
+        assert!(light.is_delta());
+        let sample = light.sample_li(Vec3A::ZERO, Vec2::splat(0.5)).unwrap();
+        assert_eq!(sample.radiance, Color::new_white());
+        assert_abs_diff_eq!(sample.pdf, 1.0);
+        assert_abs_diff_eq!(light.pdf_li(Vec3A::ZERO, sample.direction), 0.0);
+    }
+
+    #[test]
+    fn environment_light_is_sampled_over_the_whole_sphere() {
+        let light = Light::from(EnvironmentLight::new(Background::SolidColor(
+            Color::new_blue(),
+        )));
+        let sample = light.sample_li(Vec3A::ZERO, Vec2::new(0.3, 0.8)).unwrap();
+        assert_eq!(sample.radiance, Color::new_blue());
+        assert_eq!(sample.distance, f32::INFINITY);
+        assert_abs_diff_eq!(sample.pdf, 1.0 / (4.0 * PI));
+        assert!(light.samples(Vec3A::ZERO, &mut sampler()).is_empty());
+    }
+
+    #[rstest]
+    fn environment_panorama_is_sampled_by_luminance(sunny_panorama: ImageTexture) {
+        let light = Light::from(EnvironmentLight::new(Background::Equirectangular(
+            sunny_panorama,
+        )));
+        let mut near_sun = 0;
+        for u in strata(32) {
+            let sample = light.sample_li(Vec3A::ZERO, u).unwrap();
+            assert_abs_diff_eq!(
+                sample.pdf,
+                light.pdf_li(Vec3A::ZERO, sample.direction),
+                epsilon = sample.pdf * 1e-3
+            );
+            let (u, v) = direction_to_equirectangular_uv(sample.direction);
+            let column = (u.rem_euclid(1.0) * 8.0).floor();
+            let row = ((1.0 - v) * 4.0).floor();
+            if (column - 2.0).abs() <= 1.0 && (row - 1.0).abs() <= 1.0 {
+                near_sun += 1;
+            }
+        }
+        // The sun is 50 / 0.2 = 250 times brighter than the rest of the sky, filtering spreads it to
+        // the neighbouring texels
+        assert!(near_sun as f32 / 1024.0 > 0.9);
+    }
+
+    #[rstest]
+    fn environment_panorama_samples_estimate_the_total_radiance(sunny_panorama: ImageTexture) {
+        let background = Background::Equirectangular(sunny_panorama);
+        let light = Light::from(EnvironmentLight::new(background.clone()));
+        let estimate = strata(64)
+            .map(|u| {
+                let sample = light.sample_li(Vec3A::ZERO, u).unwrap();
+                sample.radiance.get_red_val() / sample.pdf
+            })
+            .sum::<f32>()
+            / 4096.0;
+
+        let expected = strata(256)
+            .map(|u| {
+                let direction = uniform_sample_sphere(u);
+                background.color_for_direction(direction).get_red_val() / uniform_sphere_pdf()
+            })
+            .sum::<f32>()
+            / 65536.0;
+        assert_abs_diff_eq!(estimate, expected, epsilon = expected * 0.02);
+    }
+
+    #[test]
+    fn sky_light_samples_the_sun_and_the_sky_for_irradiance() {
+        let sky = Sky::new(Vec3A::new(0.0, 0.5, 1.0), 3.0, Color::new_white());
+        let light = Light::from(EnvironmentLight::new(Background::Sky(sky.clone())));
+
+        let mut estimate = 0.0;
+        let mut sun_samples = 0;
+        for u in strata(64) {
+            let sample = light.sample_li(Vec3A::ZERO, u).unwrap();
+            assert_abs_diff_eq!(
+                sample.pdf,
+                light.pdf_li(Vec3A::ZERO, sample.direction),
+                epsilon = sample.pdf * 1e-3
+            );
+            if sample.direction.dot(sky.sun_direction()) >= sky.sun_cos_angular_radius() {
+                sun_samples += 1;
+            }
+            estimate += sample.radiance.get_red_val() * sample.direction.y.max(0.0) / sample.pdf;
+        }
+        estimate /= 4096.0;
+        assert!(sun_samples > 1000);
+
+        // Irradiance on a horizontal surface, the sky integrated numerically and the sun added analytically
+        let sky_irradiance = strata(256)
+            .map(|u| {
+                let direction = uniform_sample_sphere(u);
+                sky.radiance_without_sun(direction).get_red_val() * direction.y.max(0.0)
+                    / uniform_sphere_pdf()
+            })
+            .sum::<f32>()
+            / 65536.0;
+        let sun_irradiance =
+            sky.sun_radiance().get_red_val() * sun_solid_angle() * sky.sun_direction().y;
+        let expected = sky_irradiance + sun_irradiance;
+        assert_abs_diff_eq!(estimate, expected, epsilon = expected * 0.02);
+    }
+}
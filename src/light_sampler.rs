@@ -0,0 +1,259 @@
+use crate::emitter::Emitter;
+
+/// Discrete distribution sampled in constant time (Walker's alias method). Every bin holds its own item
+/// with probability `threshold`, otherwise its alias.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AliasTable {
+    bins: Vec<AliasBin>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct AliasBin {
+    pmf: f32,
+    threshold: f32,
+    alias: usize,
+}
+
+impl AliasTable {
+    /// Items are chosen proportionally to their weights, all of them uniformly if the weights sum up to 0.0.
+    pub fn new(weights: &[f32]) -> Self {
+        assert!(!weights.is_empty(), "alias table should not be empty");
+        let count = weights.len();
+        let total: f32 = weights.iter().map(|weight| weight.max(0.0)).sum();
+        let pmfs: Vec<f32> = weights
+            .iter()
+            .map(|weight| {
+                if total > 0.0 {
+                    weight.max(0.0) / total
+                } else {
+                    1.0 / count as f32
+                }
+            })
+            .collect();
+
+        // Bins with less than the average probability are topped up by the ones with more
+        let mut scaled: Vec<f32> = pmfs.iter().map(|pmf| pmf * count as f32).collect();
+        let mut bins: Vec<AliasBin> = (0..count)
+            .map(|i| AliasBin {
+                pmf: pmfs[i],
+                threshold: 1.0,
+                alias: i,
+            })
+            .collect();
+        let (mut under, mut over): (Vec<usize>, Vec<usize>) =
+            (0..count).partition(|&i| scaled[i] < 1.0);
+        while let (Some(&small), Some(&large)) = (under.last(), over.last()) {
+            under.pop();
+            bins[small].threshold = scaled[small];
+            bins[small].alias = large;
+            scaled[large] -= 1.0 - scaled[small];
+            if scaled[large] < 1.0 {
+                over.pop();
+                under.push(large);
+            }
+        }
+        // Whatever is left is 1.0 up to rounding errors
+        for i in under.into_iter().chain(over) {
+            bins[i].threshold = 1.0;
+        }
+
+        Self { bins }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bins.is_empty()
+    }
+
+    /// Samples an index with the 1D sample, returns it with its probability.
+    pub fn sample(&self, u: f32) -> (usize, f32) {
+        let scaled = u * self.len() as f32;
+        let bin = (scaled as usize).min(self.len() - 1);
+        let index = if scaled - (bin as f32) < self.bins[bin].threshold {
+            bin
+        } else {
+            self.bins[bin].alias
+        };
+        (index, self.bins[index].pmf)
+    }
+
+    pub fn pmf(&self, index: usize) -> f32 {
+        self.bins[index].pmf
+    }
+}
+
+/// Chooses one of the world's emitters for direct lighting, so its cost does not grow with the number of
+/// lights. Emitters of finite size are chosen proportionally to their power. The power of directional and
+/// environment lights depends on the size of the world, each of them is chosen as often as all of the
+/// finite emitters together.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LightSampler {
+    finite: Vec<usize>,
+    finite_table: Option<AliasTable>,
+    total_finite_power: f32,
+    infinite: Vec<usize>,
+}
+
+impl LightSampler {
+    /// Emitters come with the indices `sample` returns for them.
+    pub fn new<'a>(emitters: impl IntoIterator<Item = (usize, Emitter<'a>)>) -> Self {
+        let mut finite = vec![];
+        let mut powers = vec![];
+        let mut infinite = vec![];
+        for (index, emitter) in emitters {
+            match emitter.power() {
+                Some(power) => {
+                    finite.push(index);
+                    powers.push(power);
+                }
+                None => infinite.push(index),
+            }
+        }
+
+        Self {
+            finite,
+            finite_table: (!powers.is_empty()).then(|| AliasTable::new(&powers)),
+            total_finite_power: powers.iter().sum(),
+            infinite,
+        }
+    }
+
+    /// Samples an emitter with the 1D sample, returns its index with the probability of choosing it.
+    pub fn sample(&self, u: f32) -> Option<(usize, f32)> {
+        let infinite_probability = self.infinite_probability();
+        let all_infinite_probability = infinite_probability * self.infinite.len() as f32;
+        if u < all_infinite_probability {
+            let i = ((u / infinite_probability) as usize).min(self.infinite.len() - 1);
+            return Some((self.infinite[i], infinite_probability));
+        }
+
+        let table = self.finite_table.as_ref()?;
+        let u = ((u - all_infinite_probability) / (1.0 - all_infinite_probability))
+            .min(1.0 - f32::EPSILON);
+        let (i, pmf) = table.sample(u);
+        Some((self.finite[i], pmf * (1.0 - all_infinite_probability)))
+    }
+
+    /// Probability of `sample` choosing the emitter.
+    pub fn pmf(&self, emitter: Emitter) -> f32 {
+        let infinite_probability = self.infinite_probability();
+        match emitter.power() {
+            None => infinite_probability,
+            Some(_) if self.finite.is_empty() => 0.0,
+            Some(power) => {
+                let finite_probability = 1.0 - infinite_probability * self.infinite.len() as f32;
+                let share = if self.total_finite_power > 0.0 {
+                    power / self.total_finite_power
+                } else {
+                    1.0 / self.finite.len() as f32
+                };
+                finite_probability * share
+            }
+        }
+    }
+
+    fn infinite_probability(&self) -> f32 {
+        if self.infinite.is_empty() {
+            return 0.0;
+        }
+        let groups = self.infinite.len() + usize::from(!self.finite.is_empty());
+        1.0 / groups as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use glam::Vec3A;
+    use rstest::*;
+
+    use crate::{
+        background::Background,
+        color::Color,
+        light::{DirectionalLight, EnvironmentLight, Light, PointLight},
+    };
+
+    use super::*;
+
+    fn point_light(intensity: f32) -> Light {
+        Light::from(PointLight::new(
+            Vec3A::ZERO,
+            Color::new_color(intensity, intensity, intensity),
+        ))
+    }
+
+    #[rstest]
+    #[case(vec![1.0, 2.0, 3.0, 4.0])]
+    #[case(vec![10.0, 0.0, 0.5])]
+    #[case(vec![0.0, 0.0])]
+    fn alias_table_samples_proportionally_to_weights(#[case] weights: Vec<f32>) {
+        let table = AliasTable::new(&weights);
+        let total: f32 = weights.iter().sum();
+
+        let samples = 10_000;
+        let mut counts = vec![0; weights.len()];
+        for i in 0..samples {
+            let (index, pmf) = table.sample((i as f32 + 0.5) / samples as f32);
+            assert_abs_diff_eq!(pmf, table.pmf(index));
+            counts[index] += 1;
+        }
+        for (i, weight) in weights.iter().enumerate() {
+            let expected = if total > 0.0 {
+                weight / total
+            } else {
+                1.0 / weights.len() as f32
+            };
+            assert_abs_diff_eq!(table.pmf(i), expected, epsilon = 1e-6);
+            assert_abs_diff_eq!(counts[i] as f32 / samples as f32, expected, epsilon = 1e-3);
+        }
+    }
+
+    #[test]
+    fn finite_emitters_are_chosen_by_power() {
+        let lights = [point_light(1.0), point_light(3.0)];
+        let light_sampler = LightSampler::new(lights.iter().map(Emitter::Light).enumerate());
+
+        assert_abs_diff_eq!(light_sampler.pmf(Emitter::Light(&lights[0])), 0.25);
+        assert_abs_diff_eq!(light_sampler.pmf(Emitter::Light(&lights[1])), 0.75);
+        assert_eq!(light_sampler.sample(0.1), Some((0, 0.25)));
+        assert_eq!(light_sampler.sample(0.9), Some((1, 0.75)));
+    }
+
+    #[test]
+    fn infinite_lights_are_chosen_as_often_as_all_finite_emitters() {
+        let lights = [
+            point_light(1.0),
+            Light::from(DirectionalLight::new(-Vec3A::Y, Color::new_white())),
+            point_light(3.0),
+            Light::from(EnvironmentLight::new(Background::SolidColor(
+                Color::new_white(),
+            ))),
+        ];
+        let light_sampler = LightSampler::new(lights.iter().map(Emitter::Light).enumerate());
+
+        let pmfs: Vec<f32> = lights
+            .iter()
+            .map(|light| light_sampler.pmf(Emitter::Light(light)))
+            .collect();
+        assert_abs_diff_eq!(
+            pmfs.as_slice(),
+            [0.25 / 3.0, 1.0 / 3.0, 0.75 / 3.0, 1.0 / 3.0].as_slice()
+        );
+
+        for u in [0.1, 0.5, 0.7, 0.95] {
+            let (index, pmf) = light_sampler.sample(u).unwrap();
+            assert_abs_diff_eq!(pmf, pmfs[index], epsilon = 1e-6);
+        }
+        assert_eq!(light_sampler.sample(0.1).unwrap().0, 1);
+        assert_eq!(light_sampler.sample(0.5).unwrap().0, 3);
+    }
+
+    #[test]
+    fn no_emitters_give_no_samples() {
+        let light_sampler = LightSampler::new(std::iter::empty());
+        assert_eq!(light_sampler.sample(0.5), None);
+    }
+}
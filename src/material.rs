@@ -0,0 +1,330 @@
+use glam::Vec3A;
+
+use crate::{
+    bsdf::Bsdf,
+    bump_map::{BumpMap, TangentFrame},
+    color::Color,
+    light::{Light, LightSample},
+    medium::Medium,
+    ray::reflect,
+    subsurface::Subsurface,
+};
+
+/// Surface properties of an object. Path tracing interprets them physically: `color * diffuse` is the albedo
+/// of a Lambertian surface, `reflective` the probability of a perfect mirror bounce, and the ambient and Phong
+/// specular terms are ignored.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Material {
+    pub color: Color,
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub shininess: f32,
+    pub reflective: f32,
+    /// Light emitted by the surface itself, it does not depend on any light source
+    pub emissive: Color,
+    pub bump_map: Option<BumpMap>,
+    /// Physically based scattering model, replaces the Phong diffuse and specular terms when set
+    pub bsdf: Option<Bsdf>,
+    /// Participating medium inside of the object, for path tracing. Without a BSDF the surface only bounds
+    /// the medium, rays pass through it.
+    pub medium: Option<Medium>,
+    /// Light scattering beneath the surface, for path tracing. It replaces the BSDF, other integrators shade
+    /// the surface with the Phong terms or as diffuse.
+    pub subsurface: Option<Subsurface>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: Color::new_white(),
+            ambient: 0.1,
+            diffuse: 0.9,
+            specular: 0.9,
+            shininess: 200.0,
+            reflective: 0.0,
+            emissive: Color::new_black(),
+            bump_map: None,
+            bsdf: None,
+            medium: None,
+            subsurface: None,
+        }
+    }
+}
+
+impl Material {
+    /// Surface that only bounds a medium, it does not scatter light.
+    pub fn is_medium_boundary(&self) -> bool {
+        self.medium.is_some() && self.bsdf.is_none()
+    }
+
+    /// Phong reflection model for a single light. Diffuse and specular terms are averaged over the light's
+    /// samples taken for the shaded point and scaled by the light intensity (fraction of the light visible
+    /// from the point). Materials with a BSDF use it instead of the diffuse and specular terms.
+    pub fn lighting(
+        &self,
+        light: &Light,
+        samples: &[LightSample],
+        eye_vector: Vec3A,
+        normal_vector: Vec3A,
+        light_intensity: f32,
+    ) -> Color {
+        let ambient = self.color * light.intensity() * self.ambient;
+        if light_intensity == 0.0 {
+            return ambient;
+        }
+
+        if let Some(bsdf) = &self.bsdf {
+            let frame = TangentFrame::from_normal(normal_vector);
+            let wo = frame.world_to_local(eye_vector);
+            let sum = samples.iter().fold(Color::new_black(), |acc, sample| {
+                let wi = frame.world_to_local(sample.direction);
+                acc + bsdf.f(wo, wi) * sample.intensity * wi.z.max(0.0)
+            });
+            return ambient + sum / samples.len() as f32 * light_intensity;
+        }
+
+        let sum = samples.iter().fold(Color::new_black(), |acc, sample| {
+            let light_vector = sample.direction;
+            // Negative value means the light is on the other side of the surface
+            let light_dot_normal = light_vector.dot(normal_vector);
+            if light_dot_normal < 0.0 {
+                return acc;
+            }
+
+            let diffuse = self.color * sample.intensity * self.diffuse * light_dot_normal;
+            let reflect_vector = reflect(-light_vector, normal_vector);
+            // Negative value means the light reflects away from the eye
+            let reflect_dot_eye = reflect_vector.dot(eye_vector);
+            let specular = if reflect_dot_eye <= 0.0 {
+                Color::new_black()
+            } else {
+                sample.intensity * self.specular * reflect_dot_eye.powf(self.shininess)
+            };
+
+            acc + diffuse + specular
+        });
+
+        ambient + sum / samples.len() as f32 * light_intensity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use crate::{
+        light::{AreaLight, DirectionalLight, PointLight, SpotLight},
+        microfacet::MicrofacetBsdf,
+        sampler::IndependentSampler,
+        test_helpers::assert_color_eq,
+    };
+
+    use super::*;
+
+    #[fixture]
+    pub fn material() -> Material {
+        Material::default()
+    }
+
+    /// Samples for lights without jitter, they do not depend on the sampler state.
+    fn samples_at(light: &Light, point: Vec3A) -> Vec<LightSample> {
+        light.samples(point, &mut IndependentSampler::new(1, 0))
+    }
+
+    #[rstest]
+    fn default_material(material: Material) {
+        assert_eq!(material.color, Color::new_white());
+        assert_abs_diff_eq!(material.ambient, 0.1);
+        assert_abs_diff_eq!(material.diffuse, 0.9);
+        assert_abs_diff_eq!(material.specular, 0.9);
+        assert_abs_diff_eq!(material.shininess, 200.0);
+        assert_abs_diff_eq!(material.reflective, 0.0);
+        assert_eq!(material.emissive, Color::new_black());
+        assert!(material.bump_map.is_none());
+        assert!(material.bsdf.is_none());
+    }
+
+    #[rstest]
+    #[case::eye_between_light_and_surface(
+        Vec3A::new(0.0, 0.0, -1.0),
+        Vec3A::new(0.0, 0.0, -10.0),
+        Color::new_color(1.9, 1.9, 1.9)
+    )]
+    #[case::eye_offset_45_degrees(
+        Vec3A::new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+        Vec3A::new(0.0, 0.0, -10.0),
+        Color::new_color(1.0, 1.0, 1.0)
+    )]
+    #[case::light_offset_45_degrees(
+        Vec3A::new(0.0, 0.0, -1.0),
+        Vec3A::new(0.0, 10.0, -10.0),
+        Color::new_color(0.7364, 0.7364, 0.7364)
+    )]
+    #[case::eye_in_the_path_of_the_reflection_vector(
+        Vec3A::new(0.0, -FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+        Vec3A::new(0.0, 10.0, -10.0),
+        Color::new_color(1.6364, 1.6364, 1.6364)
+    )]
+    #[case::light_behind_the_surface(
+        Vec3A::new(0.0, 0.0, -1.0),
+        Vec3A::new(0.0, 0.0, 10.0),
+        Color::new_color(0.1, 0.1, 0.1)
+    )]
+    fn lighting_with_the_eye_and_light_in_various_positions(
+        material: Material,
+        #[case] eye_vector: Vec3A,
+        #[case] light_position: Vec3A,
+        #[case] expected_color: Color,
+    ) {
+        let light = Light::from(PointLight::new(light_position, Color::new_white()));
+        let result = material.lighting(
+            &light,
+            &samples_at(&light, Vec3A::ZERO),
+            eye_vector,
+            Vec3A::new(0.0, 0.0, -1.0),
+            1.0,
+        );
+        assert_color_eq(result, expected_color);
+    }
+
+    #[rstest]
+    fn lighting_with_the_surface_in_shadow(material: Material) {
+        let light = Light::from(PointLight::new(
+            Vec3A::new(0.0, 0.0, -10.0),
+            Color::new_white(),
+        ));
+        let result = material.lighting(
+            &light,
+            &samples_at(&light, Vec3A::ZERO),
+            Vec3A::new(0.0, 0.0, -1.0),
+            Vec3A::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        assert_color_eq(result, Color::new_color(0.1, 0.1, 0.1));
+    }
+
+    #[rstest]
+    #[case(1.0, Color::new_color(1.0, 1.0, 1.0))]
+    #[case(0.5, Color::new_color(0.55, 0.55, 0.55))]
+    #[case(0.0, Color::new_color(0.1, 0.1, 0.1))]
+    fn lighting_uses_light_intensity_to_attenuate_color(
+        #[case] light_intensity: f32,
+        #[case] expected_color: Color,
+    ) {
+        let material = Material {
+            specular: 0.0,
+            ..Default::default()
+        };
+        let light = Light::from(PointLight::new(
+            Vec3A::new(0.0, 0.0, -10.0),
+            Color::new_white(),
+        ));
+        let result = material.lighting(
+            &light,
+            &samples_at(&light, Vec3A::new(0.0, 0.0, -1.0)),
+            Vec3A::new(0.0, 0.0, -1.0),
+            Vec3A::new(0.0, 0.0, -1.0),
+            light_intensity,
+        );
+        assert_color_eq(result, expected_color);
+    }
+
+    #[rstest]
+    #[case(Vec3A::new(0.0, 0.0, -1.0), Color::new_color(0.9965, 0.9965, 0.9965))]
+    #[case(
+        Vec3A::new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+        Color::new_color(0.62318, 0.62318, 0.62318)
+    )]
+    fn lighting_samples_the_area_light(#[case] point: Vec3A, #[case] expected_color: Color) {
+        let mut area_light = AreaLight::new(
+            Vec3A::new(-0.5, -0.5, -5.0),
+            Vec3A::new(1.0, 0.0, 0.0),
+            2,
+            Vec3A::new(0.0, 1.0, 0.0),
+            2,
+            Color::new_white(),
+        );
+        area_light.set_jitter(false);
+        let light = Light::Area(area_light);
+        let material = Material {
+            ambient: 0.1,
+            diffuse: 0.9,
+            specular: 0.0,
+            ..Default::default()
+        };
+        let eye = Vec3A::new(0.0, 0.0, -5.0);
+        let result = material.lighting(
+            &light,
+            &samples_at(&light, point),
+            (eye - point).normalize(),
+            point,
+            1.0,
+        );
+        assert_color_eq(result, expected_color);
+    }
+
+    #[rstest]
+    fn lighting_with_a_directional_light(material: Material) {
+        let light = Light::from(DirectionalLight::new(
+            Vec3A::new(0.0, 0.0, 1.0),
+            Color::new_white(),
+        ));
+        let result = material.lighting(
+            &light,
+            &samples_at(&light, Vec3A::new(50.0, -3.0, 0.0)),
+            Vec3A::new(0.0, 0.0, -1.0),
+            Vec3A::new(0.0, 0.0, -1.0),
+            1.0,
+        );
+        assert_color_eq(result, Color::new_color(1.9, 1.9, 1.9));
+    }
+
+    #[rstest]
+    fn lighting_outside_of_the_spot_light_cone_is_ambient_only(material: Material) {
+        let light = Light::from(SpotLight::new(
+            Vec3A::new(0.0, 0.0, -10.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+            0.1,
+            0.2,
+            Color::new_white(),
+        ));
+        let result = material.lighting(
+            &light,
+            &samples_at(&light, Vec3A::ZERO),
+            Vec3A::new(0.0, 0.0, -1.0),
+            Vec3A::new(0.0, 0.0, -1.0),
+            1.0,
+        );
+        assert_color_eq(result, Color::new_color(0.1, 0.1, 0.1));
+    }
+
+    #[test]
+    fn lighting_with_a_bsdf_replaces_the_phong_terms() {
+        let bsdf = Bsdf::Microfacet(MicrofacetBsdf::new(
+            Color::new_color(0.9, 0.6, 0.2),
+            0.5,
+            1.0,
+        ));
+        let material = Material {
+            ambient: 0.0,
+            bsdf: Some(bsdf),
+            ..Default::default()
+        };
+        let light = Light::from(PointLight::new(
+            Vec3A::new(0.0, 0.0, -10.0),
+            Color::new_white(),
+        ));
+        let result = material.lighting(
+            &light,
+            &samples_at(&light, Vec3A::ZERO),
+            Vec3A::new(0.0, 0.0, -1.0),
+            Vec3A::new(0.0, 0.0, -1.0),
+            1.0,
+        );
+        assert_color_eq(result, bsdf.f(Vec3A::Z, Vec3A::Z));
+    }
+}
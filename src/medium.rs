@@ -0,0 +1,336 @@
+use std::f32::consts::PI;
+
+use glam::{Vec2, Vec3A};
+
+use crate::{bump_map::TangentFrame, color::Color, density::Density, ray::Ray, sampler::Sampler};
+
+/// Henyey-Greenstein phase function, the angular distribution of light scattered in a medium. Directions point
+/// away from the scattering point: `wo` towards the viewer and `wi` towards the light.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct HenyeyGreenstein {
+    /// Asymmetry, the average cosine of the scattering angle. Positive values scatter forward, negative backward
+    /// and 0.0 equally in all directions.
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f32) -> Self {
+        if g <= -1.0 || g >= 1.0 {
+            panic!("Henyey-Greenstein asymmetry should be between -1.0 and 1.0");
+        }
+
+        Self { g }
+    }
+
+    pub fn g(&self) -> f32 {
+        self.g
+    }
+
+    /// Fraction of light arriving from `wi` that is scattered towards `wo`, per unit solid angle.
+    pub fn p(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        // Light goes on forward when wi is opposite to wo
+        let denominator = 1.0 + self.g * self.g + 2.0 * self.g * wo.dot(wi);
+        (1.0 - self.g * self.g) / (4.0 * PI * denominator * denominator.max(0.0).sqrt())
+    }
+
+    /// Samples `wi` proportionally to the phase function, returns it with its probability density.
+    pub fn sample(&self, wo: Vec3A, u: Vec2) -> (Vec3A, f32) {
+        let g = self.g;
+        // Cosine of the angle between wo and wi
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u.x
+        } else {
+            let term = (1.0 - g * g) / (1.0 + g - 2.0 * g * u.x);
+            -(1.0 + g * g - term * term) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y;
+        let wi = TangentFrame::from_normal(wo).local_to_world(Vec3A::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        (wi, self.p(wo, wi))
+    }
+}
+
+/// What a ray found travelling through a medium, sampled by `Medium::sample_distance`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum MediumSample {
+    /// Scattered at the distance along the ray, the throughput of the ray is multiplied by the weight
+    Scattered { distance: f32, weight: Color },
+    /// Absorbed before reaching the end of the ray
+    Absorbed,
+    /// Passed through the medium, the throughput of the ray is multiplied by the weight
+    Transmitted { weight: Color },
+}
+
+/// Participating medium, such as fog or smoke, which absorbs and scatters light travelling through it rather
+/// than at surfaces. Coefficients are per unit distance, their sum is the extinction coefficient. Both are scaled
+/// by the density at each point, for media such as smoke whose density varies in space. At scattering events
+/// the phase function takes the place of a BSDF.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Medium {
+    /// Absorption coefficient
+    pub sigma_a: Color,
+    /// Scattering coefficient
+    pub sigma_s: Color,
+    pub phase: HenyeyGreenstein,
+    pub density: Density,
+}
+
+impl Medium {
+    /// Medium with the asymmetry `g` of its Henyey-Greenstein phase function.
+    pub fn new(sigma_a: Color, sigma_s: Color, g: f32) -> Self {
+        Self {
+            sigma_a,
+            sigma_s,
+            phase: HenyeyGreenstein::new(g),
+            density: Density::Uniform,
+        }
+    }
+
+    /// Extinction coefficient, the fraction of light absorbed or scattered away per unit distance.
+    pub fn sigma_t(&self) -> Color {
+        self.sigma_a + self.sigma_s
+    }
+
+    /// Largest extinction coefficient anywhere in the medium, the rate at which tracking samples collisions.
+    fn majorant(&self) -> f32 {
+        self.sigma_t().max_component() * self.density.max()
+    }
+
+    /// Fraction of light that passes the distance along the ray through the medium. It is exact for media of
+    /// uniform density, otherwise it is estimated with ratio tracking: each collision sampled with the majorant
+    /// multiplies the transmittance by the probability of it being a null collision.
+    pub fn transmittance(&self, ray: Ray, distance: f32, sampler: &mut dyn Sampler) -> Color {
+        let sigma_t = self.sigma_t();
+        if self.density.is_uniform() {
+            return Color::new_color(
+                (-sigma_t.get_red_val() * distance).exp(),
+                (-sigma_t.get_green_val() * distance).exp(),
+                (-sigma_t.get_blue_val() * distance).exp(),
+            );
+        }
+
+        let majorant = self.majorant();
+        let mut transmittance = Color::new_white();
+        if majorant <= 0.0 {
+            return transmittance;
+        }
+        let mut t = 0.0;
+        loop {
+            t -= (1.0 - sampler.next_1d()).ln() / majorant;
+            if t >= distance {
+                return transmittance;
+            }
+            let density = self.density.at(ray.position(t));
+            let null = Color::new_color(majorant, majorant, majorant) - sigma_t * density;
+            transmittance = transmittance * (null / majorant);
+        }
+    }
+
+    /// Samples where a ray scatters before reaching `max_distance`, with delta tracking. Collisions are sampled
+    /// with the largest extinction coefficient, each of them is absorption, scattering or a null collision
+    /// the ray passes through. For media whose coefficients differ between channels, the event is chosen
+    /// by the average coefficients and the weight corrects the throughput of each channel.
+    pub fn sample_distance(
+        &self,
+        ray: Ray,
+        max_distance: f32,
+        sampler: &mut dyn Sampler,
+    ) -> MediumSample {
+        let sigma_t = self.sigma_t();
+        let majorant = self.majorant();
+        let mut weight = Color::new_white();
+        if majorant <= 0.0 {
+            return MediumSample::Transmitted { weight };
+        }
+
+        let mut distance = 0.0;
+        loop {
+            distance -= (1.0 - sampler.next_1d()).ln() / majorant;
+            if distance >= max_distance {
+                return MediumSample::Transmitted { weight };
+            }
+
+            let density = self.density.at(ray.position(distance));
+            let absorption_probability = average(self.sigma_a) * density / majorant;
+            let scattering_probability = average(self.sigma_s) * density / majorant;
+            let u = sampler.next_1d();
+            if u < absorption_probability {
+                return MediumSample::Absorbed;
+            }
+            let null = Color::new_color(majorant, majorant, majorant) - sigma_t * density;
+            let null_average = average(null);
+            // Rounding can leave a sliver for null collisions in media without them
+            if u < absorption_probability + scattering_probability || null_average <= 0.0 {
+                weight = weight * (self.sigma_s / average(self.sigma_s));
+                return MediumSample::Scattered { distance, weight };
+            }
+            weight = weight * (null / null_average);
+        }
+    }
+}
+
+fn average(color: Color) -> f32 {
+    (color.get_red_val() + color.get_green_val() + color.get_blue_val()) / 3.0
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use std::sync::Arc;
+
+    use crate::{
+        density::VoxelGrid,
+        sampler::IndependentSampler,
+        sampling::{uniform_sample_sphere, uniform_sphere_pdf},
+    };
+
+    use super::*;
+
+    #[rstest]
+    #[case(0.0)]
+    #[case(0.7)]
+    #[case(-0.4)]
+    fn phase_function_is_normalized_and_sampled_by_its_asymmetry(#[case] g: f32) {
+        let phase = HenyeyGreenstein::new(g);
+        let wo = Vec3A::new(0.0, 0.6, 0.8);
+        let strata = 100;
+        let mut integral = 0.0;
+        let mut average_cos = 0.0;
+        for i in 0..strata {
+            for j in 0..strata {
+                let u = Vec2::new(i as f32 + 0.5, j as f32 + 0.5) / strata as f32;
+                integral += phase.p(wo, uniform_sample_sphere(u)) / uniform_sphere_pdf();
+
+                let (wi, pdf) = phase.sample(wo, u);
+                assert_abs_diff_eq!(wi.length(), 1.0, epsilon = 1e-5);
+                assert_abs_diff_eq!(pdf, phase.p(wo, wi), epsilon = pdf * 1e-4);
+                // Cosine of the scattering angle, between the incoming light and wo
+                average_cos += (-wi).dot(wo);
+            }
+        }
+        let count = (strata * strata) as f32;
+        assert_abs_diff_eq!(integral / count, 1.0, epsilon = 0.01);
+        assert_abs_diff_eq!(average_cos / count, g, epsilon = 0.01);
+    }
+
+    #[test]
+    #[should_panic(expected = "Henyey-Greenstein asymmetry should be between -1.0 and 1.0")]
+    fn phase_function_asymmetry_must_be_below_one() {
+        HenyeyGreenstein::new(1.0);
+    }
+
+    #[rstest]
+    #[case::gray(Color::new_color(0.5, 0.5, 0.5), Color::new_color(1.0, 1.0, 1.0))]
+    #[case::chromatic(Color::new_color(0.1, 0.4, 0.0), Color::new_color(0.2, 0.6, 1.5))]
+    fn delta_tracking_transmits_the_transmittance(#[case] sigma_a: Color, #[case] sigma_s: Color) {
+        let medium = Medium::new(sigma_a, sigma_s, 0.0);
+        let ray = Ray::new(Vec3A::ZERO, Vec3A::new(1.0, 0.0, 0.0));
+        let distance = 1.5;
+        let samples = 20_000;
+        let mut sampler = IndependentSampler::new(samples, 0);
+        let mut transmitted = Color::new_black();
+        let mut scattered = Color::new_black();
+        for sample_index in 0..samples {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            match medium.sample_distance(ray, distance, &mut sampler) {
+                MediumSample::Transmitted { weight } => transmitted += weight,
+                MediumSample::Scattered { weight, .. } => scattered += weight,
+                MediumSample::Absorbed => {}
+            }
+        }
+
+        let transmittance = medium.transmittance(ray, distance, &mut sampler);
+        let sigma_t = medium.sigma_t();
+        for (estimate, expected) in [
+            (transmitted.get_red_val(), transmittance.get_red_val()),
+            (transmitted.get_green_val(), transmittance.get_green_val()),
+            (transmitted.get_blue_val(), transmittance.get_blue_val()),
+        ] {
+            assert_abs_diff_eq!(estimate / samples as f32, expected, epsilon = 0.02);
+        }
+        // Light scattered on the way is the scattering albedo of the light that did not get through
+        let expected_scattered = sigma_s.get_green_val() / sigma_t.get_green_val()
+            * (1.0 - transmittance.get_green_val());
+        assert_abs_diff_eq!(
+            scattered.get_green_val() / samples as f32,
+            expected_scattered,
+            epsilon = 0.02
+        );
+    }
+
+    /// Medium along the x axis with no density up to 0.5, rising linearly to 1.0 at 1.5 and staying there up to
+    /// 2.0, so the optical depth across it is the extinction coefficient.
+    fn ramp_medium(sigma_a: Color, sigma_s: Color) -> Medium {
+        let grid = VoxelGrid::new(
+            [2, 1, 1],
+            vec![0.0, 1.0],
+            Vec3A::ZERO,
+            Vec3A::new(2.0, 1.0, 1.0),
+        );
+        Medium {
+            density: Density::Grid(Arc::new(grid)),
+            ..Medium::new(sigma_a, sigma_s, 0.0)
+        }
+    }
+
+    #[test]
+    fn tracking_through_heterogeneous_media_matches_the_optical_depth() {
+        let medium = ramp_medium(
+            Color::new_color(0.2, 0.5, 1.0),
+            Color::new_color(0.8, 0.5, 0.5),
+        );
+        let ray = Ray::new(Vec3A::new(0.0, 0.5, 0.5), Vec3A::new(1.0, 0.0, 0.0));
+        let samples = 20_000;
+        let mut sampler = IndependentSampler::new(samples, 0);
+        let mut ratio_tracked = Color::new_black();
+        let mut delta_tracked = Color::new_black();
+        for sample_index in 0..samples {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            ratio_tracked += medium.transmittance(ray, 2.0, &mut sampler);
+            if let MediumSample::Transmitted { weight } =
+                medium.sample_distance(ray, 2.0, &mut sampler)
+            {
+                delta_tracked += weight;
+            }
+        }
+
+        let sigma_t = medium.sigma_t();
+        for (ratio, delta, sigma_t) in [
+            (
+                ratio_tracked.get_red_val(),
+                delta_tracked.get_red_val(),
+                sigma_t.get_red_val(),
+            ),
+            (
+                ratio_tracked.get_blue_val(),
+                delta_tracked.get_blue_val(),
+                sigma_t.get_blue_val(),
+            ),
+        ] {
+            let expected = (-sigma_t).exp();
+            assert_abs_diff_eq!(ratio / samples as f32, expected, epsilon = 0.01);
+            assert_abs_diff_eq!(delta / samples as f32, expected, epsilon = 0.02);
+        }
+    }
+
+    #[test]
+    fn rays_do_not_scatter_where_there_is_no_density() {
+        let medium = ramp_medium(Color::new_black(), Color::new_color(5.0, 5.0, 5.0));
+        let ray = Ray::new(Vec3A::new(0.0, 0.5, 0.5), Vec3A::new(1.0, 0.0, 0.0));
+        let mut sampler = IndependentSampler::new(1000, 0);
+        for sample_index in 0..1000 {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            if let MediumSample::Scattered { distance, .. } =
+                medium.sample_distance(ray, 2.0, &mut sampler)
+            {
+                assert!(distance > 0.5);
+            }
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use std::f32::consts::{FRAC_1_PI, PI};
+
+use glam::{Vec2, Vec3A};
+
+use crate::{
+    bsdf::BsdfSample,
+    color::Color,
+    sampling::{cosine_hemisphere_pdf, cosine_sample_hemisphere},
+};
+
+/// Smallest alpha used by the distribution, perfectly smooth surfaces would make it a delta function.
+const MIN_ALPHA: f32 = 1e-3;
+
+/// Isotropic GGX (Trowbridge-Reitz) distribution of microfacet normals with the Smith height-correlated
+/// masking-shadowing function. Directions are in the local shading frame, where the normal is +z.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct GgxDistribution {
+    alpha: f32,
+}
+
+impl GgxDistribution {
+    /// Perceptual roughness in the 0.0 - 1.0 range, alpha is roughness squared.
+    pub fn from_roughness(roughness: f32) -> Self {
+        Self {
+            alpha: (roughness.clamp(0.0, 1.0).powi(2)).max(MIN_ALPHA),
+        }
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    /// Density of microfacets with the normal, per unit of projected area.
+    pub fn d(&self, wm: Vec3A) -> f32 {
+        let cos_theta = wm.z;
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let denominator = cos_theta * cos_theta * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function, ratio of hidden to visible microfacet area in the direction.
+    pub fn lambda(&self, w: Vec3A) -> f32 {
+        let cos2_theta = w.z * w.z;
+        if cos2_theta == 0.0 {
+            return f32::INFINITY;
+        }
+        let tan2_theta = (1.0 - cos2_theta).max(0.0) / cos2_theta;
+        ((1.0 + self.alpha * self.alpha * tan2_theta).sqrt() - 1.0) / 2.0
+    }
+
+    /// Fraction of microfacets visible from the direction.
+    pub fn g1(&self, w: Vec3A) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both directions.
+    pub fn g(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Distribution of normals visible from the direction, it is also the pdf of `sample_visible_normal`.
+    pub fn visible_normal_pdf(&self, w: Vec3A, wm: Vec3A) -> f32 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * w.dot(wm).max(0.0)
+    }
+
+    /// Samples a microfacet normal visible from the direction (Heitz 2018). Sampling only visible normals
+    /// avoids wasting samples on microfacets facing away from the viewer.
+    pub fn sample_visible_normal(&self, w: Vec3A, u: Vec2) -> Vec3A {
+        // Stretch the view direction, so the distribution becomes a hemisphere
+        let mut wh = Vec3A::new(self.alpha * w.x, self.alpha * w.y, w.z).normalize();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z < 0.99999 {
+            Vec3A::Z.cross(wh).normalize()
+        } else {
+            Vec3A::X
+        };
+        let t2 = wh.cross(t1);
+
+        // Uniform point on the disk, warped to the projection of the visible hemisphere
+        let radius = u.x.sqrt();
+        let phi = 2.0 * PI * u.y;
+        let px = radius * phi.cos();
+        let mut py = radius * phi.sin();
+        let h = (1.0 - px * px).max(0.0).sqrt();
+        let s = (1.0 + wh.z) / 2.0;
+        py = (1.0 - s) * h + s * py;
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = t1 * px + t2 * py + wh * pz;
+
+        // Unstretch back to the ellipsoid
+        Vec3A::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+/// Fresnel reflectance of a dielectric interface. The relative index of refraction is the ratio of the
+/// inside to the outside medium, negative cosine means the direction is inside.
+pub fn fresnel_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_theta_i = cos_theta_i.min(1.0);
+
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// Fresnel reflectance of a conductor with the complex index of refraction `eta + i * k`, per color channel.
+pub fn fresnel_conductor(cos_theta_i: f32, eta: Vec3A, k: Vec3A) -> Color {
+    let channel = |eta, k| fresnel_complex(cos_theta_i, Complex::new(eta, k));
+    Color::new_color(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+/// Schlick's approximation of Fresnel reflectance from the reflectance at normal incidence.
+pub fn fresnel_schlick(cos_theta_i: f32, f0: Color) -> Color {
+    let weight = (1.0 - cos_theta_i.clamp(0.0, 1.0)).powi(5);
+    f0 + (Color::new_white() - f0) * weight
+}
+
+fn fresnel_complex(cos_theta_i: f32, eta: Complex) -> f32 {
+    let cos_theta_i = Complex::new(cos_theta_i.clamp(0.0, 1.0), 0.0);
+    let sin2_theta_i = Complex::new(1.0, 0.0) - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    let cos_theta_t = (Complex::new(1.0, 0.0) - sin2_theta_t).sqrt();
+
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (parallel.norm_squared() + perpendicular.norm_squared()) / 2.0
+}
+
+/// Just enough complex arithmetic for the conductor Fresnel equations.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    fn norm_squared(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Principal square root.
+    fn sqrt(self) -> Self {
+        let norm = self.norm_squared().sqrt();
+        if norm == 0.0 {
+            return Self::new(0.0, 0.0);
+        }
+        let re = ((norm + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((norm - self.re) / 2.0).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl std::ops::Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl std::ops::Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl std::ops::Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl std::ops::Div for Complex {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let scale = 1.0 / other.norm_squared();
+        Self::new(
+            (self.re * other.re + self.im * other.im) * scale,
+            (self.im * other.re - self.re * other.im) * scale,
+        )
+    }
+}
+
+/// Cook-Torrance microfacet BSDF with a GGX distribution, in the metalness workflow. The dielectric part is
+/// a Lambertian base under a specular coat with exact dielectric Fresnel, the metallic part is specular only,
+/// tinted by the base color. An explicit complex index of refraction replaces the metalness workflow with
+/// an exact conductor (e.g. measured gold or copper).
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct MicrofacetBsdf {
+    pub base_color: Color,
+    pub roughness: f32,
+    pub metalness: f32,
+    /// Index of refraction of the dielectric part
+    pub ior: f32,
+    /// Complex index of refraction (eta, k) per color channel, for exact conductor Fresnel
+    pub conductor: Option<(Vec3A, Vec3A)>,
+}
+
+impl Default for MicrofacetBsdf {
+    fn default() -> Self {
+        Self {
+            base_color: Color::new_white(),
+            roughness: 0.5,
+            metalness: 0.0,
+            ior: 1.5,
+            conductor: None,
+        }
+    }
+}
+
+impl MicrofacetBsdf {
+    pub fn new(base_color: Color, roughness: f32, metalness: f32) -> Self {
+        Self {
+            base_color,
+            roughness,
+            metalness: metalness.clamp(0.0, 1.0),
+            ..Default::default()
+        }
+    }
+
+    pub fn new_conductor(eta: Vec3A, k: Vec3A, roughness: f32) -> Self {
+        Self {
+            roughness,
+            metalness: 1.0,
+            conductor: Some((eta, k)),
+            ..Default::default()
+        }
+    }
+
+    pub fn distribution(&self) -> GgxDistribution {
+        GgxDistribution::from_roughness(self.roughness)
+    }
+
+    fn fresnel(&self, cos_theta: f32) -> Color {
+        match self.conductor {
+            Some((eta, k)) => fresnel_conductor(cos_theta, eta, k),
+            None => {
+                let dielectric = fresnel_dielectric(cos_theta, self.ior);
+                let metallic = fresnel_schlick(cos_theta, self.base_color);
+                Color::new_color(dielectric, dielectric, dielectric) * (1.0 - self.metalness)
+                    + metallic * self.metalness
+            }
+        }
+    }
+
+    fn diffuse_weight(&self) -> f32 {
+        match self.conductor {
+            Some(_) => 0.0,
+            None => 1.0 - self.metalness,
+        }
+    }
+
+    /// Probability of sampling the specular lobe instead of the diffuse one.
+    fn specular_probability(&self) -> f32 {
+        1.0 - self.diffuse_weight() / 2.0
+    }
+
+    pub fn f(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new_black();
+        }
+        let wm = (wo + wi).normalize();
+        let distribution = self.distribution();
+        let specular = self.fresnel(wo.dot(wm)) * distribution.d(wm) * distribution.g(wo, wi)
+            / (4.0 * wo.z * wi.z);
+
+        // Light refracted through the specular coat scatters in the base
+        let transmitted = 1.0 - fresnel_dielectric(wi.z, self.ior);
+        let diffuse = self.base_color * (self.diffuse_weight() * transmitted * FRAC_1_PI);
+        specular + diffuse
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = (wo + wi).normalize();
+        let specular_pdf = self.distribution().visible_normal_pdf(wo, wm) / (4.0 * wo.dot(wm));
+        let specular_probability = self.specular_probability();
+        specular_probability * specular_pdf
+            + (1.0 - specular_probability) * cosine_hemisphere_pdf(wi.z)
+    }
+
+    /// Samples the specular lobe through visible normals or the diffuse lobe with cosine weighting.
+    pub fn sample(&self, wo: Vec3A, u_lobe: f32, u: Vec2) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let wi = if u_lobe < self.specular_probability() {
+            let wm = self.distribution().sample_visible_normal(wo, u);
+            -wo + wm * 2.0 * wo.dot(wm)
+        } else {
+            cosine_sample_hemisphere(u)
+        };
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        let pdf = self.pdf(wo, wi);
+        (pdf > 0.0).then(|| BsdfSample {
+            wi,
+            f: self.f(wo, wi),
+            pdf,
+            is_specular: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use std::f32::consts::FRAC_PI_2;
+
+    use crate::test_helpers::{integrate_directions, strata};
+
+    use super::*;
+
+    #[rstest]
+    #[case(0.3)]
+    #[case(0.6)]
+    #[case(1.0)]
+    fn projected_microfacet_area_is_normalized(#[case] roughness: f32) {
+        let distribution = GgxDistribution::from_roughness(roughness);
+        let projected_area = integrate_directions(FRAC_PI_2, |wm| distribution.d(wm) * wm.z);
+        assert_abs_diff_eq!(projected_area, 1.0, epsilon = 1e-2);
+    }
+
+    #[rstest]
+    #[case(0.5, Vec3A::new(0.0, 0.0, 1.0))]
+    #[case(0.8, Vec3A::new(0.6, 0.0, 0.8))]
+    fn visible_normal_distribution_is_normalized(#[case] roughness: f32, #[case] w: Vec3A) {
+        let distribution = GgxDistribution::from_roughness(roughness);
+        let total = integrate_directions(FRAC_PI_2, |wm| distribution.visible_normal_pdf(w, wm));
+        assert_abs_diff_eq!(total, 1.0, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn masking_is_one_towards_the_normal_and_decreases_at_grazing_angles() {
+        let distribution = GgxDistribution::from_roughness(0.7);
+        assert_abs_diff_eq!(distribution.g1(Vec3A::Z), 1.0);
+        let grazing = Vec3A::new(0.99, 0.0, 0.141).normalize();
+        assert!(distribution.g1(grazing) < 0.9);
+        assert!(
+            distribution.g(Vec3A::Z, grazing)
+                <= distribution.g1(Vec3A::Z) * distribution.g1(grazing)
+        );
+    }
+
+    #[test]
+    fn sampled_visible_normals_face_the_viewer() {
+        let distribution = GgxDistribution::from_roughness(0.8);
+        let w = Vec3A::new(0.8, 0.0, 0.6);
+        for u in strata(16) {
+            let wm = distribution.sample_visible_normal(w, u);
+            assert_abs_diff_eq!(wm.length(), 1.0, epsilon = 1e-5);
+            assert!(wm.z > 0.0);
+            assert!(w.dot(wm) >= -1e-5);
+        }
+    }
+
+    #[rstest]
+    #[case::normal_incidence(1.0, 1.5, 0.04)]
+    #[case::grazing(0.0, 1.5, 1.0)]
+    #[case::total_internal_reflection(-0.3, 1.5, 1.0)]
+    #[case::index_matched(0.7, 1.0, 0.0)]
+    fn dielectric_fresnel(#[case] cos_theta: f32, #[case] eta: f32, #[case] expected: f32) {
+        assert_abs_diff_eq!(fresnel_dielectric(cos_theta, eta), expected, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn conductor_fresnel_at_normal_incidence() {
+        // Gold at roughly 650, 550 and 450 nm
+        let eta = Vec3A::new(0.143, 0.374, 1.442);
+        let k = Vec3A::new(3.983, 2.385, 1.603);
+        let reflectance = fresnel_conductor(1.0, eta, k);
+        let expected =
+            |eta: f32, k: f32| ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+        assert_abs_diff_eq!(
+            reflectance.get_red_val(),
+            expected(eta.x, k.x),
+            epsilon = 1e-5
+        );
+        assert_abs_diff_eq!(
+            reflectance.get_blue_val(),
+            expected(eta.z, k.z),
+            epsilon = 1e-5
+        );
+        // Reflectance goes to one at grazing angles
+        assert_abs_diff_eq!(
+            fresnel_conductor(0.0, eta, k).get_blue_val(),
+            1.0,
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn schlick_fresnel_interpolates_to_white() {
+        let f0 = Color::new_color(0.9, 0.6, 0.2);
+        assert_eq!(fresnel_schlick(1.0, f0), f0);
+        assert_eq!(fresnel_schlick(0.0, f0), Color::new_white());
+    }
+
+    #[rstest]
+    #[case::plastic(MicrofacetBsdf::new(Color::new_color(0.2, 0.5, 0.8), 0.4, 0.0))]
+    #[case::rough_metal(MicrofacetBsdf::new(Color::new_color(0.9, 0.6, 0.2), 0.7, 1.0))]
+    #[case::half_metal(MicrofacetBsdf::new(Color::new_white(), 0.3, 0.5))]
+    fn sampling_matches_the_pdf_and_conserves_energy(#[case] bsdf: MicrofacetBsdf) {
+        let wo = Vec3A::new(0.5, 0.1, 0.8).normalize();
+        let mut sampled_albedo = 0.0;
+        let mut count = 0;
+        for (index, u) in strata(64).enumerate() {
+            let u_lobe = (index as f32 * 0.618034).fract();
+            if let Some(sample) = bsdf.sample(wo, u_lobe, u) {
+                assert_abs_diff_eq!(sample.pdf, bsdf.pdf(wo, sample.wi), epsilon = 1e-3);
+                sampled_albedo += sample.f.get_green_val() * sample.wi.z / sample.pdf;
+            }
+            count += 1;
+        }
+        let sampled_albedo = sampled_albedo / count as f32;
+
+        let integrated_albedo =
+            integrate_directions(FRAC_PI_2, |wi| bsdf.f(wo, wi).get_green_val() * wi.z);
+        let integrated_pdf = integrate_directions(FRAC_PI_2, |wi| bsdf.pdf(wo, wi));
+        // Some specular reflections leave below the horizon, so the pdf may integrate to less than one
+        assert!(integrated_pdf > 0.5 && integrated_pdf <= 1.02);
+        assert!(integrated_albedo <= 1.0);
+        assert_abs_diff_eq!(sampled_albedo, integrated_albedo, epsilon = 2e-2);
+    }
+
+    #[test]
+    fn smooth_white_metal_reflects_almost_everything() {
+        let bsdf = MicrofacetBsdf::new(Color::new_white(), 0.2, 1.0);
+        let wo = Vec3A::Z;
+        let albedo = integrate_directions(FRAC_PI_2, |wi| bsdf.f(wo, wi).get_red_val() * wi.z);
+        assert!(albedo > 0.95 && albedo <= 1.01);
+    }
+
+    #[test]
+    fn directions_below_the_surface_do_not_scatter() {
+        let bsdf = MicrofacetBsdf::default();
+        let below = Vec3A::new(0.0, 0.6, -0.8);
+        assert_eq!(bsdf.f(Vec3A::Z, below), Color::new_black());
+        assert_abs_diff_eq!(bsdf.pdf(Vec3A::Z, below), 0.0);
+        assert!(bsdf.sample(below, 0.5, Vec2::splat(0.5)).is_none());
+    }
+}
@@ -0,0 +1,235 @@
+use glam::{Affine3A, Quat, Vec2, Vec3, Vec3A};
+
+use crate::{
+    bump_map::TangentFrame,
+    intersection::{Intersections, SingleIntersection},
+    material::Material,
+    ray::Ray,
+    shape::Shape,
+};
+
+/// Transform that changes over time, interpolated between keyframes. Each keyframe is split into scale,
+/// rotation and translation, which are interpolated separately: scale and translation linearly and rotation
+/// along the shortest arc (slerp). Before the first and after the last keyframe the transform stays the same.
+/// Keyframes should not shear, shear is lost when they are split.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Keyframe {
+    time: f32,
+    scale: Vec3,
+    rotation: Quat,
+    translation: Vec3,
+}
+
+impl AnimatedTransform {
+    /// Keyframes are pairs of time and the transform at that time, in any order.
+    pub fn new(keyframes: Vec<(f32, Affine3A)>) -> Self {
+        if keyframes.is_empty() {
+            panic!("Animated transform should have at least one keyframe");
+        }
+
+        let mut keyframes: Vec<Keyframe> = keyframes
+            .into_iter()
+            .map(|(time, transform)| {
+                let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+                Keyframe {
+                    time,
+                    scale,
+                    rotation,
+                    translation,
+                }
+            })
+            .collect();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keyframes }
+    }
+
+    pub fn at(&self, time: f32) -> Affine3A {
+        let next_index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        let (previous, next) = match next_index {
+            0 => return self.keyframes[0].transform(),
+            index if index == self.keyframes.len() => return self.keyframes[index - 1].transform(),
+            index => (&self.keyframes[index - 1], &self.keyframes[index]),
+        };
+
+        let t = (time - previous.time) / (next.time - previous.time);
+        Affine3A::from_scale_rotation_translation(
+            previous.scale.lerp(next.scale, t),
+            previous.rotation.slerp(next.rotation, t),
+            previous.translation.lerp(next.translation, t),
+        )
+    }
+}
+
+impl Keyframe {
+    fn transform(&self) -> Affine3A {
+        Affine3A::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// Shape in motion, such as a spinning wheel or a moving vehicle, which is blurred over the camera shutter
+/// interval. The animated transform moves the shape on top of its own transform. Shapes in motion are sampled
+/// as emitters where they are at the time 0.0.
+#[derive(Debug, Clone)]
+pub struct MovingShape<S: Shape> {
+    shape: S,
+    motion: AnimatedTransform,
+    /// Transform at the time 0.0
+    transform: Affine3A,
+}
+
+impl<S: Shape> MovingShape<S> {
+    pub fn new(shape: S, motion: AnimatedTransform) -> Self {
+        let transform = motion.at(0.0) * *shape.transform();
+        Self {
+            shape,
+            motion,
+            transform,
+        }
+    }
+
+    pub fn shape(&self) -> &S {
+        &self.shape
+    }
+
+    pub fn motion(&self) -> &AnimatedTransform {
+        &self.motion
+    }
+}
+
+impl<S: Shape> Shape for MovingShape<S> {
+    fn id(&self) -> &String {
+        self.shape.id()
+    }
+
+    fn transform(&self) -> &Affine3A {
+        &self.transform
+    }
+
+    fn transform_at(&self, time: f32) -> Affine3A {
+        self.motion.at(time) * *self.shape.transform()
+    }
+
+    fn material(&self) -> &Material {
+        self.shape.material()
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections<'_> {
+        self.shape.local_intersect(local_ray)
+    }
+
+    fn local_normal_at(&self, local_point: Vec3A) -> Vec3A {
+        self.shape.local_normal_at(local_point)
+    }
+
+    fn local_uv_at(&self, local_point: Vec3A, hit: &SingleIntersection) -> Vec2 {
+        self.shape.local_uv_at(local_point, hit)
+    }
+
+    fn local_tangent_frame_at(&self, local_point: Vec3A, hit: &SingleIntersection) -> TangentFrame {
+        self.shape.local_tangent_frame_at(local_point, hit)
+    }
+
+    fn local_area(&self) -> f32 {
+        self.shape.local_area()
+    }
+
+    fn local_sample_point(&self, u: Vec2) -> Vec3A {
+        self.shape.local_sample_point(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use crate::sphere::Sphere;
+
+    use super::*;
+
+    #[fixture]
+    fn motion() -> AnimatedTransform {
+        AnimatedTransform::new(vec![
+            (
+                1.0,
+                Affine3A::from_scale_rotation_translation(
+                    Vec3::splat(3.0),
+                    Quat::from_rotation_y(2.0 * PI / 3.0),
+                    Vec3::new(4.0, 0.0, 0.0),
+                ),
+            ),
+            (0.0, Affine3A::IDENTITY),
+        ])
+    }
+
+    #[rstest]
+    #[case::before_the_first_keyframe(-1.0, Vec3::ONE, 0.0, Vec3::ZERO)]
+    #[case::halfway(0.5, Vec3::splat(2.0), PI / 3.0, Vec3::new(2.0, 0.0, 0.0))]
+    #[case::after_the_last_keyframe(2.0, Vec3::splat(3.0), 2.0 * PI / 3.0, Vec3::new(4.0, 0.0, 0.0))]
+    fn animated_transform_interpolates_between_keyframes(
+        motion: AnimatedTransform,
+        #[case] time: f32,
+        #[case] scale: Vec3,
+        #[case] angle: f32,
+        #[case] translation: Vec3,
+    ) {
+        let expected = Affine3A::from_scale_rotation_translation(
+            scale,
+            Quat::from_rotation_y(angle),
+            translation,
+        );
+        assert!(motion.at(time).abs_diff_eq(expected, 1e-4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Animated transform should have at least one keyframe")]
+    fn animated_transform_needs_a_keyframe() {
+        AnimatedTransform::new(vec![]);
+    }
+
+    #[rstest]
+    #[case::at_the_start(0.0, Some(4.0))]
+    #[case::moved_away(1.0, None)]
+    fn moving_shapes_are_intersected_where_they_are_at_the_time_of_the_ray(
+        #[case] time: f32,
+        #[case] expected_t: Option<f32>,
+    ) {
+        let motion = AnimatedTransform::new(vec![
+            (0.0, Affine3A::IDENTITY),
+            (1.0, Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0))),
+        ]);
+        let shape = MovingShape::new(Sphere::new(Vec3A::ZERO), motion);
+        let ray = Ray::new_at_time(Vec3A::new(0.0, 0.0, -5.0), Vec3A::Z, time);
+        let intersections = shape.intersect(ray);
+        let hit = intersections.hit();
+        assert_eq!(hit.map(|hit| hit.t), expected_t);
+        if let Some(hit) = hit {
+            assert_abs_diff_eq!(hit.time, time);
+        }
+    }
+
+    #[test]
+    fn normals_of_moving_shapes_follow_their_motion() {
+        let motion = AnimatedTransform::new(vec![
+            (0.0, Affine3A::IDENTITY),
+            (1.0, Affine3A::from_translation(Vec3::new(2.0, 0.0, 0.0))),
+        ]);
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_transform(Affine3A::from_scale(Vec3::splat(0.5)));
+        let shape = MovingShape::new(sphere, motion);
+        let normal = shape.normal_at_time(Vec3A::new(1.0, 0.5, 0.0), 0.5);
+        assert!(normal.abs_diff_eq(Vec3A::Y, 1e-5));
+        assert!(shape
+            .normal_at(Vec3A::new(0.5, 0.0, 0.0))
+            .abs_diff_eq(Vec3A::X, 1e-5));
+    }
+}
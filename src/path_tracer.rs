@@ -0,0 +1,981 @@
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::{
+    bsdf::Bsdf,
+    bump_map::TangentFrame,
+    color::Color,
+    emitter::Emitter,
+    integrator::Integrator,
+    intersection::Computations,
+    light::Light,
+    medium::{Medium, MediumSample},
+    photon_map::PhotonMap,
+    ray::Ray,
+    sampler::Sampler,
+    sampling::MisHeuristic,
+    world::World,
+};
+
+/// Unidirectional Monte Carlo path tracer. Unlike Whitted-style shading it follows diffuse bounces,
+/// so surfaces are lit by each other (indirect light, color bleeding).
+#[derive(Debug, PartialEq, Clone)]
+pub struct PathTracer {
+    /// Maximum number of bounces of a path
+    pub max_depth: u32,
+    /// Number of bounces after which paths are randomly terminated based on their throughput (Russian roulette)
+    pub russian_roulette_depth: u32,
+    /// Weighting of light and BSDF samples of direct lighting
+    pub heuristic: MisHeuristic,
+    /// Photons traced from the lights with `PhotonMap::trace_caustics`. Paths from a diffuse hit through smooth
+    /// mirrors and glass to an emitter are not counted then, the photons estimate that light instead.
+    pub caustics: Option<Arc<PhotonMap>>,
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        Self {
+            max_depth: 16,
+            russian_roulette_depth: 3,
+            heuristic: MisHeuristic::Power,
+            caustics: None,
+        }
+    }
+}
+
+impl PathTracer {
+    pub fn new(max_depth: u32, russian_roulette_depth: u32) -> Self {
+        Self {
+            max_depth,
+            russian_roulette_depth,
+            ..Default::default()
+        }
+    }
+
+    /// Next-event estimation, one sample of an emitter chosen by its power with a shadow ray towards it. Samples
+    /// of all but delta lights are weighted against sampling the scattering, which can find the same emitter.
+    /// `scattering` gives the fraction of light arriving from a direction that is scattered towards the viewer,
+    /// with the cosine term at surfaces, and the density of sampling the direction. `origin` gives the start
+    /// of the shadow ray towards a direction and the medium it starts in, `None` if light cannot arrive from it.
+    /// The shadow ray travels at the time of the path.
+    fn direct_lighting<'a>(
+        &self,
+        world: &'a World,
+        point: Vec3A,
+        time: f32,
+        scattering: impl Fn(Vec3A) -> (Color, f32),
+        origin: impl Fn(Vec3A) -> Option<(Vec3A, Option<&'a Medium>)>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let u_emitter = sampler.next_1d();
+        let u = sampler.next_2d();
+        let (emitter, pmf) = match world.sample_emitter(u_emitter) {
+            Some(chosen) => chosen,
+            None => return Color::new_black(),
+        };
+        let sample = match emitter.sample_li(point, u) {
+            Some(sample) => sample,
+            None => return Color::new_black(),
+        };
+        let (origin, medium) = match origin(sample.direction) {
+            Some(origin) => origin,
+            None => return Color::new_black(),
+        };
+        let shadow_ray = Ray::new_at_time(origin, sample.direction, time);
+        let transmittance = world.transmittance(shadow_ray, sample.distance, medium, sampler);
+        if transmittance == Color::new_black() {
+            return Color::new_black();
+        }
+
+        let (f, scattering_pdf) = scattering(sample.direction);
+        let pdf = sample.pdf * pmf;
+        let weight = if emitter.is_delta() {
+            1.0
+        } else {
+            self.heuristic.weight(pdf, scattering_pdf)
+        };
+        f * sample.radiance * transmittance * (weight / pdf)
+    }
+
+    /// Weight of light found by a ray sampled from a BSDF with the pdf, against sampling the emitter directly.
+    fn emitter_weight(
+        &self,
+        world: &World,
+        emitter: Emitter,
+        ray: Ray,
+        bsdf_pdf: Option<f32>,
+    ) -> f32 {
+        match bsdf_pdf {
+            Some(pdf) => self.heuristic.weight(
+                pdf,
+                world.emitter_pmf(emitter) * emitter.pdf_li(ray.origin_point, ray.direction_vector),
+            ),
+            None => 1.0,
+        }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, world: &World, camera_ray: Ray, sampler: &mut dyn Sampler) -> Color {
+        let mut radiance = Color::new_black();
+        let mut throughput = Color::new_white();
+        let mut ray = camera_ray;
+        // The camera is outside of all objects
+        let mut medium = world.atmosphere();
+        // Pdf of the BSDF sample that continued the path, `None` for camera rays and perfect mirror bounces,
+        // light they find could not have been sampled directly
+        let mut bsdf_pdf = None;
+        // The path went through a diffuse hit, then only through smooth mirrors and glass since the last one,
+        // the light it finds is a caustic
+        let mut diffuse_seen = false;
+        let mut caustic_path = false;
+
+        for depth in 0..=self.max_depth {
+            let counts_emission = self.caustics.is_none() || !caustic_path;
+            let comps = match next_interaction(world, ray, &mut medium, &mut throughput, sampler) {
+                Interaction::AreaLight(light) => {
+                    if counts_emission {
+                        radiance += throughput
+                            * light.intensity()
+                            * self.emitter_weight(world, Emitter::Light(light), ray, bsdf_pdf);
+                    }
+                    break;
+                }
+                Interaction::Escaped => {
+                    // The background is only sampled directly when it is the environment light
+                    let weight = world.environment_light().map_or(1.0, |light| {
+                        self.emitter_weight(world, Emitter::Light(light), ray, bsdf_pdf)
+                    });
+                    radiance += throughput
+                        * world.background().color_for_direction(ray.direction_vector)
+                        * weight;
+                    break;
+                }
+                Interaction::Absorbed => break,
+                Interaction::Medium(point) => {
+                    if depth == self.max_depth {
+                        break;
+                    }
+                    let phase = medium.expect("should scatter in a medium").phase;
+                    let wo = -ray.direction_vector;
+                    // Photons are only stored on surfaces, light scattered in media is path traced
+                    diffuse_seen = false;
+                    caustic_path = false;
+                    radiance += throughput
+                        * self.direct_lighting(
+                            world,
+                            point,
+                            ray.time,
+                            |wi| {
+                                let p = phase.p(wo, wi);
+                                (Color::new_color(p, p, p), p)
+                            },
+                            |_| Some((point, medium)),
+                            sampler,
+                        );
+
+                    // The phase function is sampled exactly, the throughput does not change
+                    let (wi, pdf) = phase.sample(wo, sampler.next_2d());
+                    ray = ray.spawn(point, wi);
+                    bsdf_pdf = Some(pdf);
+                    None
+                }
+                Interaction::Surface(comps) => Some(comps),
+            };
+
+            if let Some(comps) = comps {
+                let object = world
+                    .object_by_id(&comps.object_id)
+                    .expect("should find the object that was hit in the world");
+                let material = object.material();
+
+                if material.emissive != Color::new_black() && counts_emission {
+                    radiance += throughput
+                        * material.emissive
+                        * self.emitter_weight(world, Emitter::Object(object), ray, bsdf_pdf);
+                }
+                if depth == self.max_depth {
+                    break;
+                }
+
+                let mirror = match &material.subsurface {
+                    // Light reflected by the smooth surface does not enter the object
+                    Some(subsurface) => {
+                        let cos_theta = comps.eye_vector.dot(comps.normal_vector);
+                        sampler.next_1d() < subsurface.reflectance(cos_theta)
+                    }
+                    None => material.bsdf.is_none() && sampler.next_1d() < material.reflective,
+                };
+                if mirror {
+                    // Perfect mirror, the whole throughput carries on
+                    ray = ray.spawn(comps.over_point, comps.reflect_vector);
+                    bsdf_pdf = None;
+                    caustic_path = diffuse_seen;
+                } else {
+                    let (comps, bsdf) = match &material.subsurface {
+                        // The path goes on from where the walk leaves the object, as from a white diffuse surface
+                        Some(subsurface) => match subsurface.random_walk(world, &comps, sampler) {
+                            Some((exit, weight)) => {
+                                throughput = throughput * weight;
+                                (
+                                    exit,
+                                    Bsdf::Lambertian {
+                                        albedo: Color::new_white(),
+                                    },
+                                )
+                            }
+                            None => break,
+                        },
+                        None => {
+                            let bsdf = match &material.bsdf {
+                                Some(bsdf) if comps.inside => bsdf.seen_from_inside(),
+                                Some(bsdf) => *bsdf,
+                                None => Bsdf::Lambertian {
+                                    albedo: material.color * material.diffuse,
+                                },
+                            };
+                            (comps, bsdf)
+                        }
+                    };
+                    let frame = TangentFrame::from_normal(comps.normal_vector);
+                    let wo = frame.world_to_local(comps.eye_vector);
+                    if bsdf.is_near_specular() {
+                        caustic_path = diffuse_seen;
+                    } else {
+                        diffuse_seen = true;
+                        caustic_path = false;
+                        if let Some(caustics) = &self.caustics {
+                            radiance += throughput
+                                * caustics.estimate_radiance(comps.point, |direction| {
+                                    bsdf.f(wo, frame.world_to_local(direction))
+                                });
+                        }
+                    }
+                    // Light transmitted through the surface comes from the medium on its other side
+                    let medium_across = world.medium_across(object, &comps);
+                    // Direct light through smooth mirrors and glass is a caustic too
+                    if !(self.caustics.is_some() && caustic_path) {
+                        radiance += throughput
+                            * self.direct_lighting(
+                                world,
+                                comps.over_point,
+                                comps.time,
+                                |direction| {
+                                    let wi = frame.world_to_local(direction);
+                                    (bsdf.f(wo, wi) * wi.z.abs(), bsdf.pdf(wo, wi))
+                                },
+                                |direction| {
+                                    let wi_z = frame.world_to_local(direction).z;
+                                    if wi_z < 0.0 {
+                                        Some((comps.under_point, medium_across))
+                                    } else if wi_z > 0.0 {
+                                        Some((comps.over_point, medium))
+                                    } else {
+                                        None
+                                    }
+                                },
+                                sampler,
+                            );
+                    }
+
+                    let u_lobe = sampler.next_1d();
+                    let sample = match bsdf.sample(wo, u_lobe, sampler.next_2d()) {
+                        Some(sample) => sample,
+                        None => break,
+                    };
+                    let direction = frame.local_to_world(sample.wi);
+                    // With shading normals the sampled direction may end up on the other side of the geometric surface
+                    let transmitted = sample.wi.z < 0.0;
+                    if (direction.dot(comps.geometric_normal_vector) < 0.0) != transmitted {
+                        break;
+                    }
+                    let weight = if sample.is_specular {
+                        sample.wi.z.abs()
+                    } else {
+                        sample.wi.z.abs() / sample.pdf
+                    };
+                    throughput = throughput * sample.f * weight;
+                    let origin = if transmitted {
+                        medium = medium_across;
+                        comps.under_point
+                    } else {
+                        comps.over_point
+                    };
+                    ray = ray.spawn(origin, direction);
+                    bsdf_pdf = (!sample.is_specular).then_some(sample.pdf);
+                }
+            }
+
+            if depth + 1 >= self.russian_roulette_depth {
+                let survival_probability = throughput.max_component().min(0.95);
+                if sampler.next_1d() >= survival_probability {
+                    break;
+                }
+                throughput = throughput / survival_probability;
+            }
+        }
+
+        radiance
+    }
+}
+
+/// What a ray of a path reaches first.
+enum Interaction<'a> {
+    AreaLight(&'a Light),
+    Surface(Computations),
+    /// Scattered in the medium at the point
+    Medium(Vec3A),
+    Absorbed,
+    /// Left the world
+    Escaped,
+}
+
+/// Follows the ray through the media it travels in, passing through boundaries of media, and multiplies
+/// the throughput by the weights of the media samples. The medium is updated as boundaries are crossed.
+/// Scattering distances are sampled with delta tracking.
+fn next_interaction<'a>(
+    world: &'a World,
+    ray: Ray,
+    medium: &mut Option<&'a Medium>,
+    throughput: &mut Color,
+    sampler: &mut dyn Sampler,
+) -> Interaction<'a> {
+    let mut ray = ray;
+    loop {
+        let hit = world.hit_computations(ray);
+        let hit_distance = hit.as_ref().map_or(f32::INFINITY, |comps| comps.t);
+        // Area lights are not part of the world's geometry, paths end on them
+        let area_light = closest_area_light(world, ray, hit_distance);
+        let distance = area_light.map_or(hit_distance, |(_, distance)| distance);
+        if let Some(current) = medium.filter(|_| distance.is_finite()) {
+            match current.sample_distance(ray, distance, sampler) {
+                MediumSample::Scattered { distance, weight } => {
+                    *throughput = *throughput * weight;
+                    return Interaction::Medium(ray.position(distance));
+                }
+                MediumSample::Absorbed => return Interaction::Absorbed,
+                MediumSample::Transmitted { weight } => *throughput = *throughput * weight,
+            }
+        }
+
+        if let Some((light, _)) = area_light {
+            return Interaction::AreaLight(light);
+        }
+        let comps = match hit {
+            Some(comps) => comps,
+            None => return Interaction::Escaped,
+        };
+        let object = world
+            .object_by_id(&comps.object_id)
+            .expect("should find the object that was hit in the world");
+        if !object.material().is_medium_boundary() {
+            return Interaction::Surface(comps);
+        }
+        *medium = world.medium_across(object, &comps);
+        ray = ray.spawn(comps.under_point, ray.direction_vector);
+    }
+}
+
+/// Area light hit by the ray before the distance with the distance to it, if any.
+pub fn closest_area_light(world: &World, ray: Ray, max_distance: f32) -> Option<(&Light, f32)> {
+    world
+        .lights()
+        .iter()
+        .filter_map(|light| match light {
+            Light::Area(area_light) => area_light
+                .intersect(ray)
+                .filter(|&distance| distance < max_distance)
+                .map(|distance| (light, distance)),
+            _ => None,
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_1_PI, PI};
+
+    use approx::assert_abs_diff_eq;
+    use glam::{Affine3A, Vec3, Vec3A};
+    use rstest::*;
+
+    use crate::{
+        background::Background,
+        light::{AreaLight, EnvironmentLight, PointLight},
+        material::Material,
+        medium::Medium,
+        microfacet::MicrofacetBsdf,
+        principled::Principled,
+        quad::Quad,
+        sampler::IndependentSampler,
+        sampling::uniform_sample_sphere,
+        shape::Shape,
+        sphere::Sphere,
+        subsurface::Subsurface,
+        test_helpers::{average_radiance, floor_world, lambertian, strata, sunny_panorama},
+        texture::ImageTexture,
+        triangle::Triangle,
+    };
+
+    use super::*;
+
+    #[test]
+    fn rays_that_miss_return_the_background() {
+        let mut world = World::new_empty();
+        world.set_background(Background::SolidColor(Color::new_blue()));
+        let ray = Ray::new(Vec3A::ZERO, Vec3A::new(0.0, 0.0, 1.0));
+        let mut sampler = IndependentSampler::new(1, 0);
+        assert_eq!(
+            PathTracer::default().radiance(&world, ray, &mut sampler),
+            Color::new_blue()
+        );
+    }
+
+    #[test]
+    fn emissive_surfaces_are_visible_without_lights() {
+        let mut world = World::new_empty();
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(Material {
+            color: Color::new_black(),
+            emissive: Color::new_red(),
+            ..Default::default()
+        });
+        world.add_object(sphere);
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::default(), &world, ray, 4);
+        assert_eq!(radiance, Color::new_red());
+    }
+
+    #[test]
+    fn next_event_estimation_gives_lambertian_direct_light() {
+        let mut world = World::new_empty();
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(lambertian(0.5));
+        world.add_object(sphere);
+        world.add_light(PointLight::new(
+            Vec3A::new(0.0, 0.0, -10.0),
+            Color::new_white(),
+        ));
+
+        // Single bounce, only the direct light towards the point light is gathered
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::new(1, 1), &world, ray, 1);
+        assert_abs_diff_eq!(radiance.get_red_val(), 0.5 * FRAC_1_PI, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn next_event_estimation_evaluates_the_bsdf() {
+        let bsdf = Bsdf::Microfacet(MicrofacetBsdf::new(
+            Color::new_color(0.2, 0.5, 0.8),
+            0.4,
+            0.0,
+        ));
+        let mut world = World::new_empty();
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(Material {
+            bsdf: Some(bsdf),
+            ..Default::default()
+        });
+        world.add_object(sphere);
+        world.add_light(PointLight::new(
+            Vec3A::new(0.0, 10.0, -10.0),
+            Color::new_white(),
+        ));
+
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::new(1, 1), &world, ray, 1);
+        // Normal towards the eye, the BSDF is isotropic so only the angle to the light matters
+        let wi = Vec3A::new(0.0, 10.0, 9.0).normalize();
+        let expected = bsdf.f(Vec3A::Z, wi) * wi.z;
+        assert_abs_diff_eq!(
+            radiance.get_red_val(),
+            expected.get_red_val(),
+            epsilon = 1e-4
+        );
+        assert_abs_diff_eq!(
+            radiance.get_blue_val(),
+            expected.get_blue_val(),
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn light_passes_through_a_glass_sphere() {
+        let mut world = World::new_empty();
+        world.set_background(Background::SolidColor(Color::new_blue()));
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(Material::from(Principled {
+            base_color: Color::new_white(),
+            roughness: 0.0,
+            transmission: 1.0,
+            ..Default::default()
+        }));
+        world.add_object(sphere);
+
+        // Every path, refracted or reflected, ends up in the background
+        let ray = Ray::new(Vec3A::new(0.0, 0.2, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::default(), &world, ray, 256);
+        assert_abs_diff_eq!(radiance.get_red_val(), 0.0);
+        assert_abs_diff_eq!(radiance.get_blue_val(), 1.0, epsilon = 0.05);
+    }
+
+    #[test]
+    fn furnace_test_with_a_metal_stays_below_the_emission_series() {
+        // Smooth white metal reflects almost everything, rough metal loses energy to single scattering
+        let mut world = World::new_empty();
+        let mut enclosure = Sphere::new(Vec3A::ZERO);
+        enclosure.set_transform(Affine3A::from_scale(Vec3::splat(10.0)));
+        enclosure.set_material(Material {
+            emissive: Color::new_color(0.1, 0.1, 0.1),
+            bsdf: Some(Bsdf::Microfacet(MicrofacetBsdf::new(
+                Color::new_color(0.5, 0.5, 0.5),
+                0.3,
+                1.0,
+            ))),
+            ..Default::default()
+        });
+        world.add_object(enclosure);
+
+        let ray = Ray::new(Vec3A::ZERO, Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::new(64, 3), &world, ray, 500);
+        // Reflectance is between 0.5 at normal incidence and 1.0 at grazing angles
+        assert!(radiance.get_red_val() > 0.15 && radiance.get_red_val() < 0.25);
+    }
+
+    #[test]
+    fn furnace_test_converges_to_the_geometric_series() {
+        // Inside a closed sphere of uniform emission and albedo, radiance is emission / (1 - albedo)
+        let mut world = World::new_empty();
+        let mut enclosure = Sphere::new(Vec3A::ZERO);
+        enclosure.set_transform(Affine3A::from_scale(Vec3::splat(10.0)));
+        enclosure.set_material(Material {
+            emissive: Color::new_color(0.5, 0.5, 0.5),
+            ..lambertian(0.5)
+        });
+        world.add_object(enclosure);
+
+        let ray = Ray::new(Vec3A::ZERO, Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::new(64, 3), &world, ray, 2000);
+        assert_abs_diff_eq!(radiance.get_red_val(), 1.0, epsilon = 0.05);
+    }
+
+    #[test]
+    fn indirect_light_bleeds_color_between_surfaces() {
+        // Floor point in the shadow of a small sphere, next to a lit red wall
+        let mut world = World::new_empty();
+        let mut floor = Triangle::new(
+            Vec3A::new(-20.0, -1.0, -10.0),
+            Vec3A::new(20.0, -1.0, -10.0),
+            Vec3A::new(0.0, -1.0, 30.0),
+        );
+        floor.set_material(lambertian(0.8));
+        let mut wall = Triangle::new(
+            Vec3A::new(-20.0, -2.0, 2.0),
+            Vec3A::new(20.0, -2.0, 2.0),
+            Vec3A::new(0.0, 30.0, 2.0),
+        );
+        wall.set_material(Material {
+            color: Color::new_red(),
+            ..lambertian(0.9)
+        });
+        let mut occluder = Sphere::new(Vec3A::ZERO);
+        occluder.set_transform(
+            Affine3A::from_translation(Vec3::new(0.0, 3.0, 0.0))
+                * Affine3A::from_scale(Vec3::splat(0.5)),
+        );
+        occluder.set_material(lambertian(0.0));
+        world.add_object(floor);
+        world.add_object(wall);
+        world.add_object(occluder);
+        world.add_light(PointLight::new(
+            Vec3A::new(0.0, 5.0, 0.0),
+            Color::new_white(),
+        ));
+
+        let ray = Ray::new(
+            Vec3A::new(0.0, 1.0, -2.0),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let direct_only = average_radiance(&PathTracer::new(1, 1), &world, ray, 64);
+        let global = average_radiance(&PathTracer::default(), &world, ray, 256);
+        assert_eq!(direct_only, Color::new_black());
+        assert!(global.get_red_val() > 0.0);
+        assert!(global.get_red_val() > global.get_green_val());
+    }
+
+    /// Square area light of the half size, centered 1 unit above the origin.
+    fn square_light(half_size: f32) -> AreaLight {
+        AreaLight::new(
+            Vec3A::new(-half_size, 1.0, -half_size),
+            Vec3A::new(2.0 * half_size, 0.0, 0.0),
+            1,
+            Vec3A::new(0.0, 0.0, 2.0 * half_size),
+            1,
+            Color::new_white(),
+        )
+    }
+
+    /// Irradiance at the origin from a square light of unit radiance, centered 1 unit above it.
+    fn square_light_irradiance(half_size: f32) -> f32 {
+        let x = half_size / (1.0 + half_size * half_size).sqrt();
+        4.0 * x * x.atan()
+    }
+
+    #[rstest]
+    #[case::small_light_balance(0.1, MisHeuristic::Balance)]
+    #[case::small_light_power(0.1, MisHeuristic::Power)]
+    #[case::large_light_balance(5.0, MisHeuristic::Balance)]
+    #[case::large_light_power(5.0, MisHeuristic::Power)]
+    fn direct_light_from_area_lights_is_unbiased(
+        #[from(floor_world)] mut world: World,
+        #[case] half_size: f32,
+        #[case] heuristic: MisHeuristic,
+    ) {
+        world.add_light(square_light(half_size));
+        let path_tracer = PathTracer {
+            heuristic,
+            ..PathTracer::new(1, 2)
+        };
+        let ray = Ray::new(
+            Vec3A::new(0.0, 0.5, -0.5),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let radiance = average_radiance(&path_tracer, &world, ray, 4000);
+        let expected = 0.5 * FRAC_1_PI * square_light_irradiance(half_size);
+        assert_abs_diff_eq!(radiance.get_red_val(), expected, epsilon = expected * 0.05);
+    }
+
+    #[rstest]
+    fn glossy_surfaces_under_a_large_light_converge(
+        #[with(Material {
+            bsdf: Some(Bsdf::Microfacet(MicrofacetBsdf::new(Color::new_white(), 0.3, 1.0))),
+            ..Default::default()
+        })]
+        #[from(floor_world)]
+        mut world: World,
+    ) {
+        world.add_light(square_light(5.0));
+        let ray = Ray::new(
+            Vec3A::new(0.0, 0.5, -0.5),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        // The mirror-like reflection of the eye ray hits the light, most of the lobe sees it
+        let radiance = average_radiance(&PathTracer::new(1, 2), &world, ray, 2000);
+        assert!(radiance.get_red_val() > 0.8 && radiance.get_red_val() <= 1.0);
+    }
+
+    #[rstest]
+    fn many_lights_are_sampled_one_at_a_time(#[from(floor_world)] mut world: World) {
+        let mut irradiance = 0.0;
+        for i in 0..50 {
+            let position = Vec3A::new((i % 10) as f32 - 4.5, 2.0, (i / 10) as f32 - 2.0);
+            let intensity = 0.01 * (i % 7 + 1) as f32;
+            world.add_light(PointLight::new(
+                position,
+                Color::new_color(intensity, intensity, intensity),
+            ));
+            irradiance += intensity * position.normalize().y;
+        }
+
+        let ray = Ray::new(
+            Vec3A::new(0.0, 1.0, -1.0),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let radiance = average_radiance(&PathTracer::new(1, 2), &world, ray, 4000);
+        let expected = 0.5 * FRAC_1_PI * irradiance;
+        assert_abs_diff_eq!(radiance.get_red_val(), expected, epsilon = expected * 0.03);
+    }
+
+    #[rstest]
+    #[case::sampled_as_light(true)]
+    #[case::found_by_bsdf_sampling_only(false)]
+    fn environment_light_lights_the_floor(
+        #[from(floor_world)] mut world: World,
+        #[case] as_light: bool,
+    ) {
+        let sky = Background::SolidColor(Color::new_white());
+        if as_light {
+            world.add_light(EnvironmentLight::new(sky));
+        } else {
+            world.set_background(sky);
+        }
+
+        // Irradiance from a uniform sky over the hemisphere is π times its radiance
+        let ray = Ray::new(
+            Vec3A::new(0.0, 1.0, -1.0),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let radiance = average_radiance(&PathTracer::new(1, 2), &world, ray, 2000);
+        assert_abs_diff_eq!(radiance.get_red_val(), 0.5, epsilon = 0.02);
+    }
+
+    #[rstest]
+    fn environment_panorama_lights_the_floor(
+        #[from(floor_world)] mut world: World,
+        sunny_panorama: ImageTexture,
+    ) {
+        // Dim sky with a small bright sun, hard to find by sampling the BSDF alone
+        let sky = Background::Equirectangular(sunny_panorama);
+        world.add_light(EnvironmentLight::new(sky.clone()));
+
+        // Reflected radiance is albedo / π times the irradiance, integrated over the upper hemisphere
+        let irradiance = strata(512)
+            .map(|u| {
+                let direction = uniform_sample_sphere(u);
+                sky.color_for_direction(direction).get_red_val() * direction.y.max(0.0)
+            })
+            .sum::<f32>()
+            * 4.0
+            * PI
+            / (512 * 512) as f32;
+        let expected = 0.5 * FRAC_1_PI * irradiance;
+
+        let ray = Ray::new(
+            Vec3A::new(0.0, 1.0, -1.0),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let radiance = average_radiance(&PathTracer::new(1, 2), &world, ray, 2000);
+        assert_abs_diff_eq!(radiance.get_red_val(), expected, epsilon = expected * 0.03);
+    }
+
+    #[rstest]
+    #[case::path_traced_only(false, 4.0)]
+    #[case::with_caustic_photons(true, 4.0 + 1.0 / 1.5_f32.powi(2))]
+    fn mirrors_focus_light_of_point_lights_into_caustics(
+        #[from(floor_world)] mut world: World,
+        #[case] with_caustics: bool,
+        #[case] expected_irradiance: f32,
+    ) {
+        let mut mirror = Quad::new(
+            Vec3A::new(-5.0, 1.0, -5.0),
+            Vec3A::new(10.0, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, 10.0),
+        );
+        mirror.set_material(Material {
+            color: Color::new_black(),
+            reflective: 1.0,
+            ..Default::default()
+        });
+        world.add_object(mirror);
+        let mut light = PointLight::new(Vec3A::new(0.0, 0.5, 0.0), Color::new_white());
+        light.set_inverse_square_attenuation(true);
+        world.add_light(light);
+
+        // The reflection of the light is 1.5 away from the floor, path tracing never finds the point light in the
+        // mirror
+        let path_tracer = PathTracer {
+            caustics: with_caustics.then(|| Arc::new(PhotonMap::trace_caustics(&world, 100_000))),
+            ..PathTracer::new(1, 2)
+        };
+        let ray = Ray::new(
+            Vec3A::new(0.0, 0.3, -0.3),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let radiance = average_radiance(&path_tracer, &world, ray, 16);
+        let expected = 0.5 * FRAC_1_PI * expected_irradiance;
+        assert_abs_diff_eq!(radiance.get_red_val(), expected, epsilon = expected * 0.02);
+    }
+
+    /// Material of an emissive object that does not reflect any light.
+    fn white_emitter() -> Material {
+        Material {
+            color: Color::new_black(),
+            emissive: Color::new_white(),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case::balance(MisHeuristic::Balance)]
+    #[case::power(MisHeuristic::Power)]
+    fn emissive_panels_light_the_scene(
+        #[from(floor_world)] mut world: World,
+        #[case] heuristic: MisHeuristic,
+    ) {
+        // Same square as the area light of the same size, but as geometry
+        let mut panel = Quad::new(
+            Vec3A::new(-0.5, 1.0, -0.5),
+            Vec3A::new(0.0, 0.0, 1.0),
+            Vec3A::new(1.0, 0.0, 0.0),
+        );
+        panel.set_material(white_emitter());
+        world.add_object(panel);
+        let path_tracer = PathTracer {
+            heuristic,
+            ..PathTracer::new(1, 2)
+        };
+        let ray = Ray::new(
+            Vec3A::new(0.0, 0.5, -0.5),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let radiance = average_radiance(&path_tracer, &world, ray, 4000);
+        let expected = 0.5 * FRAC_1_PI * square_light_irradiance(0.5);
+        assert_abs_diff_eq!(radiance.get_red_val(), expected, epsilon = expected * 0.05);
+    }
+
+    #[rstest]
+    fn emissive_spheres_light_the_scene(#[from(floor_world)] mut world: World) {
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_transform(
+            Affine3A::from_translation(Vec3::new(0.0, 2.0, 0.0))
+                * Affine3A::from_scale(Vec3::splat(0.5)),
+        );
+        sphere.set_material(white_emitter());
+        world.add_object(sphere);
+        let ray = Ray::new(
+            Vec3A::new(0.0, 0.5, -0.5),
+            Vec3A::new(0.0, -1.0, 1.0).normalize(),
+        );
+        let radiance = average_radiance(&PathTracer::new(1, 2), &world, ray, 8000);
+        // Irradiance from a sphere of unit radiance directly above the point is π (radius / distance)²
+        let expected = 0.5 * FRAC_1_PI * PI * 0.25_f32.powi(2);
+        assert_abs_diff_eq!(radiance.get_red_val(), expected, epsilon = expected * 0.05);
+    }
+
+    fn gray(value: f32) -> Color {
+        Color::new_color(value, value, value)
+    }
+
+    #[test]
+    fn boundaries_of_media_are_invisible() {
+        let mut world = World::new_empty();
+        world.set_background(Background::SolidColor(Color::new_blue()));
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(Material {
+            medium: Some(Medium::new(Color::new_black(), Color::new_black(), 0.0)),
+            ..Default::default()
+        });
+        world.add_object(sphere);
+
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::default(), &world, ray, 4);
+        assert_eq!(radiance, Color::new_blue());
+    }
+
+    #[rstest]
+    #[case(1.0)]
+    #[case(5.0)]
+    fn fog_attenuates_light_with_distance(#[case] distance: f32) {
+        let mut world = World::new_empty();
+        world.set_atmosphere(Medium::new(gray(0.2), Color::new_black(), 0.0));
+        let mut panel = Quad::new(
+            Vec3A::new(-10.0, -10.0, distance),
+            Vec3A::new(20.0, 0.0, 0.0),
+            Vec3A::new(0.0, 20.0, 0.0),
+        );
+        panel.set_material(white_emitter());
+        world.add_object(panel);
+
+        let ray = Ray::new(Vec3A::ZERO, Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::default(), &world, ray, 2000);
+        let expected = (-0.2 * distance).exp();
+        assert_abs_diff_eq!(radiance.get_red_val(), expected, epsilon = expected * 0.05);
+    }
+
+    #[rstest]
+    #[case::isotropic(0.0)]
+    #[case::forward(0.6)]
+    fn media_that_do_not_absorb_conserve_light_of_a_white_environment(#[case] g: f32) {
+        let mut world = World::new_empty();
+        world.add_light(EnvironmentLight::new(Background::SolidColor(
+            Color::new_white(),
+        )));
+        let mut cloud = Sphere::new(Vec3A::ZERO);
+        cloud.set_material(Material {
+            medium: Some(Medium::new(Color::new_black(), gray(2.0), g)),
+            ..Default::default()
+        });
+        world.add_object(cloud);
+
+        // Scattered or not, all light reaching the eye comes from the environment
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::new(64, 8), &world, ray, 1000);
+        assert_abs_diff_eq!(radiance.get_red_val(), 1.0, epsilon = 0.03);
+    }
+
+    /// Octahedron with its corners on the axes at the distance of 1, made of triangles facing outwards.
+    fn octahedron(material: Material) -> Vec<Triangle> {
+        let corners = [
+            Vec3A::X,
+            Vec3A::Y,
+            Vec3A::Z,
+            -Vec3A::X,
+            -Vec3A::Y,
+            -Vec3A::Z,
+        ];
+        let mut faces = Vec::new();
+        for x in [0, 3] {
+            for y in [1, 4] {
+                for z in [2, 5] {
+                    let (a, b, c) = (corners[x], corners[y], corners[z]);
+                    let mut face = Triangle::new(a, b, c);
+                    let centroid = (a + b + c) / 3.0;
+                    if face.normal_at(centroid).dot(centroid) < 0.0 {
+                        face = Triangle::new(a, c, b);
+                    }
+                    face.set_material(material.clone());
+                    faces.push(face);
+                }
+            }
+        }
+        faces
+    }
+
+    #[rstest]
+    #[case::sphere(false)]
+    #[case::mesh(true)]
+    fn subsurface_scattering_without_absorption_conserves_light_of_a_white_environment(
+        #[case] mesh: bool,
+    ) {
+        let mut world = World::new_empty();
+        world.add_light(EnvironmentLight::new(Background::SolidColor(
+            Color::new_white(),
+        )));
+        let material = Material {
+            subsurface: Some(Subsurface {
+                albedo: Color::new_white(),
+                mean_free_path: gray(0.2),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        if mesh {
+            for face in octahedron(material) {
+                world.add_object(face);
+            }
+        } else {
+            let mut sphere = Sphere::new(Vec3A::ZERO);
+            sphere.set_material(material);
+            world.add_object(sphere);
+        }
+
+        let ray = Ray::new(Vec3A::new(0.1, 0.2, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::new(8, 8), &world, ray, 500);
+        assert_abs_diff_eq!(radiance.get_red_val(), 1.0, epsilon = 0.03);
+    }
+
+    #[test]
+    fn subsurface_albedo_colors_the_light_leaving_the_object() {
+        let mut world = World::new_empty();
+        world.add_light(EnvironmentLight::new(Background::SolidColor(
+            Color::new_white(),
+        )));
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(Material {
+            subsurface: Some(Subsurface {
+                albedo: Color::new_color(0.9, 0.5, 0.1),
+                mean_free_path: gray(0.1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        world.add_object(sphere);
+
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let radiance = average_radiance(&PathTracer::new(8, 8), &world, ray, 500);
+        assert!(radiance.get_red_val() > radiance.get_green_val());
+        assert!(radiance.get_green_val() > radiance.get_blue_val());
+        assert!(radiance.get_red_val() < 1.0);
+    }
+}
@@ -0,0 +1,497 @@
+use std::{cmp::Ordering, collections::BinaryHeap, f32::consts::PI, fmt};
+
+use glam::Vec3A;
+use log::info;
+use rayon::prelude::*;
+
+use crate::{
+    bump_map::TangentFrame,
+    color::Color,
+    ray::Ray,
+    sampler::{IndependentSampler, Sampler},
+    world::World,
+};
+
+/// Photons are dropped after this many specular bounces.
+const MAX_PHOTON_BOUNCES: u32 = 16;
+
+/// Light arriving at a surface, traced from a light.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Photon {
+    pub position: Vec3A,
+    /// Unit vector pointing back towards where the photon came from
+    pub direction: Vec3A,
+    pub power: Color,
+}
+
+/// Photons stored in a balanced kd-tree for finding the nearest ones to a point. The tree is implicit: the
+/// median photon of a range splits it along the axis stored with it, the two halves are its subtrees.
+///
+/// Caustics, light focused onto diffuse surfaces by smooth mirrors and glass, converge very slowly with path
+/// tracing alone, the path tracer estimates them from a caustic photon map instead.
+///
+/// Radiance is estimated from the `lookup_count` photons closest to the shaded point, no further away than
+/// `lookup_radius`. More photons give smoother, but blurrier caustics.
+#[derive(PartialEq, Clone)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    split_axes: Vec<u8>,
+    pub lookup_count: usize,
+    pub lookup_radius: f32,
+}
+
+impl PhotonMap {
+    pub fn new(photons: Vec<Photon>) -> Self {
+        let mut photons = photons;
+        let mut split_axes = vec![0; photons.len()];
+        build_tree(&mut photons, &mut split_axes);
+        Self {
+            photons,
+            split_axes,
+            lookup_count: 100,
+            lookup_radius: 0.5,
+        }
+    }
+
+    /// Shoots photons from the world's emitters and keeps the ones that reached a diffuse surface through
+    /// smooth mirrors or glass, which are the caustics. The number of photons shot sets their power, only
+    /// a fraction of them is stored. Photons are traced at the time 0.0, objects in motion do not blur caustics.
+    pub fn trace_caustics(world: &World, photon_count: u32) -> Self {
+        info!("Tracing caustic photons, photons: {}", photon_count);
+        let photons: Vec<Photon> = (0..photon_count)
+            .into_par_iter()
+            .filter_map(|photon_index| {
+                let mut sampler = IndependentSampler::new(1, 0);
+                sampler.start_pixel_sample(0, 0, photon_index);
+                trace_caustic_photon(world, photon_count, &mut sampler)
+            })
+            .collect();
+        info!("Stored caustic photons: {}", photons.len());
+        Self::new(photons)
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Up to `count` photons closest to the point within the distance, with their squared distances,
+    /// the closest first.
+    pub fn nearest(&self, point: Vec3A, count: usize, max_distance: f32) -> Vec<(&Photon, f32)> {
+        let mut neighbours = BinaryHeap::with_capacity(count + 1);
+        let mut max_distance_squared = max_distance * max_distance;
+        if count > 0 {
+            self.search(
+                0,
+                self.photons.len(),
+                point,
+                count,
+                &mut neighbours,
+                &mut max_distance_squared,
+            );
+        }
+        neighbours
+            .into_sorted_vec()
+            .into_iter()
+            .map(|neighbour| (&self.photons[neighbour.index], neighbour.distance_squared))
+            .collect()
+    }
+
+    /// Radiance leaving the point towards the viewer, estimated from the density of the photons around it.
+    /// `f` is the BSDF at the point for light arriving from the direction of a photon.
+    pub fn estimate_radiance(&self, point: Vec3A, f: impl Fn(Vec3A) -> Color) -> Color {
+        let neighbours = self.nearest(point, self.lookup_count, self.lookup_radius);
+        let radius_squared = match neighbours.last() {
+            None => return Color::new_black(),
+            Some(&(_, farthest)) if neighbours.len() == self.lookup_count => farthest,
+            Some(_) => self.lookup_radius * self.lookup_radius,
+        };
+        if radius_squared == 0.0 {
+            return Color::new_black();
+        }
+        let reflected = neighbours
+            .iter()
+            .fold(Color::new_black(), |acc, (photon, _)| {
+                acc + f(photon.direction) * photon.power
+            });
+        reflected / (PI * radius_squared)
+    }
+
+    fn search(
+        &self,
+        start: usize,
+        end: usize,
+        point: Vec3A,
+        count: usize,
+        neighbours: &mut BinaryHeap<Neighbour>,
+        max_distance_squared: &mut f32,
+    ) {
+        if start >= end {
+            return;
+        }
+        let middle = start + (end - start) / 2;
+        let photon = &self.photons[middle];
+        let axis = self.split_axes[middle] as usize;
+        let offset = point[axis] - photon.position[axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+
+        self.search(
+            near.0,
+            near.1,
+            point,
+            count,
+            neighbours,
+            max_distance_squared,
+        );
+        let distance_squared = photon.position.distance_squared(point);
+        if distance_squared <= *max_distance_squared {
+            neighbours.push(Neighbour {
+                distance_squared,
+                index: middle,
+            });
+            if neighbours.len() > count {
+                neighbours.pop();
+            }
+            if neighbours.len() == count {
+                *max_distance_squared = neighbours
+                    .peek()
+                    .expect("should have the farthest neighbour")
+                    .distance_squared;
+            }
+        }
+        // The other side of the splitting plane can only hold closer photons if the plane is close enough
+        if offset * offset <= *max_distance_squared {
+            self.search(far.0, far.1, point, count, neighbours, max_distance_squared);
+        }
+    }
+}
+
+impl fmt::Debug for PhotonMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhotonMap")
+            .field("photons", &self.photons.len())
+            .field("lookup_count", &self.lookup_count)
+            .field("lookup_radius", &self.lookup_radius)
+            .finish()
+    }
+}
+
+/// Photon found by a search, ordered by its distance so the heap keeps the farthest one on top.
+#[derive(Debug, Copy, Clone)]
+struct Neighbour {
+    distance_squared: f32,
+    index: usize,
+}
+
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared.total_cmp(&other.distance_squared)
+    }
+}
+
+/// Reorders the photons into the implicit kd-tree, splitting every range along its longest extent.
+fn build_tree(photons: &mut [Photon], split_axes: &mut [u8]) {
+    if photons.len() <= 1 {
+        return;
+    }
+    let (min, max) = photons.iter().fold(
+        (Vec3A::splat(f32::INFINITY), Vec3A::splat(f32::NEG_INFINITY)),
+        |(min, max), photon| (min.min(photon.position), max.max(photon.position)),
+    );
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+
+    let middle = photons.len() / 2;
+    photons.select_nth_unstable_by(middle, |a, b| a.position[axis].total_cmp(&b.position[axis]));
+    split_axes[middle] = axis as u8;
+    let (left_photons, right_photons) = photons.split_at_mut(middle);
+    let (left_axes, right_axes) = split_axes.split_at_mut(middle);
+    build_tree(left_photons, left_axes);
+    build_tree(&mut right_photons[1..], &mut right_axes[1..]);
+}
+
+/// Follows one photon through smooth mirrors and glass, it is stored where it lands on a diffuse surface
+/// after at least one such bounce. Passing through boundaries of media is not a bounce, the power of the photon
+/// is attenuated by the transmittance of the media it travels through.
+fn trace_caustic_photon(
+    world: &World,
+    photon_count: u32,
+    sampler: &mut dyn Sampler,
+) -> Option<Photon> {
+    let (emitter, pmf) = world.sample_emitter(sampler.next_1d())?;
+    let u_position = sampler.next_2d();
+    let (mut ray, power) = emitter.sample_photon(u_position, sampler.next_2d())?;
+    let mut power = power / (pmf * photon_count as f32);
+    // Lights are outside of all objects
+    let mut medium = world.atmosphere();
+    let mut bounces = 0;
+
+    while bounces < MAX_PHOTON_BOUNCES {
+        let comps = world.hit_computations(ray)?;
+        if let Some(current) = medium {
+            power = power * current.transmittance(ray, comps.t, sampler);
+        }
+        let object = world
+            .object_by_id(&comps.object_id)
+            .expect("should find the object that was hit in the world");
+        let material = object.material();
+        if material.is_medium_boundary() {
+            medium = world.medium_across(object, &comps);
+            ray = Ray::new(comps.under_point, ray.direction_vector);
+            continue;
+        }
+
+        match material.bsdf {
+            None if sampler.next_1d() < material.reflective => {
+                bounces += 1;
+                ray = Ray::new(comps.over_point, comps.reflect_vector);
+            }
+            Some(bsdf) if bsdf.is_near_specular() => {
+                let bsdf = if comps.inside {
+                    bsdf.seen_from_inside()
+                } else {
+                    bsdf
+                };
+                let frame = TangentFrame::from_normal(comps.normal_vector);
+                let wo = frame.world_to_local(comps.eye_vector);
+                let u_lobe = sampler.next_1d();
+                let sample = bsdf.sample(wo, u_lobe, sampler.next_2d())?;
+                let direction = frame.local_to_world(sample.wi);
+                let transmitted = sample.wi.z < 0.0;
+                if (direction.dot(comps.geometric_normal_vector) < 0.0) != transmitted {
+                    return None;
+                }
+                let weight = if sample.is_specular {
+                    sample.wi.z.abs()
+                } else {
+                    sample.wi.z.abs() / sample.pdf
+                };
+                power = power * sample.f * weight;
+                let origin = if transmitted {
+                    medium = world.medium_across(object, &comps);
+                    comps.under_point
+                } else {
+                    comps.over_point
+                };
+                bounces += 1;
+                ray = Ray::new(origin, direction);
+            }
+            _ => {
+                return (bounces > 0).then_some(Photon {
+                    position: comps.point,
+                    direction: comps.eye_vector,
+                    power,
+                })
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use glam::{Affine3A, Vec3};
+
+    use crate::{
+        light::PointLight, material::Material, medium::Medium, quad::Quad, sphere::Sphere,
+        test_helpers::floor_world,
+    };
+
+    use super::*;
+
+    /// Photons on a jittered grid, with the power of each one telling it apart.
+    #[fixture]
+    pub fn photons() -> Vec<Photon> {
+        (0..1000)
+            .map(|i| {
+                let jitter = ((i * 7919) % 101) as f32 / 101.0;
+                Photon {
+                    position: Vec3A::new(
+                        (i % 10) as f32 + jitter,
+                        ((i / 10) % 10) as f32 * 0.5,
+                        (i / 100) as f32 * 2.0 - jitter,
+                    ),
+                    direction: Vec3A::Y,
+                    power: Color::new_color(i as f32, 0.0, 0.0),
+                }
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case(Vec3A::new(4.5, 2.0, 9.0), 10, 100.0)]
+    #[case(Vec3A::new(0.0, 0.0, 0.0), 25, 1.5)]
+    #[case(Vec3A::new(20.0, 20.0, 20.0), 5, 1.0)]
+    fn nearest_photons_match_a_brute_force_search(
+        photons: Vec<Photon>,
+        #[case] point: Vec3A,
+        #[case] count: usize,
+        #[case] max_distance: f32,
+    ) {
+        let mut expected: Vec<f32> = photons
+            .iter()
+            .map(|photon| photon.position.distance_squared(point))
+            .filter(|&distance_squared| distance_squared <= max_distance * max_distance)
+            .collect();
+        expected.sort_by(f32::total_cmp);
+        expected.truncate(count);
+
+        let map = PhotonMap::new(photons);
+        let found: Vec<f32> = map
+            .nearest(point, count, max_distance)
+            .iter()
+            .map(|&(photon, distance_squared)| {
+                assert_abs_diff_eq!(photon.position.distance_squared(point), distance_squared);
+                distance_squared
+            })
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn density_of_uniform_photons_gives_the_irradiance() {
+        // Photons of power 0.01 on a grid with spacing 0.1 deliver irradiance of 1.0
+        let photons = (0..10_000)
+            .map(|i| Photon {
+                position: Vec3A::new((i % 100) as f32 * 0.1, 0.0, (i / 100) as f32 * 0.1),
+                direction: Vec3A::Y,
+                power: Color::new_color(0.01, 0.01, 0.01),
+            })
+            .collect();
+        let mut map = PhotonMap::new(photons);
+        map.lookup_count = 200;
+        let radiance =
+            map.estimate_radiance(Vec3A::new(5.0, 0.0, 5.0), |_| Color::new_white() / PI);
+        assert_abs_diff_eq!(radiance.get_red_val(), 1.0 / PI, epsilon = 0.05 / PI);
+    }
+
+    #[rstest]
+    fn diffuse_surfaces_lit_directly_have_no_caustics(#[from(floor_world)] mut world: World) {
+        world.add_light(PointLight::new(
+            Vec3A::new(0.0, 2.0, 0.0),
+            Color::new_white(),
+        ));
+        assert!(PhotonMap::trace_caustics(&world, 1000).is_empty());
+    }
+
+    #[rstest]
+    fn passing_through_boundaries_of_media_is_not_a_bounce(#[from(floor_world)] mut world: World) {
+        let mut fog = Sphere::new(Vec3A::ZERO);
+        fog.set_transform(
+            Affine3A::from_translation(Vec3::new(0.0, 1.0, 0.0))
+                * Affine3A::from_scale(Vec3::splat(0.5)),
+        );
+        fog.set_material(Material {
+            medium: Some(Medium::new(
+                Color::new_color(0.5, 0.5, 0.5),
+                Color::new_color(0.5, 0.5, 0.5),
+                0.0,
+            )),
+            ..Default::default()
+        });
+        world.add_object(fog);
+        world.add_light(PointLight::new(
+            Vec3A::new(0.0, 2.0, 0.0),
+            Color::new_white(),
+        ));
+        assert!(PhotonMap::trace_caustics(&world, 1000).is_empty());
+    }
+
+    #[rstest]
+    fn photons_are_attenuated_by_the_media_they_pass_through(
+        #[from(floor_world)] mut world: World,
+    ) {
+        let mut mirror = Quad::new(
+            Vec3A::new(-1.0, 1.0, -1.0),
+            Vec3A::new(2.0, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, 2.0),
+        );
+        mirror.set_material(Material {
+            color: Color::new_black(),
+            reflective: 1.0,
+            ..Default::default()
+        });
+        world.add_object(mirror);
+        world.set_atmosphere(Medium::new(
+            Color::new_color(0.1, 0.1, 0.1),
+            Color::new_black(),
+            0.0,
+        ));
+        world.add_light(PointLight::new(
+            Vec3A::new(0.0, 0.5, 0.0),
+            Color::new_white(),
+        ));
+
+        // Photons travel 0.5 up to the mirror and 1.0 down to the floor, at least 1.5 in the absorbing fog
+        let map = PhotonMap::trace_caustics(&world, 2000);
+        assert!(!map.is_empty());
+        for photon in &map.photons {
+            assert!(photon.power.get_red_val() <= 4.0 * PI / 2000.0 * (-0.15_f32).exp() + 1e-6);
+        }
+    }
+
+    #[rstest]
+    fn photons_reflected_by_a_mirror_are_stored_on_the_diffuse_surface(
+        #[from(floor_world)] mut world: World,
+    ) {
+        let mut mirror = Quad::new(
+            Vec3A::new(-1.0, 1.0, -1.0),
+            Vec3A::new(2.0, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, 2.0),
+        );
+        mirror.set_material(Material {
+            color: Color::new_black(),
+            reflective: 1.0,
+            ..Default::default()
+        });
+        world.add_object(mirror);
+        // The light is between the floor and the mirror, the floor only gets photons through the mirror
+        world.add_light(PointLight::new(
+            Vec3A::new(0.0, 0.5, 0.0),
+            Color::new_white(),
+        ));
+
+        let map = PhotonMap::trace_caustics(&world, 2000);
+        assert!(!map.is_empty());
+        for photon in &map.photons {
+            assert_abs_diff_eq!(photon.position.y, 0.0, epsilon = 1e-4);
+            assert!(photon.direction.y > 0.0);
+            // Each photon carries 4π / 2000 of the light's power
+            assert_abs_diff_eq!(
+                photon.power.get_red_val(),
+                4.0 * PI / 2000.0,
+                epsilon = 1e-6
+            );
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use std::f32::consts::FRAC_1_PI;
+
+use glam::{Vec2, Vec3A};
+
+use crate::{
+    bsdf::{Bsdf, BsdfSample},
+    color::Color,
+    material::Material,
+    microfacet::{fresnel_dielectric, fresnel_schlick, GgxDistribution},
+    sampling::{cosine_hemisphere_pdf, cosine_sample_hemisphere},
+};
+
+/// Principled (Disney-style) uber-material, with the parameters of the principled shaders of DCC tools so
+/// assets can be imported one to one. All parameters except the colors and `ior` are in the 0.0 - 1.0 range.
+///
+/// Layers, from the top: a clearcoat, a specular GGX lobe (tinted by the base color as it gets metallic) and
+/// either a rough glass (transmission) or a Burley diffuse base with sheen. Convert it to a `Material` to use it.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Principled {
+    pub base_color: Color,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Reflectance of dielectric surfaces at normal incidence, 0.5 is the common 4 %
+    pub specular: f32,
+    /// Soft reflection at grazing angles, for cloth
+    pub sheen: f32,
+    /// Blends the sheen from white to the hue of the base color
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    /// Fraction of the dielectric part that is glass instead of a diffuse base
+    pub transmission: f32,
+    /// Index of refraction of the transmissive part, relative to the outside
+    pub ior: f32,
+    /// Emitted radiance, it becomes the emissive color of the material
+    pub emission: Color,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: Color::new_color(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            ior: 1.45,
+            emission: Color::new_black(),
+        }
+    }
+}
+
+impl From<Principled> for Material {
+    fn from(principled: Principled) -> Self {
+        Material {
+            color: principled.base_color,
+            emissive: principled.emission,
+            bsdf: Some(Bsdf::Principled(principled)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Weights of the layered lobes, also used as their sampling probabilities once normalized.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct LobeWeights {
+    diffuse: f32,
+    specular: f32,
+    clearcoat: f32,
+    transmission: f32,
+}
+
+impl LobeWeights {
+    fn normalized(self) -> Self {
+        let sum = self.diffuse + self.specular + self.clearcoat + self.transmission;
+        Self {
+            diffuse: self.diffuse / sum,
+            specular: self.specular / sum,
+            clearcoat: self.clearcoat / sum,
+            transmission: self.transmission / sum,
+        }
+    }
+}
+
+impl Principled {
+    /// Same material seen from inside of the object, the relative index of refraction is inverted.
+    pub fn seen_from_inside(&self) -> Self {
+        Self {
+            ior: 1.0 / self.ior,
+            ..*self
+        }
+    }
+
+    fn lobe_weights(&self) -> LobeWeights {
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        let transmission = (1.0 - metallic) * self.transmission.clamp(0.0, 1.0);
+        LobeWeights {
+            diffuse: (1.0 - metallic) - transmission,
+            specular: 1.0 - transmission,
+            clearcoat: 0.25 * self.clearcoat.clamp(0.0, 1.0),
+            transmission,
+        }
+    }
+
+    fn distribution(&self) -> GgxDistribution {
+        GgxDistribution::from_roughness(self.roughness)
+    }
+
+    fn clearcoat_distribution(&self) -> GgxDistribution {
+        GgxDistribution::from_roughness(self.clearcoat_roughness)
+    }
+
+    /// Reflectance of the specular lobe at normal incidence.
+    fn specular_f0(&self) -> Color {
+        let dielectric = 0.08 * self.specular;
+        Color::new_color(dielectric, dielectric, dielectric) * (1.0 - self.metallic)
+            + self.base_color * self.metallic
+    }
+
+    fn sheen_color(&self) -> Color {
+        let luminance = self.base_color.luminance();
+        let tint = if luminance > 0.0 {
+            self.base_color / luminance
+        } else {
+            Color::new_white()
+        };
+        (Color::new_white() * (1.0 - self.sheen_tint) + tint * self.sheen_tint) * self.sheen
+    }
+
+    pub fn f(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Color::new_black();
+        }
+        let weights = self.lobe_weights();
+        if wi.z < 0.0 {
+            return match refraction_half_vector(wo, wi, self.ior) {
+                Some(wm) => self.refraction_f(wo, wi, wm) * weights.transmission,
+                None => Color::new_black(),
+            };
+        }
+
+        let wm = (wo + wi).normalize();
+        let cos_theta_d = wi.dot(wm);
+
+        // Burley diffuse, with retro-reflection on rough surfaces at grazing angles
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_theta_d * cos_theta_d;
+        let diffuse = self.base_color
+            * (FRAC_1_PI
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z)));
+        let sheen = self.sheen_color() * schlick_weight(cos_theta_d);
+
+        let specular = fresnel_schlick(wo.dot(wm), self.specular_f0())
+            * microfacet_reflection(&self.distribution(), wo, wi, wm);
+        let clearcoat = fresnel_schlick(wo.dot(wm), Color::new_color(0.04, 0.04, 0.04))
+            * microfacet_reflection(&self.clearcoat_distribution(), wo, wi, wm);
+        let glass_reflection = microfacet_reflection(&self.distribution(), wo, wi, wm)
+            * fresnel_dielectric(wo.dot(wm), self.ior);
+
+        (diffuse + sheen) * weights.diffuse
+            + specular * weights.specular
+            + clearcoat * weights.clearcoat
+            + Color::new_white() * (glass_reflection * weights.transmission)
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let probabilities = self.lobe_weights().normalized();
+        if wi.z < 0.0 {
+            return match refraction_half_vector(wo, wi, self.ior) {
+                Some(wm) => probabilities.transmission * self.refraction_pdf(wo, wi, wm),
+                None => 0.0,
+            };
+        }
+
+        let wm = (wo + wi).normalize();
+        let specular_pdf = reflection_pdf(&self.distribution(), wo, wm);
+        probabilities.diffuse * cosine_hemisphere_pdf(wi.z)
+            + probabilities.specular * specular_pdf
+            + probabilities.clearcoat * reflection_pdf(&self.clearcoat_distribution(), wo, wm)
+            + probabilities.transmission * fresnel_dielectric(wo.dot(wm), self.ior) * specular_pdf
+    }
+
+    /// Picks a lobe with `u_lobe`, then samples it. Within the glass lobe the rest of `u_lobe` chooses between
+    /// reflection and refraction in proportion to the Fresnel reflectance.
+    pub fn sample(&self, wo: Vec3A, u_lobe: f32, u: Vec2) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let probabilities = self.lobe_weights().normalized();
+        let reflect = |wm: Vec3A| -wo + wm * 2.0 * wo.dot(wm);
+
+        let mut threshold = probabilities.diffuse;
+        let wi = if u_lobe < threshold {
+            cosine_sample_hemisphere(u)
+        } else if u_lobe < threshold + probabilities.specular {
+            reflect(self.distribution().sample_visible_normal(wo, u))
+        } else if u_lobe < threshold + probabilities.specular + probabilities.clearcoat {
+            reflect(self.clearcoat_distribution().sample_visible_normal(wo, u))
+        } else {
+            threshold += probabilities.specular + probabilities.clearcoat;
+            let u_fresnel = ((u_lobe - threshold) / probabilities.transmission).min(0.99999);
+            let wm = self.distribution().sample_visible_normal(wo, u);
+            if u_fresnel < fresnel_dielectric(wo.dot(wm), self.ior) {
+                reflect(wm)
+            } else {
+                refract(wo, wm, self.ior)?
+            }
+        };
+
+        let pdf = self.pdf(wo, wi);
+        (pdf > 0.0).then(|| BsdfSample {
+            wi,
+            f: self.f(wo, wi),
+            pdf,
+            is_specular: false,
+        })
+    }
+
+    /// Rough dielectric transmission (Walter et al. 2007), scaled for radiance crossing the interface.
+    fn refraction_f(&self, wo: Vec3A, wi: Vec3A, wm: Vec3A) -> Color {
+        let distribution = self.distribution();
+        let transmittance = 1.0 - fresnel_dielectric(wo.dot(wm), self.ior);
+        let denominator = (wi.dot(wm) + wo.dot(wm) / self.ior).powi(2) * wi.z * wo.z;
+        let value = transmittance
+            * distribution.d(wm)
+            * distribution.g(wo, wi)
+            * (wi.dot(wm) * wo.dot(wm) / denominator).abs()
+            / (self.ior * self.ior);
+        self.base_color * value
+    }
+
+    fn refraction_pdf(&self, wo: Vec3A, wi: Vec3A, wm: Vec3A) -> f32 {
+        let transmittance = 1.0 - fresnel_dielectric(wo.dot(wm), self.ior);
+        let dwm_dwi = wi.dot(wm).abs() / (wi.dot(wm) + wo.dot(wm) / self.ior).powi(2);
+        transmittance * self.distribution().visible_normal_pdf(wo, wm) * dwm_dwi
+    }
+}
+
+fn schlick_weight(cos_theta: f32) -> f32 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// Cook-Torrance reflection without the Fresnel term.
+fn microfacet_reflection(distribution: &GgxDistribution, wo: Vec3A, wi: Vec3A, wm: Vec3A) -> f32 {
+    distribution.d(wm) * distribution.g(wo, wi) / (4.0 * wo.z * wi.z)
+}
+
+fn reflection_pdf(distribution: &GgxDistribution, wo: Vec3A, wm: Vec3A) -> f32 {
+    distribution.visible_normal_pdf(wo, wm) / (4.0 * wo.dot(wm))
+}
+
+/// Direction refracted through the microfacet, `None` on total internal reflection.
+fn refract(wo: Vec3A, wm: Vec3A, eta: f32) -> Option<Vec3A> {
+    let cos_theta_i = wo.dot(wm);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-wo / eta + wm * (cos_theta_i / eta - cos_theta_t))
+}
+
+/// Microfacet normal that refracts `wo` into `wi`, `None` when no microfacet facing both directions does.
+fn refraction_half_vector(wo: Vec3A, wi: Vec3A, eta: f32) -> Option<Vec3A> {
+    let wm = wi * eta + wo;
+    if wm.length_squared() == 0.0 {
+        return None;
+    }
+    let wm = wm.normalize() * wm.z.signum();
+    (wm.dot(wi) < 0.0 && wm.dot(wo) > 0.0).then_some(wm)
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use crate::{
+        microfacet::MicrofacetBsdf,
+        test_helpers::{integrate_directions, strata},
+    };
+
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    /// Scattered fraction of light in the green channel, estimated by sampling the BSDF.
+    fn sampled_albedo(principled: &Principled, wo: Vec3A) -> f32 {
+        let steps = 64;
+        let mut sum = 0.0;
+        for (index, u) in strata(steps).enumerate() {
+            let u_lobe = (index as f32 * 0.618034).fract();
+            if let Some(sample) = principled.sample(wo, u_lobe, u) {
+                assert_abs_diff_eq!(
+                    sample.pdf,
+                    principled.pdf(wo, sample.wi),
+                    epsilon = sample.pdf * 1e-3
+                );
+                sum += sample.f.get_green_val() * sample.wi.z.abs() / sample.pdf;
+            }
+        }
+        sum / (steps * steps) as f32
+    }
+
+    #[test]
+    fn converting_to_a_material_keeps_the_emission() {
+        let principled = Principled {
+            base_color: Color::new_red(),
+            emission: Color::new_color(2.0, 2.0, 2.0),
+            ..Default::default()
+        };
+        let material = Material::from(principled);
+        assert_eq!(material.color, Color::new_red());
+        assert_eq!(material.emissive, Color::new_color(2.0, 2.0, 2.0));
+        assert_eq!(material.bsdf, Some(Bsdf::Principled(principled)));
+    }
+
+    #[test]
+    fn fully_metallic_matches_the_microfacet_metal() {
+        let base_color = Color::new_color(0.9, 0.6, 0.2);
+        let principled = Principled {
+            base_color,
+            metallic: 1.0,
+            roughness: 0.4,
+            ..Default::default()
+        };
+        let metal = MicrofacetBsdf::new(base_color, 0.4, 1.0);
+        let wo = Vec3A::new(0.3, 0.1, 0.9).normalize();
+        let wi = Vec3A::new(-0.4, 0.2, 0.8).normalize();
+        let expected = metal.f(wo, wi);
+        let actual = principled.f(wo, wi);
+        assert_abs_diff_eq!(actual.get_red_val(), expected.get_red_val(), epsilon = 1e-5);
+        assert_abs_diff_eq!(
+            actual.get_blue_val(),
+            expected.get_blue_val(),
+            epsilon = 1e-5
+        );
+    }
+
+    #[rstest]
+    #[case::plastic(Principled::default())]
+    #[case::metal(Principled { metallic: 1.0, roughness: 0.3, ..Default::default() })]
+    #[case::velvet(Principled { sheen: 1.0, roughness: 0.9, ..Default::default() })]
+    #[case::car_paint(Principled { metallic: 0.5, clearcoat: 1.0, clearcoat_roughness: 0.2, ..Default::default() })]
+    #[case::frosted_glass(Principled { transmission: 1.0, roughness: 0.4, ..Default::default() })]
+    fn sampling_matches_the_pdf_and_integrates_the_bsdf(#[case] principled: Principled) {
+        let wo = Vec3A::new(0.5, 0.1, 0.8).normalize();
+        let integrated_albedo =
+            integrate_directions(PI, |wi| principled.f(wo, wi).get_green_val() * wi.z.abs());
+        let integrated_pdf = integrate_directions(PI, |wi| principled.pdf(wo, wi));
+        assert!(integrated_pdf > 0.5 && integrated_pdf <= 1.02);
+        assert!(integrated_albedo <= 1.05);
+        assert_abs_diff_eq!(
+            sampled_albedo(&principled, wo),
+            integrated_albedo,
+            epsilon = 3e-2
+        );
+    }
+
+    #[test]
+    fn clearcoat_and_sheen_add_reflection() {
+        let wo = Vec3A::new(0.8, 0.0, 0.6);
+        let albedo = |principled: Principled| {
+            integrate_directions(PI, |wi| principled.f(wo, wi).get_green_val() * wi.z.abs())
+        };
+        let base = albedo(Principled::default());
+        assert!(
+            albedo(Principled {
+                clearcoat: 1.0,
+                ..Default::default()
+            }) > base
+        );
+        assert!(
+            albedo(Principled {
+                sheen: 1.0,
+                ..Default::default()
+            }) > base
+        );
+    }
+
+    #[test]
+    fn transmission_refracts_light_through_the_surface() {
+        let glass = Principled {
+            base_color: Color::new_white(),
+            transmission: 1.0,
+            roughness: 0.2,
+            ..Default::default()
+        };
+        let wo = Vec3A::Z;
+        let transmitted = integrate_directions(PI, |wi| {
+            if wi.z < 0.0 {
+                glass.f(wo, wi).get_green_val() * wi.z.abs()
+            } else {
+                0.0
+            }
+        });
+        // Radiance is compressed into the denser medium, so less than the 96 % of transmitted power
+        assert_abs_diff_eq!(transmitted, 0.96 / (1.45 * 1.45), epsilon = 3e-2);
+    }
+
+    #[test]
+    fn total_internal_reflection_inside_of_glass() {
+        let glass = Principled {
+            transmission: 1.0,
+            roughness: 0.0,
+            ..Default::default()
+        }
+        .seen_from_inside();
+        let grazing = Vec3A::new(0.9, 0.0, 0.1).normalize();
+        let refracted = Vec3A::new(-0.9, 0.0, -0.1).normalize();
+        // Only microfacets tilted towards the grazing direction refract, and there are almost none of them
+        assert!(glass.f(grazing, refracted).max_component() < 1e-3);
+        let sample = glass
+            .sample(grazing, 0.99, Vec2::splat(0.5))
+            .expect("should reflect the light back");
+        assert!(sample.wi.z > 0.0);
+    }
+}
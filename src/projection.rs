@@ -0,0 +1,268 @@
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    fmt::Debug,
+};
+
+use glam::{Vec2, Vec3A};
+
+use crate::ray::Ray;
+
+/// Camera model, maps points on the film to rays in camera space, where the eye is at the origin looking
+/// towards -z with +y up and +x to the left. The camera's render loop works with any projection, it only moves
+/// the rays into the world. Settings that only make sense for some models, like the field of view, belong
+/// to them.
+pub trait Projection: Debug + Send + Sync {
+    /// Ray through the point on the film, from (0, 0) in the top left to (1, 1) in the bottom right corner.
+    /// `aspect` is the ratio of the film's width to its height. `None` where the projection does not see
+    /// anything, such film stays black.
+    fn ray_for_film(&self, film: Vec2, aspect: f32) -> Option<Ray>;
+}
+
+/// Pinhole camera of the book, with the field of view across the longer side of the film.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Perspective {
+    /// Field of view in radians
+    pub field_of_view: f32,
+}
+
+impl Perspective {
+    /// Size of a pixel on the canvas one unit in front of the eye.
+    pub fn pixel_size(&self, hsize: u16, vsize: u16) -> f32 {
+        let half_view = (self.field_of_view / 2.0).tan();
+        half_view * 2.0 / hsize.max(vsize) as f32
+    }
+}
+
+impl Projection for Perspective {
+    fn ray_for_film(&self, film: Vec2, aspect: f32) -> Option<Ray> {
+        let screen = centered(film, aspect) * (self.field_of_view / 2.0).tan();
+        Some(Ray::new(
+            Vec3A::ZERO,
+            Vec3A::new(-screen.x, screen.y, -1.0).normalize(),
+        ))
+    }
+}
+
+/// Parallel projection, for technical and architectural drawings where sizes do not change with distance.
+/// Rays start on the plane of the eye.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Orthographic {
+    /// Half of the longer side of the view, in world units
+    pub half_size: f32,
+}
+
+impl Orthographic {
+    /// Size of a pixel in world units, the scale of the drawing.
+    pub fn pixel_size(&self, hsize: u16, vsize: u16) -> f32 {
+        self.half_size * 2.0 / hsize.max(vsize) as f32
+    }
+}
+
+impl Projection for Orthographic {
+    fn ray_for_film(&self, film: Vec2, aspect: f32) -> Option<Ray> {
+        let screen = centered(film, aspect) * self.half_size;
+        Some(Ray::new(
+            Vec3A::new(-screen.x, screen.y, 0.0),
+            Vec3A::new(0.0, 0.0, -1.0),
+        ))
+    }
+}
+
+/// How a fisheye lens maps the angle from the viewing direction to the distance from the center of the image.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum FisheyeMapping {
+    /// Distance proportional to the angle
+    #[default]
+    Equidistant,
+    /// Distance proportional to the sine of half of the angle, which keeps areas of solid angle
+    Equisolid,
+}
+
+/// Fisheye lens with a circular image inscribed in the longer side of the film, film outside of the circle
+/// stays black. The field of view can be wider than 180°.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Fisheye {
+    /// Field of view across the image circle in radians, up to 2π
+    pub field_of_view: f32,
+    pub mapping: FisheyeMapping,
+}
+
+impl Projection for Fisheye {
+    fn ray_for_film(&self, film: Vec2, aspect: f32) -> Option<Ray> {
+        let screen = centered(film, aspect);
+        let radius = screen.length();
+        if radius > 1.0 {
+            return None;
+        }
+
+        let max_theta = (self.field_of_view / 2.0).min(PI);
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => radius * max_theta,
+            FisheyeMapping::Equisolid => 2.0 * (radius * (max_theta / 2.0).sin()).asin(),
+        };
+        let phi = screen.y.atan2(screen.x);
+        Some(Ray::new(
+            Vec3A::ZERO,
+            Vec3A::new(
+                -theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                -theta.cos(),
+            ),
+        ))
+    }
+}
+
+/// Full 360° panorama in the equirectangular (latitude-longitude) layout, for VR. The width of the film spans
+/// all directions around the vertical axis with the viewing direction in the middle, the height goes from
+/// straight up to straight down. Films twice as wide as they are tall have square pixels.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn ray_for_film(&self, film: Vec2, _aspect: f32) -> Option<Ray> {
+        let longitude = (film.x - 0.5) * 2.0 * PI;
+        let latitude = FRAC_PI_2 - film.y * PI;
+        Some(Ray::new(
+            Vec3A::ZERO,
+            Vec3A::new(
+                -latitude.cos() * longitude.sin(),
+                latitude.sin(),
+                -latitude.cos() * longitude.cos(),
+            ),
+        ))
+    }
+}
+
+/// Point on the film relative to its center, with x to the right and y up. The longer side of the film
+/// goes from -1.0 to 1.0.
+fn centered(film: Vec2, aspect: f32) -> Vec2 {
+    let point = Vec2::new(film.x * 2.0 - 1.0, 1.0 - film.y * 2.0);
+    if aspect >= 1.0 {
+        Vec2::new(point.x, point.y / aspect)
+    } else {
+        Vec2::new(point.x * aspect, point.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case::horizontal_canvas(200, 125)]
+    #[case::vertical_canvas(125, 200)]
+    fn the_pixel_size_of_a_perspective_projection(#[case] hsize: u16, #[case] vsize: u16) {
+        let projection = Perspective {
+            field_of_view: FRAC_PI_2,
+        };
+        assert_abs_diff_eq!(projection.pixel_size(hsize, vsize), 0.01, epsilon = 1e-6);
+    }
+
+    #[rstest]
+    #[case::perspective(&Perspective { field_of_view: FRAC_PI_2 })]
+    #[case::fisheye(&Fisheye { field_of_view: PI, mapping: FisheyeMapping::Equisolid })]
+    #[case::equirectangular(&Equirectangular)]
+    fn projections_look_forward_from_the_center_and_keep_the_image_upright(
+        #[case] projection: &dyn Projection,
+    ) {
+        let center = projection
+            .ray_for_film(Vec2::splat(0.5), 2.0)
+            .expect("should see the center");
+        assert_eq!(center.origin_point, Vec3A::ZERO);
+        assert!(center
+            .direction_vector
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-5));
+
+        // The camera's +x is on the left of the image
+        let right = projection
+            .ray_for_film(Vec2::new(0.7, 0.5), 2.0)
+            .expect("should see the right side");
+        assert_eq!(right.origin_point, Vec3A::ZERO);
+        assert!(right.direction_vector.x < 0.0);
+        let up = projection
+            .ray_for_film(Vec2::new(0.5, 0.4), 2.0)
+            .expect("should see above the center");
+        assert!(up.direction_vector.y > 0.0);
+    }
+
+    #[test]
+    fn orthographic_projection_moves_the_origin_and_keeps_the_image_upright() {
+        let projection = Orthographic { half_size: 2.0 };
+        let center = projection
+            .ray_for_film(Vec2::splat(0.5), 2.0)
+            .expect("should see the center");
+        assert!(center.origin_point.abs_diff_eq(Vec3A::ZERO, 1e-6));
+        let right = projection
+            .ray_for_film(Vec2::new(0.7, 0.5), 2.0)
+            .expect("should see the right side");
+        assert!(right.origin_point.x < 0.0);
+        let up = projection
+            .ray_for_film(Vec2::new(0.5, 0.4), 2.0)
+            .expect("should see above the center");
+        assert!(up.origin_point.y > 0.0);
+        assert_abs_diff_eq!(projection.pixel_size(200, 100), 0.02);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let projection = Orthographic { half_size: 2.0 };
+        for film in [Vec2::ZERO, Vec2::splat(0.5), Vec2::new(0.7, 0.4)] {
+            let ray = projection
+                .ray_for_film(film, 2.0)
+                .expect("should see the whole film");
+            assert_eq!(ray.direction_vector, Vec3A::new(0.0, 0.0, -1.0));
+        }
+        let corner = projection
+            .ray_for_film(Vec2::ZERO, 2.0)
+            .expect("should see the corner");
+        assert!(corner
+            .origin_point
+            .abs_diff_eq(Vec3A::new(2.0, 1.0, 0.0), 1e-6));
+    }
+
+    #[rstest]
+    #[case::equidistant_edge(FisheyeMapping::Equidistant, 1.0, FRAC_PI_2)]
+    #[case::equidistant_halfway(FisheyeMapping::Equidistant, 0.5, PI / 4.0)]
+    #[case::equisolid_edge(FisheyeMapping::Equisolid, 1.0, FRAC_PI_2)]
+    #[case::equisolid_halfway(FisheyeMapping::Equisolid, 0.5, 2.0 * (0.5 * (PI / 4.0).sin()).asin())]
+    fn fisheye_maps_the_distance_from_the_center_to_the_angle(
+        #[case] mapping: FisheyeMapping,
+        #[case] radius: f32,
+        #[case] expected_angle: f32,
+    ) {
+        let projection = Fisheye {
+            field_of_view: PI,
+            mapping,
+        };
+        let ray = projection
+            .ray_for_film(Vec2::new(0.5, 0.5 - radius / 2.0), 1.0)
+            .expect("should see inside of the image circle");
+        let angle = ray.direction_vector.dot(Vec3A::new(0.0, 0.0, -1.0)).acos();
+        assert_abs_diff_eq!(angle, expected_angle, epsilon = 1e-4);
+        assert!(ray.direction_vector.y >= 0.0);
+    }
+
+    #[test]
+    fn fisheye_does_not_see_outside_of_the_image_circle() {
+        let projection = Fisheye {
+            field_of_view: PI,
+            mapping: FisheyeMapping::Equidistant,
+        };
+        assert_eq!(projection.ray_for_film(Vec2::ZERO, 1.0), None);
+    }
+
+    #[rstest]
+    #[case::behind_on_the_left_edge(Vec2::new(0.0, 0.5), Vec3A::new(0.0, 0.0, 1.0))]
+    #[case::to_the_right(Vec2::new(0.75, 0.5), Vec3A::new(-1.0, 0.0, 0.0))]
+    #[case::straight_up(Vec2::new(0.3, 0.0), Vec3A::new(0.0, 1.0, 0.0))]
+    #[case::straight_down(Vec2::new(0.6, 1.0), Vec3A::new(0.0, -1.0, 0.0))]
+    fn equirectangular_film_covers_all_directions(#[case] film: Vec2, #[case] expected: Vec3A) {
+        let ray = Equirectangular
+            .ray_for_film(film, 2.0)
+            .expect("should see every direction");
+        assert!(ray.direction_vector.abs_diff_eq(expected, 1e-5));
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use glam::{Affine3A, Vec2, Vec3A};
+
+use crate::bump_map::TangentFrame;
+use crate::intersection::{Intersections, SingleIntersection, EPSILON};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::shape::Shape;
+
+static QUAD_ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Flat parallelogram spanning from the corner along two edges, e.g. a light panel or a wall.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Quad {
+    pub id: String,
+    corner: Vec3A,
+    edge_u: Vec3A,
+    edge_v: Vec3A,
+    normal: Vec3A,
+    transform: Affine3A,
+    material: Material,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3A, edge_u: Vec3A, edge_v: Vec3A) -> Self {
+        let quad_id = format!("quad-{}", QUAD_ID_COUNTER.fetch_add(1, Ordering::Relaxed));
+
+        Self {
+            id: quad_id,
+            corner,
+            edge_u,
+            edge_v,
+            // Same orientation as a triangle with the vertices corner, corner + edge_u, corner + edge_v
+            normal: edge_v.cross(edge_u).normalize(),
+            transform: Affine3A::default(),
+            material: Material::default(),
+        }
+    }
+
+    pub fn set_transform(&mut self, transform: Affine3A) {
+        self.transform = transform
+    }
+
+    pub fn set_material(&mut self, material: Material) {
+        self.material = material
+    }
+
+    /// Coordinates of the point along the edges, both are in the 0.0 - 1.0 range for points on the quad.
+    fn edge_coordinates(&self, local_point: Vec3A) -> Vec2 {
+        let offset = local_point - self.corner;
+        let uu = self.edge_u.dot(self.edge_u);
+        let uv = self.edge_u.dot(self.edge_v);
+        let vv = self.edge_v.dot(self.edge_v);
+        let determinant = uu * vv - uv * uv;
+        Vec2::new(
+            (offset.dot(self.edge_u) * vv - offset.dot(self.edge_v) * uv) / determinant,
+            (offset.dot(self.edge_v) * uu - offset.dot(self.edge_u) * uv) / determinant,
+        )
+    }
+}
+
+impl Shape for Quad {
+    fn id(&self) -> &String {
+        &self.id
+    }
+
+    fn transform(&self) -> &Affine3A {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections<'_> {
+        let denominator = local_ray.direction_vector.dot(self.normal);
+        if denominator.abs() < EPSILON {
+            return Intersections::new(vec![]);
+        }
+
+        let t = (self.corner - local_ray.origin_point).dot(self.normal) / denominator;
+        let coordinates = self.edge_coordinates(local_ray.position(t));
+        if !(0.0..=1.0).contains(&coordinates.x) || !(0.0..=1.0).contains(&coordinates.y) {
+            return Intersections::new(vec![]);
+        }
+        Intersections::new(vec![SingleIntersection::new_with_uv(
+            t,
+            &self.id,
+            coordinates.x,
+            coordinates.y,
+        )])
+    }
+
+    fn local_normal_at(&self, _local_point: Vec3A) -> Vec3A {
+        self.normal
+    }
+
+    fn local_uv_at(&self, _local_point: Vec3A, hit: &SingleIntersection) -> Vec2 {
+        Vec2::new(hit.u, hit.v)
+    }
+
+    fn local_tangent_frame_at(
+        &self,
+        _local_point: Vec3A,
+        _hit: &SingleIntersection,
+    ) -> TangentFrame {
+        TangentFrame::new(self.edge_u, self.edge_v, self.normal)
+    }
+
+    fn local_area(&self) -> f32 {
+        self.edge_u.cross(self.edge_v).length()
+    }
+
+    fn local_sample_point(&self, u: Vec2) -> Vec3A {
+        self.corner + self.edge_u * u.x + self.edge_v * u.y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use approx::assert_abs_diff_eq;
+    use glam::Vec3;
+    use rstest::*;
+
+    use crate::{bump_map::BumpMap, color::Color, texture::ImageTexture};
+
+    use super::*;
+
+    #[fixture]
+    pub fn quad() -> Quad {
+        Quad::new(
+            Vec3A::new(-1.0, 0.0, -1.0),
+            Vec3A::new(2.0, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, 4.0),
+        )
+    }
+
+    #[rstest]
+    fn constructing_a_quad(quad: Quad) {
+        assert!(quad.id.starts_with("quad-"));
+        assert_eq!(quad.local_normal_at(Vec3A::ZERO), Vec3A::new(0.0, 1.0, 0.0));
+        assert_abs_diff_eq!(quad.local_area(), 8.0);
+    }
+
+    #[rstest]
+    #[case::from_above(Vec3A::new(0.0, 2.0, 0.0), Vec3A::new(0.0, -1.0, 0.0), Some((2.0, Vec2::new(0.5, 0.25))))]
+    #[case::from_below(Vec3A::new(0.5, -1.0, 2.0), Vec3A::new(0.0, 1.0, 0.0), Some((1.0, Vec2::new(0.75, 0.75))))]
+    #[case::outside_of_the_edges(Vec3A::new(1.5, 2.0, 0.0), Vec3A::new(0.0, -1.0, 0.0), None)]
+    #[case::parallel(Vec3A::new(0.0, 1.0, 0.0), Vec3A::new(1.0, 0.0, 0.0), None)]
+    fn intersecting_a_quad(
+        quad: Quad,
+        #[case] origin: Vec3A,
+        #[case] direction: Vec3A,
+        #[case] expected: Option<(f32, Vec2)>,
+    ) {
+        let intersections = quad.local_intersect(Ray::new(origin, direction));
+        match expected {
+            Some((t, uv)) => {
+                assert_eq!(intersections.i.len(), 1);
+                let hit = &intersections.i[0];
+                assert_abs_diff_eq!(hit.t, t, epsilon = 1e-6);
+                assert!(quad.local_uv_at(Vec3A::ZERO, hit).abs_diff_eq(uv, 1e-6));
+            }
+            None => assert!(intersections.i.is_empty()),
+        }
+    }
+
+    #[rstest]
+    fn normal_map_follows_the_edges_of_the_quad(mut quad: Quad) {
+        let quad_id = quad.id.clone();
+        let hit = SingleIntersection::new_with_uv(1.0, &quad_id, 0.5, 0.5);
+        let frame = quad.local_tangent_frame_at(Vec3A::ZERO, &hit);
+        assert!(frame.tangent.abs_diff_eq(Vec3A::new(1.0, 0.0, 0.0), 1e-6));
+        assert!(frame.bitangent.abs_diff_eq(Vec3A::new(0.0, 0.0, 1.0), 1e-6));
+
+        // Tangent space normal (1, 0, 1) is tilted 45 degrees towards the u edge
+        quad.set_material(Material {
+            bump_map: Some(BumpMap::NormalMap {
+                texture: Arc::new(ImageTexture::new(
+                    1,
+                    1,
+                    vec![Color::new_color(1.0, 0.5, 1.0)],
+                )),
+                strength: 1.0,
+            }),
+            ..Default::default()
+        });
+        let frame = quad.tangent_frame_at(Vec3A::ZERO, &hit);
+        assert!(frame
+            .normal
+            .abs_diff_eq(Vec3A::new(1.0, 1.0, 0.0).normalize(), 1e-6));
+    }
+
+    #[rstest]
+    fn sampled_points_cover_the_transformed_quad(mut quad: Quad) {
+        quad.set_transform(Affine3A::from_scale(Vec3::new(3.0, 1.0, 1.0)));
+        let (point, pdf) = quad.sample_point(Vec2::new(1.0, 0.5));
+        assert!(point.abs_diff_eq(Vec3A::new(3.0, 0.0, 1.0), 1e-6));
+        assert_abs_diff_eq!(1.0 / pdf, 24.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(quad.area_pdf(point), pdf);
+    }
+}
@@ -4,16 +4,29 @@ use glam::{Affine3A, Vec3A};
 pub struct Ray {
     pub origin_point: Vec3A,
     pub direction_vector: Vec3A,
+    /// Moment within the camera shutter interval the ray travels at, objects in motion are intersected
+    /// where they are at that time
+    pub time: f32,
 }
 
 impl Ray {
     pub fn new(origin_point: Vec3A, direction_vector: Vec3A) -> Self {
+        Self::new_at_time(origin_point, direction_vector, 0.0)
+    }
+
+    pub fn new_at_time(origin_point: Vec3A, direction_vector: Vec3A, time: f32) -> Self {
         Self {
             origin_point,
             direction_vector,
+            time,
         }
     }
 
+    /// Ray continuing the path of this one from another point, at the same time.
+    pub fn spawn(&self, origin_point: Vec3A, direction_vector: Vec3A) -> Self {
+        Self::new_at_time(origin_point, direction_vector, self.time)
+    }
+
     pub fn position(&self, time: f32) -> Vec3A {
         self.origin_point + self.direction_vector * time
     }
@@ -23,10 +36,16 @@ impl Ray {
         Self {
             origin_point: transformation.transform_point3a(self.origin_point),
             direction_vector: transformation.transform_vector3a(self.direction_vector),
+            time: self.time,
         }
     }
 }
 
+/// Reflects the vector around the normal.
+pub fn reflect(vector: Vec3A, normal: Vec3A) -> Vec3A {
+    vector - normal * 2.0 * vector.dot(normal)
+}
+
 #[cfg(test)]
 mod tests {
     use approx::assert_abs_diff_eq;
@@ -91,6 +110,25 @@ mod tests {
             .abs_diff_eq(expected_point, f32::EPSILON));
     }
 
+    #[rstest]
+    #[case::approaching_at_45_degrees(
+        Vec3A::new(1.0, -1.0, 0.0),
+        Vec3A::new(0.0, 1.0, 0.0),
+        Vec3A::new(1.0, 1.0, 0.0)
+    )]
+    #[case::off_a_slanted_surface(
+        Vec3A::new(0.0, -1.0, 0.0),
+        Vec3A::new(std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2, 0.0),
+        Vec3A::new(1.0, 0.0, 0.0)
+    )]
+    fn can_reflect_a_vector(
+        #[case] vector: Vec3A,
+        #[case] normal: Vec3A,
+        #[case] expected_vector: Vec3A,
+    ) {
+        assert!(reflect(vector, normal).abs_diff_eq(expected_vector, 1e-6));
+    }
+
     #[rstest]
     fn can_translate_a_ray(ray2: Ray) {
         let translation = Affine3A::from_translation(Vec3::new(3.0, 4.0, 5.0));
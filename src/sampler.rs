@@ -0,0 +1,553 @@
+use std::fmt::Debug;
+
+use glam::Vec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::hash::{hash_values, mix_bits};
+
+/// Largest f32 below 1.0, samples are always in the 0.0 - 1.0 (exclusive) range.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Source of sample values for everything that needs randomness while rendering a pixel sample
+/// (position within the pixel, points on area lights, points on the lens...).
+///
+/// Each call to `next_1d` or `next_2d` consumes the next dimension of the current pixel sample. Values depend
+/// only on the pixel, the sample index, the dimension and the seed, so renders are reproducible no matter
+/// in which order (or on which thread) pixels are rendered.
+pub trait Sampler: Debug + Send + Sync {
+    fn samples_per_pixel(&self) -> u32;
+
+    /// Whether the first power of two samples of a pixel cover it evenly on their own (they are independent
+    /// or stratified), so adaptive sampling can stop after any of them.
+    fn has_unbiased_prefixes(&self) -> bool;
+
+    /// Starts a new sample of the pixel, dimensions are consumed from the beginning again.
+    fn start_pixel_sample(&mut self, x: u16, y: u16, sample_index: u32);
+
+    fn next_1d(&mut self) -> f32;
+
+    fn next_2d(&mut self) -> Vec2;
+
+    fn clone_boxed(&self) -> Box<dyn Sampler>;
+}
+
+impl Clone for Box<dyn Sampler> {
+    fn clone(&self) -> Self {
+        self.clone_boxed()
+    }
+}
+
+/// Position of the current sample, shared by the samplers that compute values from it.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+struct PixelSample {
+    x: u16,
+    y: u16,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl PixelSample {
+    fn start(&mut self, x: u16, y: u16, sample_index: u32) {
+        *self = Self {
+            x,
+            y,
+            sample_index,
+            dimension: 0,
+        }
+    }
+
+    /// Hash of the pixel and the current dimension, then moves on to the next dimension.
+    fn next_dimension_hash(&mut self, seed: u64) -> u64 {
+        let hash = hash_values(seed, &[self.x as u64, self.y as u64, self.dimension as u64]);
+        self.dimension += 1;
+        hash
+    }
+}
+
+/// Uniformly random values, every dimension is independent of the others.
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    rng: StdRng,
+}
+
+impl IndependentSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        if samples_per_pixel == 0 {
+            panic!("At least one sample per pixel is required");
+        }
+
+        Self {
+            samples_per_pixel,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn has_unbiased_prefixes(&self) -> bool {
+        true
+    }
+
+    fn start_pixel_sample(&mut self, x: u16, y: u16, sample_index: u32) {
+        self.rng = StdRng::seed_from_u64(hash_values(
+            self.seed,
+            &[x as u64, y as u64, sample_index as u64],
+        ));
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    fn next_2d(&mut self) -> Vec2 {
+        Vec2::new(self.rng.gen(), self.rng.gen())
+    }
+
+    fn clone_boxed(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Divides each dimension into strata and puts every pixel sample into a different stratum. Strata are
+/// shuffled differently for each dimension, so the dimensions are not correlated. Without jitter samples
+/// are in the centers of the strata, which gives a regular grid.
+#[derive(Debug, Clone)]
+pub struct StratifiedSampler {
+    x_strata: u32,
+    y_strata: u32,
+    jitter: bool,
+    seed: u64,
+    current: PixelSample,
+    rng: StdRng,
+}
+
+impl StratifiedSampler {
+    /// Takes `x_strata * y_strata` samples per pixel, 2D dimensions are split into an `x_strata` by `y_strata` grid.
+    pub fn new(x_strata: u32, y_strata: u32, jitter: bool, seed: u64) -> Self {
+        if x_strata == 0 || y_strata == 0 {
+            panic!(
+                "Stratified sampler needs at least one stratum in each direction, x: {}, y: {}",
+                x_strata, y_strata
+            );
+        }
+
+        Self {
+            x_strata,
+            y_strata,
+            jitter,
+            seed,
+            current: PixelSample::default(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn offset_within_stratum(&mut self) -> f32 {
+        if self.jitter {
+            self.rng.gen()
+        } else {
+            0.5
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.x_strata * self.y_strata
+    }
+
+    fn has_unbiased_prefixes(&self) -> bool {
+        // Strata are shuffled, the first samples can all fall into one part of the pixel
+        false
+    }
+
+    fn start_pixel_sample(&mut self, x: u16, y: u16, sample_index: u32) {
+        self.current.start(x, y, sample_index);
+        self.rng = StdRng::seed_from_u64(hash_values(
+            self.seed,
+            &[x as u64, y as u64, sample_index as u64],
+        ));
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let samples_per_pixel = self.samples_per_pixel();
+        let hash = self.current.next_dimension_hash(self.seed);
+        let stratum =
+            permutation_element(self.current.sample_index, samples_per_pixel, hash as u32);
+        ((stratum as f32 + self.offset_within_stratum()) / samples_per_pixel as f32)
+            .min(ONE_MINUS_EPSILON)
+    }
+
+    fn next_2d(&mut self) -> Vec2 {
+        let hash = self.current.next_dimension_hash(self.seed);
+        let stratum = permutation_element(
+            self.current.sample_index,
+            self.samples_per_pixel(),
+            hash as u32,
+        );
+        let x = (stratum % self.x_strata) as f32 + self.offset_within_stratum();
+        let y = (stratum / self.x_strata) as f32 + self.offset_within_stratum();
+        Vec2::new(
+            (x / self.x_strata as f32).min(ONE_MINUS_EPSILON),
+            (y / self.y_strata as f32).min(ONE_MINUS_EPSILON),
+        )
+    }
+
+    fn clone_boxed(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Halton low-discrepancy sequence, each dimension uses the radical inverse in the next prime base.
+/// The sequence is shifted by a random offset per pixel and dimension (Cranley-Patterson rotation),
+/// so neighbouring pixels do not repeat the same pattern.
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    current: PixelSample,
+}
+
+impl HaltonSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        if samples_per_pixel == 0 {
+            panic!("At least one sample per pixel is required");
+        }
+
+        Self {
+            samples_per_pixel,
+            seed,
+            current: PixelSample::default(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn has_unbiased_prefixes(&self) -> bool {
+        true
+    }
+
+    fn start_pixel_sample(&mut self, x: u16, y: u16, sample_index: u32) {
+        self.current.start(x, y, sample_index)
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.current.dimension as usize;
+        let hash = self.current.next_dimension_hash(self.seed);
+        let rotation = unit_float_from_bits(hash as u32);
+        // Dimensions beyond the prime table fall back to random values
+        let value = match PRIMES.get(dimension) {
+            Some(&base) => radical_inverse(base, self.current.sample_index) + rotation,
+            None => unit_float_from_bits(mix_bits(hash ^ self.current.sample_index as u64) as u32),
+        };
+        value.fract().min(ONE_MINUS_EPSILON)
+    }
+
+    fn next_2d(&mut self) -> Vec2 {
+        Vec2::new(self.next_1d(), self.next_1d())
+    }
+
+    fn clone_boxed(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Owen-scrambled Sobol points. Every dimension (or pair of dimensions for 2D samples) uses the first
+/// two Sobol dimensions, with sample order shuffled per dimension (padding) and a different scramble,
+/// which keeps the stratification of the points. Works best with a power of two samples per pixel.
+#[derive(Debug, Clone)]
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    current: PixelSample,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        if samples_per_pixel == 0 {
+            panic!("At least one sample per pixel is required");
+        }
+
+        Self {
+            samples_per_pixel,
+            seed,
+            current: PixelSample::default(),
+        }
+    }
+
+    fn shuffled_index(&self, hash: u64) -> u32 {
+        permutation_element(
+            self.current.sample_index,
+            self.samples_per_pixel,
+            mix_bits(hash) as u32,
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn has_unbiased_prefixes(&self) -> bool {
+        true
+    }
+
+    fn start_pixel_sample(&mut self, x: u16, y: u16, sample_index: u32) {
+        self.current.start(x, y, sample_index)
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let hash = self.current.next_dimension_hash(self.seed);
+        let index = self.shuffled_index(hash);
+        unit_float_from_bits(owen_scramble(sobol_first_dimension(index), hash as u32))
+    }
+
+    fn next_2d(&mut self) -> Vec2 {
+        let hash = self.current.next_dimension_hash(self.seed);
+        let index = self.shuffled_index(hash);
+        Vec2::new(
+            unit_float_from_bits(owen_scramble(sobol_first_dimension(index), hash as u32)),
+            unit_float_from_bits(owen_scramble(
+                sobol_second_dimension(index),
+                (hash >> 32) as u32,
+            )),
+        )
+    }
+
+    fn clone_boxed(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Maps 32 random bits to a float in the 0.0 - 1.0 (exclusive) range.
+fn unit_float_from_bits(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Mirrors the digits of the index in the given base around the decimal point.
+fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed_digits = 0_u64;
+    let mut inverse_base_power = 1.0;
+    while index > 0 {
+        reversed_digits = reversed_digits * base as u64 + (index % base) as u64;
+        inverse_base_power *= inverse_base;
+        index /= base;
+    }
+    ((reversed_digits as f64 * inverse_base_power) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// First Sobol dimension is the van der Corput sequence in base 2.
+fn sobol_first_dimension(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+/// Second Sobol dimension, its generator matrix is the Pascal triangle modulo 2.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut direction = 1_u32 << 31;
+    let mut value = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            value ^= direction;
+        }
+        direction ^= direction >> 1;
+        index >>= 1;
+    }
+    value
+}
+
+/// Nested uniform (Owen) scrambling using a hash-based Laine-Karras permutation on reversed bits.
+fn owen_scramble(value: u32, seed: u32) -> u32 {
+    let mut value = value.reverse_bits();
+    value ^= value.wrapping_mul(0x3d20adea);
+    value = value.wrapping_add(seed);
+    value = value.wrapping_mul((seed >> 16) | 1);
+    value ^= value.wrapping_mul(0x05526c56);
+    value ^= value.wrapping_mul(0x53a22864);
+    value.reverse_bits()
+}
+
+/// Element at the index of a random permutation of 0..length, chosen by the seed (Kensler's hashing
+/// permutation). Allows shuffling sample order without storing the permutation.
+fn permutation_element(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // Cycle walking, values outside of the range are permuted again until they fit
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < length {
+            break;
+        }
+    }
+    (index.wrapping_add(seed)) % length
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    fn all_samplers() -> Vec<Box<dyn Sampler>> {
+        vec![
+            Box::new(IndependentSampler::new(16, 0)),
+            Box::new(StratifiedSampler::new(4, 4, true, 0)),
+            Box::new(HaltonSampler::new(16, 0)),
+            Box::new(SobolSampler::new(16, 0)),
+        ]
+    }
+
+    fn first_dimensions(sampler: &mut dyn Sampler, x: u16, y: u16, sample_index: u32) -> Vec<f32> {
+        sampler.start_pixel_sample(x, y, sample_index);
+        let pixel = sampler.next_2d();
+        vec![pixel.x, pixel.y, sampler.next_1d(), sampler.next_1d()]
+    }
+
+    #[test]
+    fn samples_are_in_the_unit_range() {
+        for mut sampler in all_samplers() {
+            for sample_index in 0..sampler.samples_per_pixel() {
+                sampler.start_pixel_sample(3, 5, sample_index);
+                for _ in 0..40 {
+                    let value = sampler.next_1d();
+                    assert!((0.0..1.0).contains(&value), "{:?}: {}", sampler, value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_depend_only_on_the_pixel_and_sample_index() {
+        for mut sampler in all_samplers() {
+            let samples = first_dimensions(sampler.as_mut(), 7, 9, 3);
+            // Rendering other pixels in between does not change the values
+            first_dimensions(sampler.as_mut(), 1, 1, 0);
+            assert_eq!(samples, first_dimensions(sampler.as_mut(), 7, 9, 3));
+            assert_eq!(samples, first_dimensions(sampler.clone().as_mut(), 7, 9, 3));
+            assert_ne!(samples, first_dimensions(sampler.as_mut(), 9, 7, 3));
+            assert_ne!(samples, first_dimensions(sampler.as_mut(), 7, 9, 4));
+        }
+    }
+
+    #[rstest]
+    #[case::stratified(Box::new(StratifiedSampler::new(4, 4, true, 0)))]
+    #[case::sobol(Box::new(SobolSampler::new(16, 0)))]
+    fn pixel_samples_are_stratified(#[case] mut sampler: Box<dyn Sampler>) {
+        let mut cells = vec![0; 16];
+        let mut intervals = vec![0; 16];
+        for sample_index in 0..16 {
+            sampler.start_pixel_sample(2, 3, sample_index);
+            let sample = sampler.next_2d();
+            cells[(sample.y * 4.0) as usize * 4 + (sample.x * 4.0) as usize] += 1;
+            intervals[(sampler.next_1d() * 16.0) as usize] += 1;
+        }
+        assert_eq!(cells, vec![1; 16]);
+        assert_eq!(intervals, vec![1; 16]);
+    }
+
+    #[test]
+    fn stratified_samples_in_the_last_stratum_stay_below_one() {
+        let mut sampler = StratifiedSampler::new(64, 64, true, 0);
+        for sample_index in 0..sampler.samples_per_pixel() {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            for _ in 0..4 {
+                assert!(sampler.next_1d() < 1.0);
+                assert!(sampler.next_2d().cmplt(Vec2::ONE).all());
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_sampler_without_jitter_samples_the_centers_of_a_grid() {
+        let mut sampler = StratifiedSampler::new(2, 2, false, 0);
+        let mut samples: Vec<(f32, f32)> = (0..4)
+            .map(|sample_index| {
+                sampler.start_pixel_sample(0, 0, sample_index);
+                sampler.next_2d().into()
+            })
+            .collect();
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            samples,
+            vec![(0.25, 0.25), (0.25, 0.75), (0.75, 0.25), (0.75, 0.75)]
+        );
+    }
+
+    #[rstest]
+    #[case(2, 1, 0.5)]
+    #[case(2, 3, 0.75)]
+    #[case(2, 6, 0.375)]
+    #[case(3, 1, 1.0 / 3.0)]
+    #[case(3, 5, 7.0 / 9.0)]
+    fn radical_inverse_mirrors_digits(
+        #[case] base: u32,
+        #[case] index: u32,
+        #[case] expected: f32,
+    ) {
+        assert_abs_diff_eq!(radical_inverse(base, index), expected, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn sobol_dimensions_match_the_unscrambled_sequence() {
+        let first: Vec<f32> = (0..4)
+            .map(|i| unit_float_from_bits(sobol_first_dimension(i)))
+            .collect();
+        let second: Vec<f32> = (0..4)
+            .map(|i| unit_float_from_bits(sobol_second_dimension(i)))
+            .collect();
+        assert_eq!(first, vec![0.0, 0.5, 0.25, 0.75]);
+        assert_eq!(second, vec![0.0, 0.5, 0.75, 0.25]);
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(7)]
+    #[case(16)]
+    #[case(100)]
+    fn permutation_element_is_a_permutation(#[case] length: u32) {
+        let mut elements: Vec<u32> = (0..length)
+            .map(|index| permutation_element(index, length, 0xdeadbeef))
+            .collect();
+        elements.sort();
+        assert_eq!(elements, (0..length).collect::<Vec<u32>>());
+    }
+}
@@ -0,0 +1,242 @@
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use glam::{Vec2, Vec3A};
+
+/// Maps a uniform sample from the unit square to the unit disk, keeping strata adjacent (Shirley's concentric mapping).
+pub fn concentric_sample_disk(u: Vec2) -> Vec2 {
+    let offset = u * 2.0 - Vec2::ONE;
+    if offset == Vec2::ZERO {
+        return Vec2::ZERO;
+    }
+
+    let (radius, theta) = if offset.x.abs() > offset.y.abs() {
+        (offset.x, FRAC_PI_4 * (offset.y / offset.x))
+    } else {
+        (offset.y, FRAC_PI_2 - FRAC_PI_4 * (offset.x / offset.y))
+    };
+    Vec2::new(theta.cos(), theta.sin()) * radius
+}
+
+/// Maps a uniform sample from the unit square to a regular polygon inscribed in the unit circle, with one of its
+/// corners at the angle `rotation` from +x. The polygon is split into triangles around its center, `u.x` picks
+/// one of them.
+pub fn uniform_sample_polygon(u: Vec2, sides: u32, rotation: f32) -> Vec2 {
+    if sides < 3 {
+        panic!("Polygon should have at least 3 sides, sides: {}", sides);
+    }
+
+    let scaled = u.x * sides as f32;
+    let side = scaled.floor().min(sides as f32 - 1.0);
+    let corner = |index: f32| {
+        let angle = rotation + 2.0 * PI * index / sides as f32;
+        Vec2::new(angle.cos(), angle.sin())
+    };
+    // Uniform point in the triangle between the center and the two corners of the side
+    let sqrt_v = u.y.sqrt();
+    let along = scaled - side;
+    corner(side) * (sqrt_v * (1.0 - along)) + corner(side + 1.0) * (sqrt_v * along)
+}
+
+/// Direction on the hemisphere around +z, with density proportional to the cosine of the angle from +z
+/// (Malley's method). Meant to be used in a tangent frame, where z is the normal.
+pub fn cosine_sample_hemisphere(u: Vec2) -> Vec3A {
+    let disk = concentric_sample_disk(u);
+    let z = (1.0 - disk.length_squared()).max(0.0).sqrt();
+    Vec3A::new(disk.x, disk.y, z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 {
+    cos_theta.max(0.0) * std::f32::consts::FRAC_1_PI
+}
+
+/// Direction distributed uniformly over the whole sphere.
+pub fn uniform_sample_sphere(u: Vec2) -> Vec3A {
+    let z = 1.0 - 2.0 * u.x;
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.y;
+    Vec3A::new(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> f32 {
+    1.0 / (4.0 * PI)
+}
+
+/// Direction distributed uniformly within the cone around +z, whose half-angle has the cosine `cos_max`.
+pub fn uniform_sample_cone(u: Vec2, cos_max: f32) -> Vec3A {
+    let z = 1.0 - u.x * (1.0 - cos_max);
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.y;
+    Vec3A::new(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+pub fn uniform_cone_pdf(cos_max: f32) -> f32 {
+    1.0 / (2.0 * PI * (1.0 - cos_max))
+}
+
+/// Heuristic weighting the estimates of two sampling strategies in multiple importance sampling. Each
+/// strategy's weight is based on how likely the other one is to sample the same direction.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum MisHeuristic {
+    Balance,
+    /// Balance heuristic with squared pdfs, it favors the better strategy more strongly
+    Power,
+}
+
+impl MisHeuristic {
+    /// Weight of a sample taken with the strategy of pdf `f_pdf`, the other strategy has pdf `g_pdf`.
+    pub fn weight(&self, f_pdf: f32, g_pdf: f32) -> f32 {
+        let (f, g) = match self {
+            Self::Balance => (f_pdf, g_pdf),
+            Self::Power => (f_pdf * f_pdf, g_pdf * g_pdf),
+        };
+        if f == 0.0 {
+            return 0.0;
+        }
+        if f.is_infinite() {
+            return 1.0;
+        }
+        f / (f + g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(Vec2::new(0.5, 0.5), Vec2::ZERO)]
+    #[case(Vec2::new(1.0, 0.5), Vec2::new(1.0, 0.0))]
+    #[case(Vec2::new(0.5, 0.0), Vec2::new(0.0, -1.0))]
+    #[case(Vec2::new(0.0, 0.5), Vec2::new(-1.0, 0.0))]
+    fn concentric_mapping_maps_square_to_disk(#[case] u: Vec2, #[case] expected: Vec2) {
+        assert!(concentric_sample_disk(u).abs_diff_eq(expected, 1e-6));
+    }
+
+    #[rstest]
+    #[case(3, 0.0)]
+    #[case(6, 0.3)]
+    fn polygon_samples_cover_the_polygon_uniformly(#[case] sides: u32, #[case] rotation: f32) {
+        let apothem = (PI / sides as f32).cos();
+        let steps = 32;
+        let mut inner_count = 0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u = Vec2::new(
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                let point = uniform_sample_polygon(u, sides, rotation);
+                // Distance towards the farthest side, each side faces halfway between its corners
+                let farthest = (0..sides)
+                    .map(|side| {
+                        let angle = rotation + PI * (2 * side + 1) as f32 / sides as f32;
+                        point.dot(Vec2::new(angle.cos(), angle.sin()))
+                    })
+                    .fold(f32::MIN, f32::max);
+                assert!(farthest <= apothem + 1e-5);
+                inner_count += (farthest < 0.5 * apothem) as u32;
+            }
+        }
+        // The inner polygon at half the size has a quarter of the area
+        assert_abs_diff_eq!(
+            inner_count as f32 / (steps * steps) as f32,
+            0.25,
+            epsilon = 0.01
+        );
+    }
+
+    #[test]
+    fn cosine_weighted_directions_are_on_the_upper_hemisphere() {
+        for i in 0..16 {
+            for j in 0..16 {
+                let u = Vec2::new((i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0);
+                assert!(concentric_sample_disk(u).length() <= 1.0 + 1e-6);
+                let direction = cosine_sample_hemisphere(u);
+                assert_abs_diff_eq!(direction.length(), 1.0, epsilon = 1e-5);
+                assert!(direction.z >= 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn cosine_weighted_directions_average_to_two_thirds_height() {
+        // Mean of cos(theta) under the cosine-weighted distribution is 2/3
+        let steps = 64;
+        let mut sum = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u = Vec2::new(
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                sum += cosine_sample_hemisphere(u).z;
+            }
+        }
+        assert_abs_diff_eq!(sum / (steps * steps) as f32, 2.0 / 3.0, epsilon = 2e-3);
+        assert_abs_diff_eq!(cosine_hemisphere_pdf(1.0), std::f32::consts::FRAC_1_PI);
+        assert_abs_diff_eq!(cosine_hemisphere_pdf(-0.5), 0.0);
+    }
+
+    #[test]
+    fn uniform_sphere_directions_average_to_the_center() {
+        let steps = 64;
+        let mut sum = Vec3A::ZERO;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u = Vec2::new(
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                let direction = uniform_sample_sphere(u);
+                assert_abs_diff_eq!(direction.length(), 1.0, epsilon = 1e-5);
+                sum += direction;
+            }
+        }
+        assert!((sum / (steps * steps) as f32).abs_diff_eq(Vec3A::ZERO, 1e-3));
+    }
+
+    #[test]
+    fn uniform_cone_directions_stay_within_the_cone() {
+        let cos_max = 0.9;
+        let steps = 32;
+        let mut sum_z = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u = Vec2::new(i as f32 + 0.5, j as f32 + 0.5) / steps as f32;
+                let direction = uniform_sample_cone(u, cos_max);
+                assert_abs_diff_eq!(direction.length(), 1.0, epsilon = 1e-5);
+                assert!(direction.z >= cos_max - 1e-6);
+                sum_z += direction.z;
+            }
+        }
+        // Uniform over the solid angle, so z is uniform between cos_max and 1.0
+        assert_abs_diff_eq!(sum_z / (steps * steps) as f32, 0.95, epsilon = 1e-4);
+        assert_abs_diff_eq!(uniform_cone_pdf(cos_max), 1.0 / (0.2 * PI), epsilon = 1e-4);
+    }
+
+    #[rstest]
+    #[case::balance_equal(MisHeuristic::Balance, 2.0, 2.0, 0.5)]
+    #[case::balance(MisHeuristic::Balance, 3.0, 1.0, 0.75)]
+    #[case::power(MisHeuristic::Power, 3.0, 1.0, 0.9)]
+    #[case::impossible_sample(MisHeuristic::Power, 0.0, 1.0, 0.0)]
+    #[case::other_strategy_cannot_sample(MisHeuristic::Balance, 0.5, 0.0, 1.0)]
+    fn multiple_importance_sampling_weights(
+        #[case] heuristic: MisHeuristic,
+        #[case] f_pdf: f32,
+        #[case] g_pdf: f32,
+        #[case] expected: f32,
+    ) {
+        assert_abs_diff_eq!(heuristic.weight(f_pdf, g_pdf), expected);
+        // Weights of the two strategies sum to one
+        if f_pdf > 0.0 {
+            assert_abs_diff_eq!(
+                heuristic.weight(f_pdf, g_pdf) + heuristic.weight(g_pdf, f_pdf),
+                1.0,
+                epsilon = 1e-6
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::Debug;
+
+use glam::{Affine3A, Vec2, Vec3A};
+
+use crate::{
+    bump_map::TangentFrame,
+    intersection::{Intersections, SingleIntersection},
+    material::Material,
+    ray::Ray,
+};
+
+/// Common interface of all objects that can be placed in the world.
+/// Implementors only deal with object space, conversions from and to world space are done here.
+pub trait Shape: Debug + Send + Sync {
+    fn id(&self) -> &String;
+
+    fn transform(&self) -> &Affine3A;
+
+    /// Transform at the time, shapes in motion override this. It is the same as `transform` at the time 0.0,
+    /// which is also where shapes in motion are sampled as emitters.
+    fn transform_at(&self, _time: f32) -> Affine3A {
+        *self.transform()
+    }
+
+    fn material(&self) -> &Material;
+
+    /// Intersects ray that is already transformed to object space.
+    fn local_intersect(&self, local_ray: Ray) -> Intersections<'_>;
+
+    /// Normal vector in object space for a point that is already transformed to object space.
+    fn local_normal_at(&self, local_point: Vec3A) -> Vec3A;
+
+    /// Texture coordinates of a point in object space, shapes without texture mapping always return (0, 0).
+    fn local_uv_at(&self, _local_point: Vec3A, _hit: &SingleIntersection) -> Vec2 {
+        Vec2::ZERO
+    }
+
+    /// Shading frame in object space. Shapes with texture mapping or interpolated normals override this.
+    fn local_tangent_frame_at(
+        &self,
+        local_point: Vec3A,
+        _hit: &SingleIntersection,
+    ) -> TangentFrame {
+        TangentFrame::from_normal(self.local_normal_at(local_point))
+    }
+
+    /// Surface area in object space.
+    fn local_area(&self) -> f32;
+
+    /// Point distributed uniformly over the surface in object space.
+    fn local_sample_point(&self, u: Vec2) -> Vec3A;
+
+    /// Surface area in world space, approximate when the transform scales non-uniformly.
+    fn area(&self) -> f32 {
+        self.local_area() * self.area_scale_at(self.local_sample_point(Vec2::splat(0.5)))
+    }
+
+    /// Point on the surface in world space sampled with the 2D sample, with its probability density with respect
+    /// to world space area. Used for sampling emissive objects as lights.
+    fn sample_point(&self, u: Vec2) -> (Vec3A, f32) {
+        let local_point = self.local_sample_point(u);
+        let pdf = 1.0 / (self.local_area() * self.area_scale_at(local_point));
+        (self.transform().transform_point3a(local_point), pdf)
+    }
+
+    /// Probability density of `sample_point` choosing the point on the surface, with respect to world space area.
+    fn area_pdf(&self, world_point: Vec3A) -> f32 {
+        let local_point = self.transform().inverse().transform_point3a(world_point);
+        1.0 / (self.local_area() * self.area_scale_at(local_point))
+    }
+
+    /// Ratio of world to object space area around the point. It is constant unless the transform scales
+    /// non-uniformly, then uniform points in object space are not uniform in world space.
+    fn area_scale_at(&self, local_point: Vec3A) -> f32 {
+        let matrix = self.transform().matrix3;
+        let local_normal = self.local_normal_at(local_point).normalize();
+        matrix.determinant().abs() * (matrix.inverse().transpose() * local_normal).length()
+    }
+
+    fn intersect(&self, ray: Ray) -> Intersections<'_> {
+        let mut intersections =
+            self.local_intersect(ray.transform(self.transform_at(ray.time).inverse()));
+        for intersection in intersections.i.iter_mut() {
+            intersection.time = ray.time;
+        }
+        intersections
+    }
+
+    fn normal_at(&self, world_point: Vec3A) -> Vec3A {
+        self.normal_at_time(world_point, 0.0)
+    }
+
+    fn normal_at_time(&self, world_point: Vec3A, time: f32) -> Vec3A {
+        let inverse_transform = self.transform_at(time).inverse();
+        let local_point = inverse_transform.transform_point3a(world_point);
+        let local_normal = self.local_normal_at(local_point);
+        // Normals are transformed with the transposed inverse, translation is dropped
+        let world_normal = inverse_transform.matrix3.transpose() * local_normal;
+        world_normal.normalize()
+    }
+
+    fn uv_at(&self, world_point: Vec3A, hit: &SingleIntersection) -> Vec2 {
+        let local_point = self
+            .transform_at(hit.time)
+            .inverse()
+            .transform_point3a(world_point);
+        self.local_uv_at(local_point, hit)
+    }
+
+    /// Shading frame in world space, with the material's bump map applied.
+    fn tangent_frame_at(&self, world_point: Vec3A, hit: &SingleIntersection) -> TangentFrame {
+        let transform = self.transform_at(hit.time);
+        let local_point = transform.inverse().transform_point3a(world_point);
+        let mut local_frame = self.local_tangent_frame_at(local_point, hit);
+        if let Some(bump_map) = &self.material().bump_map {
+            let uv = self.local_uv_at(local_point, hit);
+            local_frame = bump_map.perturb(&local_frame, local_point, uv);
+        }
+        local_frame.transform(&transform)
+    }
+}
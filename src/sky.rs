@@ -0,0 +1,349 @@
+use std::f32::consts::{FRAC_1_PI, FRAC_PI_2, PI};
+
+use glam::Vec3A;
+
+use crate::color::Color;
+
+/// Angular radius of the sun disk seen from the ground, in radians.
+pub const SUN_ANGULAR_RADIUS: f32 = 0.004_65;
+
+/// Luminance of the sun above the atmosphere, in kcd/m² like the zenith luminance of the sky model.
+const SUN_LUMINANCE: f32 = 1.6e6;
+
+/// Wavelengths in micrometers at which the red, green and blue sunlight is attenuated by the atmosphere.
+const WAVELENGTHS: [f32; 3] = [0.68, 0.55, 0.44];
+
+/// Analytic daylight sky (Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight") with
+/// the sun disk, and a diffuse ground below the horizon lit by both. Radiance is relative to the sky
+/// straight up, which has luminance of `intensity`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Sky {
+    sun_direction: Vec3A,
+    turbidity: f32,
+    ground_albedo: Color,
+    /// Luminance of the sky at the zenith
+    pub intensity: f32,
+    luminance: Perez,
+    chromaticity_x: Perez,
+    chromaticity_y: Perez,
+    zenith_x: f32,
+    zenith_y: f32,
+    sun_radiance: Color,
+    ground_radiance: Color,
+}
+
+/// Coefficients of the Perez sky luminance distribution.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+}
+
+impl Perez {
+    /// `theta` is the angle of the direction from the zenith, `gamma` the angle from the sun.
+    fn f(&self, cos_theta: f32, gamma: f32) -> f32 {
+        (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * gamma.cos().powi(2))
+    }
+}
+
+impl Sky {
+    /// Sky for the sun in the direction, which points from the ground towards the sun. Turbidity is the haziness
+    /// of the atmosphere, from 2.0 for a clear sky to 10.0 for a hazy one. For suns below the horizon the sky
+    /// brightness and color are those of a sun on the horizon, but there is no sun disk and no direct sunlight.
+    pub fn new(sun_direction: Vec3A, turbidity: f32, ground_albedo: Color) -> Self {
+        if !(1.7..=10.0).contains(&turbidity) {
+            panic!(
+                "Sky turbidity should be between 1.7 and 10.0, turbidity: {}",
+                turbidity
+            );
+        }
+
+        let sun_direction = sun_direction.normalize();
+        let theta_sun = sun_direction.y.clamp(0.0, 1.0).acos();
+        let t = turbidity;
+        let mut sky = Self {
+            sun_direction,
+            turbidity,
+            ground_albedo,
+            intensity: 1.0,
+            luminance: Perez {
+                a: 0.1787 * t - 1.4630,
+                b: -0.3554 * t + 0.4275,
+                c: -0.0227 * t + 5.3251,
+                d: 0.1206 * t - 2.5771,
+                e: -0.0670 * t + 0.3703,
+            },
+            chromaticity_x: Perez {
+                a: -0.0193 * t - 0.2592,
+                b: -0.0665 * t + 0.0008,
+                c: -0.0004 * t + 0.2125,
+                d: -0.0641 * t - 0.8989,
+                e: -0.0033 * t + 0.0452,
+            },
+            chromaticity_y: Perez {
+                a: -0.0167 * t - 0.2608,
+                b: -0.0950 * t + 0.0092,
+                c: -0.0079 * t + 0.2102,
+                d: -0.0441 * t - 1.6537,
+                e: -0.0109 * t + 0.0529,
+            },
+            zenith_x: zenith_chromaticity(
+                t,
+                theta_sun,
+                [
+                    [0.00166, -0.00375, 0.00209, 0.0],
+                    [-0.02903, 0.06377, -0.03202, 0.00394],
+                    [0.11693, -0.21196, 0.06052, 0.25886],
+                ],
+            ),
+            zenith_y: zenith_chromaticity(
+                t,
+                theta_sun,
+                [
+                    [0.00275, -0.00610, 0.00317, 0.0],
+                    [-0.04214, 0.08970, -0.04153, 0.00516],
+                    [0.15346, -0.26756, 0.06670, 0.26688],
+                ],
+            ),
+            sun_radiance: Color::new_black(),
+            ground_radiance: Color::new_black(),
+        };
+
+        if sun_direction.y > 0.0 {
+            let zenith_luminance = zenith_luminance(t, theta_sun);
+            let [red, green, blue] = sun_transmittance(t, theta_sun);
+            sky.sun_radiance =
+                Color::new_color(red, green, blue) * (SUN_LUMINANCE / zenith_luminance);
+        }
+        let sun_irradiance = sky.sun_radiance * (sun_solid_angle() * sun_direction.y.max(0.0));
+        sky.ground_radiance = ground_albedo * (sky.sky_irradiance() + sun_irradiance) * FRAC_1_PI;
+        sky
+    }
+
+    pub fn sun_direction(&self) -> Vec3A {
+        self.sun_direction
+    }
+
+    pub fn turbidity(&self) -> f32 {
+        self.turbidity
+    }
+
+    pub fn ground_albedo(&self) -> Color {
+        self.ground_albedo
+    }
+
+    /// Radiance of the sun disk, after it passed through the atmosphere.
+    pub fn sun_radiance(&self) -> Color {
+        self.sun_radiance * self.intensity
+    }
+
+    pub fn sun_cos_angular_radius(&self) -> f32 {
+        SUN_ANGULAR_RADIUS.cos()
+    }
+
+    pub fn color_for_direction(&self, direction: Vec3A) -> Color {
+        let direction = direction.normalize();
+        let radiance = self.radiance_without_sun(direction);
+        if direction.y > 0.0 && direction.dot(self.sun_direction) >= self.sun_cos_angular_radius() {
+            radiance + self.sun_radiance()
+        } else {
+            radiance
+        }
+    }
+
+    /// Radiance of the sky or the ground in the direction, leaving the sun disk out.
+    pub fn radiance_without_sun(&self, direction: Vec3A) -> Color {
+        let direction = direction.normalize();
+        if direction.y <= 0.0 {
+            return self.ground_radiance * self.intensity;
+        }
+        self.sky_radiance(direction) * self.intensity
+    }
+
+    /// Radiance of the sky itself, relative to the zenith.
+    fn sky_radiance(&self, direction: Vec3A) -> Color {
+        let cos_theta = direction.y;
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+        let theta_sun = self.sun_direction.y.clamp(0.0, 1.0).acos();
+        let relative = |perez: &Perez| perez.f(cos_theta, gamma) / perez.f(1.0, theta_sun);
+
+        let luminance = relative(&self.luminance);
+        let x = self.zenith_x * relative(&self.chromaticity_x);
+        let y = self.zenith_y * relative(&self.chromaticity_y);
+        xyy_to_rgb(x, y, luminance)
+    }
+
+    /// Irradiance from the sky on a horizontal surface, the sun left out.
+    fn sky_irradiance(&self) -> Color {
+        let (theta_steps, phi_steps) = (32, 128);
+        let d_theta = FRAC_PI_2 / theta_steps as f32;
+        let d_phi = 2.0 * PI / phi_steps as f32;
+        let mut irradiance = Color::new_black();
+        for i in 0..theta_steps {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..phi_steps {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = Vec3A::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                irradiance +=
+                    self.sky_radiance(direction) * (theta.cos() * theta.sin() * d_theta * d_phi);
+            }
+        }
+        irradiance
+    }
+}
+
+/// Solid angle of the sun disk, 2π(1 - cos r) written so it does not lose precision for the small angle.
+pub fn sun_solid_angle() -> f32 {
+    4.0 * PI * (SUN_ANGULAR_RADIUS / 2.0).sin().powi(2)
+}
+
+/// Absolute zenith luminance in kcd/m².
+fn zenith_luminance(turbidity: f32, theta_sun: f32) -> f32 {
+    let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * theta_sun);
+    ((4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192).max(1e-3)
+}
+
+fn zenith_chromaticity(turbidity: f32, theta_sun: f32, matrix: [[f32; 4]; 3]) -> f32 {
+    let turbidities = [turbidity * turbidity, turbidity, 1.0];
+    let angles = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    matrix
+        .iter()
+        .zip(turbidities)
+        .map(|(row, weight)| weight * row.iter().zip(angles).map(|(m, a)| m * a).sum::<f32>())
+        .sum()
+}
+
+/// Fraction of sunlight passing through the atmosphere, scattered by air molecules (Rayleigh) and by
+/// aerosols, whose amount grows with turbidity (Ångström's formula).
+fn sun_transmittance(turbidity: f32, theta_sun: f32) -> [f32; 3] {
+    let theta_degrees = theta_sun.to_degrees();
+    let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    WAVELENGTHS.map(|lambda| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp()
+    })
+}
+
+/// Converts CIE xyY chromaticity and luminance to linear sRGB.
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Color {
+    if y <= 0.0 {
+        return Color::new_black();
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Color::new_color(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    fn sun_at_elevation(degrees: f32) -> Vec3A {
+        let elevation = degrees.to_radians();
+        Vec3A::new(0.0, elevation.sin(), elevation.cos())
+    }
+
+    #[fixture]
+    pub fn sky() -> Sky {
+        Sky::new(sun_at_elevation(45.0), 3.0, Color::new_color(0.3, 0.3, 0.3))
+    }
+
+    #[rstest]
+    #[case(2.0)]
+    #[case(3.0)]
+    #[case(10.0)]
+    fn zenith_is_as_bright_as_the_intensity(#[case] turbidity: f32) {
+        let mut sky = Sky::new(sun_at_elevation(30.0), turbidity, Color::new_white());
+        assert_abs_diff_eq!(
+            sky.color_for_direction(Vec3A::Y).luminance(),
+            1.0,
+            epsilon = 1e-3
+        );
+        sky.intensity = 5.0;
+        assert_abs_diff_eq!(
+            sky.color_for_direction(Vec3A::Y).luminance(),
+            5.0,
+            epsilon = 5e-3
+        );
+    }
+
+    #[rstest]
+    fn clear_sky_is_blue_and_brightest_around_the_sun(sky: Sky) {
+        let towards_sun = Vec3A::new(0.2, 0.7, 0.7).normalize();
+        let away_from_sun = Vec3A::new(-0.2, 0.7, -0.7).normalize();
+        let near = sky.color_for_direction(towards_sun);
+        let far = sky.color_for_direction(away_from_sun);
+        assert!(near.luminance() > 2.0 * far.luminance());
+        assert!(far.get_blue_val() > far.get_red_val());
+    }
+
+    #[rstest]
+    fn sun_disk_is_only_part_of_the_full_radiance(sky: Sky) {
+        let sun = sky.sun_direction();
+        let with_sun = sky.color_for_direction(sun);
+        let without_sun = sky.radiance_without_sun(sun);
+        assert!(with_sun.luminance() > 1000.0 * without_sun.luminance());
+        assert_eq!(with_sun - without_sun, sky.sun_radiance());
+
+        let beside_sun = (sun + Vec3A::X * 0.01).normalize();
+        assert_eq!(
+            sky.color_for_direction(beside_sun),
+            sky.radiance_without_sun(beside_sun)
+        );
+    }
+
+    #[test]
+    fn setting_sun_is_dimmer_and_redder() {
+        let high = Sky::new(sun_at_elevation(60.0), 3.0, Color::new_black()).sun_radiance;
+        let low = Sky::new(sun_at_elevation(3.0), 3.0, Color::new_black()).sun_radiance;
+        let zenith_luminance_ratio =
+            zenith_luminance(3.0, 87_f32.to_radians()) / zenith_luminance(3.0, 30_f32.to_radians());
+        assert!(low.luminance() * zenith_luminance_ratio < high.luminance());
+        assert!(low.get_blue_val() / low.get_red_val() < high.get_blue_val() / high.get_red_val());
+    }
+
+    #[test]
+    fn sun_below_the_horizon_has_no_disk() {
+        let sky = Sky::new(Vec3A::new(0.0, -0.2, 1.0), 3.0, Color::new_white());
+        assert_eq!(sky.sun_radiance(), Color::new_black());
+    }
+
+    #[rstest]
+    fn ground_is_lit_by_the_sky_and_the_sun(sky: Sky) {
+        let ground = sky.color_for_direction(-Vec3A::Y);
+        let sun_irradiance = sky.sun_radiance() * (sun_solid_angle() * sky.sun_direction().y);
+        let expected =
+            Color::new_color(0.3, 0.3, 0.3) * (sky.sky_irradiance() + sun_irradiance) * FRAC_1_PI;
+        assert_eq!(ground, expected);
+        assert!(ground.luminance() > 0.0);
+
+        let black_ground = Sky::new(sky.sun_direction(), 3.0, Color::new_black());
+        assert_eq!(
+            black_ground.color_for_direction(Vec3A::new(0.3, -0.5, 0.1)),
+            Color::new_black()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Sky turbidity should be between 1.7 and 10.0")]
+    fn turbidity_outside_of_the_model_range_panics() {
+        Sky::new(Vec3A::Y, 20.0, Color::new_white());
+    }
+}
@@ -1,8 +1,13 @@
+use std::f32::consts::PI;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-use glam::{Affine3A, Vec3A};
+use glam::{Affine3A, Vec2, Vec3A};
 
+use crate::bump_map::TangentFrame;
 use crate::intersection::Intersections;
+use crate::material::Material;
+use crate::sampling::uniform_sample_sphere;
+use crate::shape::Shape;
 use crate::{intersection::SingleIntersection, ray::Ray};
 
 // TODO: Maybe a better solution for sphere id? They need to be unique and UUID seems excessive
@@ -13,6 +18,7 @@ pub struct Sphere {
     pub id: String,
     sphere_center_point: Vec3A,
     transform: Affine3A,
+    material: Material,
 }
 
 impl Sphere {
@@ -26,11 +32,33 @@ impl Sphere {
             sphere_center_point,
             id: sphere_id,
             transform: Affine3A::default(),
+            material: Material::default(),
         }
     }
 
-    pub fn intersect(&self, ray: Ray) -> Intersections {
-        let ray_inverse = ray.transform(self.transform.inverse());
+    pub fn set_transform(&mut self, transform: Affine3A) {
+        self.transform = transform
+    }
+
+    pub fn set_material(&mut self, material: Material) {
+        self.material = material
+    }
+}
+
+impl Shape for Sphere {
+    fn id(&self) -> &String {
+        &self.id
+    }
+
+    fn transform(&self) -> &Affine3A {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn local_intersect(&self, ray_inverse: Ray) -> Intersections<'_> {
         let sphere_to_ray = ray_inverse.origin_point - self.sphere_center_point;
         let a = ray_inverse
             .direction_vector
@@ -43,21 +71,46 @@ impl Sphere {
         } else {
             let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
             let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
-            let i1 = SingleIntersection {
-                object_id: &self.id,
-                t: t1,
-            };
-            let i2 = SingleIntersection {
-                object_id: &self.id,
-                t: t2,
-            };
+            let i1 = SingleIntersection::new(t1, &self.id);
+            let i2 = SingleIntersection::new(t2, &self.id);
 
             Intersections::new(vec![i1, i2])
         }
     }
 
-    pub fn set_transform(&mut self, transform: Affine3A) {
-        self.transform = transform
+    fn local_normal_at(&self, local_point: Vec3A) -> Vec3A {
+        local_point - self.sphere_center_point
+    }
+
+    fn local_area(&self) -> f32 {
+        4.0 * PI
+    }
+
+    fn local_sample_point(&self, u: Vec2) -> Vec3A {
+        self.sphere_center_point + uniform_sample_sphere(u)
+    }
+
+    /// Spherical mapping, `u` goes around the y axis and `v` goes from the south pole to the north pole.
+    fn local_uv_at(&self, local_point: Vec3A, _hit: &SingleIntersection) -> Vec2 {
+        let p = local_point - self.sphere_center_point;
+        let theta = p.x.atan2(p.z);
+        let phi = (p.y / p.length()).clamp(-1.0, 1.0).acos();
+        let raw_u = theta / (2.0 * PI);
+        Vec2::new(1.0 - (raw_u + 0.5), 1.0 - phi / PI)
+    }
+
+    fn local_tangent_frame_at(
+        &self,
+        local_point: Vec3A,
+        _hit: &SingleIntersection,
+    ) -> TangentFrame {
+        let p = (local_point - self.sphere_center_point).normalize();
+        // Partial derivatives of the spherical mapping, up to a positive scale
+        let dp_du = Vec3A::new(-p.z, 0.0, p.x);
+        let sin_phi = (p.x * p.x + p.z * p.z).sqrt();
+        let theta = p.x.atan2(p.z);
+        let dp_dv = Vec3A::new(-p.y * theta.sin(), sin_phi, -p.y * theta.cos());
+        TangentFrame::new(dp_du, dp_dv, p)
     }
 }
 
@@ -65,6 +118,7 @@ impl Sphere {
 mod tests {
     use approx::assert_abs_diff_eq;
     use glam::Vec3;
+    use rstest::*;
 
     use super::*;
 
@@ -195,6 +249,106 @@ mod tests {
         );
     }
 
+    #[test]
+    fn a_sphere_has_a_default_material() {
+        let sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        assert_eq!(sphere.material, Material::default());
+    }
+
+    #[test]
+    fn a_sphere_may_be_assigned_a_material() {
+        let mut sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        let material = Material {
+            ambient: 1.0,
+            ..Default::default()
+        };
+        sphere.set_material(material.clone());
+        assert_eq!(sphere.material, material);
+    }
+
+    #[rstest]
+    #[case::on_the_x_axis(Vec3A::new(1.0, 0.0, 0.0), Vec3A::new(1.0, 0.0, 0.0))]
+    #[case::on_the_y_axis(Vec3A::new(0.0, 1.0, 0.0), Vec3A::new(0.0, 1.0, 0.0))]
+    #[case::on_the_z_axis(Vec3A::new(0.0, 0.0, 1.0), Vec3A::new(0.0, 0.0, 1.0))]
+    #[case::at_a_nonaxial_point(
+        Vec3A::new(3_f32.sqrt() / 3.0, 3_f32.sqrt() / 3.0, 3_f32.sqrt() / 3.0),
+        Vec3A::new(3_f32.sqrt() / 3.0, 3_f32.sqrt() / 3.0, 3_f32.sqrt() / 3.0)
+    )]
+    fn the_normal_on_a_sphere(#[case] point: Vec3A, #[case] expected_normal: Vec3A) {
+        let sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        let normal = sphere.normal_at(point);
+        assert!(normal.abs_diff_eq(expected_normal, 1e-6));
+        assert!(normal.abs_diff_eq(normal.normalize(), 1e-6));
+    }
+
+    #[test]
+    fn computing_the_normal_on_a_translated_sphere() {
+        let mut sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        sphere.set_transform(Affine3A::from_translation(Vec3::new(0.0, 1.0, 0.0)));
+        let normal = sphere.normal_at(Vec3A::new(
+            0.0,
+            1.0 + std::f32::consts::FRAC_1_SQRT_2,
+            -std::f32::consts::FRAC_1_SQRT_2,
+        ));
+        assert!(normal.abs_diff_eq(
+            Vec3A::new(
+                0.0,
+                std::f32::consts::FRAC_1_SQRT_2,
+                -std::f32::consts::FRAC_1_SQRT_2
+            ),
+            1e-5
+        ));
+    }
+
+    #[test]
+    fn computing_the_normal_on_a_transformed_sphere() {
+        let mut sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        sphere.set_transform(
+            Affine3A::from_scale(Vec3::new(1.0, 0.5, 1.0))
+                * Affine3A::from_rotation_z(std::f32::consts::PI / 5.0),
+        );
+        let normal = sphere.normal_at(Vec3A::new(
+            0.0,
+            std::f32::consts::FRAC_1_SQRT_2,
+            -std::f32::consts::FRAC_1_SQRT_2,
+        ));
+        assert!(normal.abs_diff_eq(Vec3A::new(0.0, 0.97014, -0.24254), 1e-5));
+    }
+
+    #[rstest]
+    #[case(Vec3A::new(0.0, 0.0, -1.0), Vec2::new(0.0, 0.5))]
+    #[case(Vec3A::new(1.0, 0.0, 0.0), Vec2::new(0.25, 0.5))]
+    #[case(Vec3A::new(0.0, 0.0, 1.0), Vec2::new(0.5, 0.5))]
+    #[case(Vec3A::new(-1.0, 0.0, 0.0), Vec2::new(0.75, 0.5))]
+    #[case(Vec3A::new(0.0, 1.0, 0.0), Vec2::new(0.5, 1.0))]
+    #[case(Vec3A::new(0.0, -1.0, 0.0), Vec2::new(0.5, 0.0))]
+    #[case(
+        Vec3A::new(std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2, 0.0),
+        Vec2::new(0.25, 0.75)
+    )]
+    fn using_a_spherical_mapping_on_a_point(#[case] point: Vec3A, #[case] expected_uv: Vec2) {
+        let sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        let hit = SingleIntersection::new(0.0, &sphere.id);
+        let uv = sphere.local_uv_at(point, &hit);
+        assert_abs_diff_eq!(uv.x.rem_euclid(1.0), expected_uv.x, epsilon = 1e-6);
+        assert_abs_diff_eq!(uv.y, expected_uv.y, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn tangent_frame_on_a_sphere_follows_the_spherical_mapping() {
+        let sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        let hit = SingleIntersection::new(0.0, &sphere.id);
+        let frame = sphere.local_tangent_frame_at(Vec3A::new(0.0, 0.0, -1.0), &hit);
+        assert!(frame.tangent.abs_diff_eq(Vec3A::new(1.0, 0.0, 0.0), 1e-6));
+        assert!(frame.bitangent.abs_diff_eq(Vec3A::new(0.0, 1.0, 0.0), 1e-6));
+        assert!(frame.normal.abs_diff_eq(Vec3A::new(0.0, 0.0, -1.0), 1e-6));
+
+        // Tangent frame is defined at the poles too
+        let frame = sphere.local_tangent_frame_at(Vec3A::new(0.0, 1.0, 0.0), &hit);
+        assert!(frame.tangent.dot(frame.normal).abs() < 1e-6);
+        assert!(frame.bitangent.dot(frame.normal).abs() < 1e-6);
+    }
+
     #[test]
     fn intersecting_a_translated_sphere_with_a_ray() {
         let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
@@ -204,4 +358,25 @@ mod tests {
         let intersection = sphere.intersect(ray);
         assert_eq!(intersection.i.len(), 0);
     }
+
+    #[rstest]
+    #[case::unit(Affine3A::IDENTITY, 4.0 * PI)]
+    #[case::uniformly_scaled(Affine3A::from_scale(Vec3::splat(2.0)), 16.0 * PI)]
+    #[case::translated(Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0)), 4.0 * PI)]
+    fn sampled_points_are_uniform_over_the_sphere(
+        #[case] transform: Affine3A,
+        #[case] expected_area: f32,
+    ) {
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_transform(transform);
+        let center = transform.transform_point3a(Vec3A::ZERO);
+        let (point, pdf) = sphere.sample_point(Vec2::new(0.3, 0.7));
+        assert_abs_diff_eq!(1.0 / pdf, expected_area, epsilon = 1e-3);
+        assert_abs_diff_eq!(sphere.area_pdf(point), pdf, epsilon = 1e-6);
+        assert_abs_diff_eq!(
+            (point - center).length(),
+            (expected_area / (4.0 * PI)).sqrt(),
+            epsilon = 1e-5
+        );
+    }
 }
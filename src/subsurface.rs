@@ -0,0 +1,174 @@
+use crate::{
+    bump_map::TangentFrame,
+    color::Color,
+    intersection::Computations,
+    medium::{Medium, MediumSample},
+    microfacet::fresnel_dielectric,
+    ray::Ray,
+    sampler::Sampler,
+    sampling::cosine_sample_hemisphere,
+    world::World,
+};
+
+/// Random-walk subsurface scattering, for translucent materials such as wax, skin, marble and milk. Light that
+/// is not reflected by the smooth surface enters the object and scatters inside of it, as in a medium, until
+/// it leaves somewhere else. It leaves evenly in all directions, as if the surface were diffuse. The walk only
+/// finds the surfaces around it, so any closed shape works, including triangle meshes.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Subsurface {
+    /// Color of the material, the fraction of light that leaves the object after scattering inside
+    pub albedo: Color,
+    /// Average distance light travels inside before scattering, per color channel. Longer paths make the
+    /// material more translucent in that color.
+    pub mean_free_path: Color,
+    /// Asymmetry of the Henyey-Greenstein phase function inside
+    pub g: f32,
+    /// Index of refraction of the surface, relative to the outside
+    pub ior: f32,
+    /// Walks that scatter more times than this are terminated, losing their light
+    pub max_steps: u32,
+}
+
+impl Default for Subsurface {
+    fn default() -> Self {
+        Self {
+            albedo: Color::new_color(0.8, 0.8, 0.8),
+            mean_free_path: Color::new_color(0.1, 0.1, 0.1),
+            g: 0.0,
+            ior: 1.4,
+            max_steps: 256,
+        }
+    }
+}
+
+impl Subsurface {
+    /// Medium inside of the object. The scattering albedo of a single event is found from the albedo of the
+    /// whole walk with the fit by Chiang et al., "Practical and Controllable Subsurface Scattering for
+    /// Production Path Tracing".
+    pub fn medium(&self) -> Medium {
+        let channel = |albedo: f32, mean_free_path: f32| {
+            let sigma_t = 1.0 / mean_free_path.max(1e-6);
+            (sigma_t, single_scattering_albedo(albedo) * sigma_t)
+        };
+        let (red_t, red_s) = channel(self.albedo.get_red_val(), self.mean_free_path.get_red_val());
+        let (green_t, green_s) = channel(
+            self.albedo.get_green_val(),
+            self.mean_free_path.get_green_val(),
+        );
+        let (blue_t, blue_s) = channel(
+            self.albedo.get_blue_val(),
+            self.mean_free_path.get_blue_val(),
+        );
+        let sigma_s = Color::new_color(red_s, green_s, blue_s);
+        let sigma_t = Color::new_color(red_t, green_t, blue_t);
+        Medium::new(sigma_t - sigma_s, sigma_s, self.g)
+    }
+
+    /// Fraction of light arriving at the cosine of the angle to the normal that is reflected by the surface.
+    pub fn reflectance(&self, cos_theta: f32) -> f32 {
+        fresnel_dielectric(cos_theta.abs(), self.ior)
+    }
+
+    /// Walks through the object from the point light entered it, returns where it leaves the object with
+    /// the weight of the walk. The computations describe the outside of the surface at the exit, their eye
+    /// vector is the normal. Light absorbed inside, or lost when the walk is too long, returns `None`.
+    pub fn random_walk(
+        &self,
+        world: &World,
+        entry: &Computations,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Computations, Color)> {
+        let medium = self.medium();
+        let inward = TangentFrame::from_normal(-entry.normal_vector);
+        let direction = inward.local_to_world(cosine_sample_hemisphere(sampler.next_2d()));
+        let mut ray = Ray::new_at_time(entry.under_point, direction, entry.time);
+        let mut weight = Color::new_white();
+        for _ in 0..self.max_steps {
+            // Open meshes let the walk escape into the world
+            let hit = world.hit_computations(ray)?;
+            match medium.sample_distance(ray, hit.t, sampler) {
+                MediumSample::Scattered {
+                    distance,
+                    weight: scattering,
+                } => {
+                    weight = weight * scattering;
+                    let (wi, _) = medium
+                        .phase
+                        .sample(-ray.direction_vector, sampler.next_2d());
+                    ray = ray.spawn(ray.position(distance), wi);
+                }
+                MediumSample::Absorbed => return None,
+                MediumSample::Transmitted {
+                    weight: transmission,
+                } => return Some((exit_computations(hit), weight * transmission)),
+            }
+        }
+        None
+    }
+}
+
+/// Fraction of light scattered at each event inside, for the fraction that leaves the object after the walk.
+pub fn single_scattering_albedo(albedo: f32) -> f32 {
+    let albedo = albedo.clamp(0.0, 1.0);
+    let term = 4.09712 + 4.20863 * albedo
+        - (9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo).sqrt();
+    (1.0 - term * term).clamp(0.0, 1.0)
+}
+
+/// Turns the hit seen from inside to the other side of the surface, facing the way the light leaves.
+fn exit_computations(hit: Computations) -> Computations {
+    let outward = -hit.normal_vector;
+    Computations {
+        over_point: hit.under_point,
+        under_point: hit.over_point,
+        eye_vector: outward,
+        normal_vector: outward,
+        geometric_normal_vector: -hit.geometric_normal_vector,
+        reflect_vector: outward,
+        inside: !hit.inside,
+        ..hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0.0, 0.0)]
+    #[case(1.0, 1.0)]
+    fn single_scattering_albedo_keeps_the_extremes(#[case] albedo: f32, #[case] expected: f32) {
+        assert_abs_diff_eq!(single_scattering_albedo(albedo), expected, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn single_scattering_albedo_is_higher_than_the_albedo_of_the_walk() {
+        let mut previous = 0.0;
+        for i in 1..10 {
+            let albedo = i as f32 / 10.0;
+            let single = single_scattering_albedo(albedo);
+            assert!(single > albedo);
+            assert!(single > previous);
+            previous = single;
+        }
+    }
+
+    #[test]
+    fn mean_free_path_sets_the_extinction_per_channel() {
+        let subsurface = Subsurface {
+            albedo: Color::new_color(1.0, 0.5, 0.0),
+            mean_free_path: Color::new_color(1.0, 0.5, 0.25),
+            ..Default::default()
+        };
+        let medium = subsurface.medium();
+        let sigma_t = medium.sigma_t();
+        assert_abs_diff_eq!(sigma_t.get_red_val(), 1.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(sigma_t.get_green_val(), 2.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(sigma_t.get_blue_val(), 4.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(medium.sigma_a.get_red_val(), 0.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(medium.sigma_s.get_blue_val(), 0.0, epsilon = 1e-3);
+    }
+}
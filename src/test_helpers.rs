@@ -0,0 +1,106 @@
+use std::f32::consts::PI;
+
+use approx::assert_abs_diff_eq;
+use glam::{Vec2, Vec3A};
+use rstest::fixture;
+
+use crate::{
+    color::Color,
+    integrator::Integrator,
+    material::Material,
+    ray::Ray,
+    sampler::{IndependentSampler, Sampler},
+    texture::ImageTexture,
+    triangle::Triangle,
+    world::World,
+};
+
+/// Empty world with a large floor in the xz plane through the origin, facing up. Tests add lights and objects
+/// to it.
+#[fixture]
+pub fn floor_world(#[default(lambertian(0.5))] floor: Material) -> World {
+    let mut world = World::new_empty();
+    let mut triangle = Triangle::new(
+        Vec3A::new(-100.0, 0.0, -100.0),
+        Vec3A::new(100.0, 0.0, -100.0),
+        Vec3A::new(0.0, 0.0, 100.0),
+    );
+    triangle.set_material(floor);
+    world.add_object(triangle);
+    world
+}
+
+/// Dim 8x4 panorama with a single bright texel above the horizon.
+#[fixture]
+pub fn sunny_panorama() -> ImageTexture {
+    let mut texels = vec![Color::new_color(0.2, 0.2, 0.2); 32];
+    texels[8 + 2] = Color::new_color(50.0, 50.0, 50.0);
+    ImageTexture::new(8, 4, texels)
+}
+
+/// White diffuse material with the albedo, as the path tracing integrators see it.
+pub fn lambertian(albedo: f32) -> Material {
+    Material {
+        color: Color::new_white(),
+        diffuse: albedo,
+        ..Default::default()
+    }
+}
+
+/// Mean of the integrator's radiance estimates along the ray, with independent samples.
+pub fn average_radiance(
+    integrator: &dyn Integrator,
+    world: &World,
+    ray: Ray,
+    samples: u32,
+) -> Color {
+    let mut sampler = IndependentSampler::new(samples, 0);
+    let sum = (0..samples).fold(Color::new_black(), |acc, sample_index| {
+        sampler.start_pixel_sample(0, 0, sample_index);
+        acc + integrator.radiance(world, ray, &mut sampler)
+    });
+    sum / samples as f32
+}
+
+pub fn assert_color_eq(actual: Color, expected: Color) {
+    assert_abs_diff_eq!(actual.get_red_val(), expected.get_red_val(), epsilon = 1e-4);
+    assert_abs_diff_eq!(
+        actual.get_green_val(),
+        expected.get_green_val(),
+        epsilon = 1e-4
+    );
+    assert_abs_diff_eq!(
+        actual.get_blue_val(),
+        expected.get_blue_val(),
+        epsilon = 1e-4
+    );
+}
+
+/// Integrates the function over the directions within `max_theta` of +z with the midpoint rule, e.g. PI / 2.0
+/// for the hemisphere and PI for the whole sphere.
+pub fn integrate_directions(max_theta: f32, function: impl Fn(Vec3A) -> f32) -> f32 {
+    let theta_steps = (max_theta / PI * 800.0).ceil() as u32;
+    let phi_steps = 200;
+    let d_theta = max_theta / theta_steps as f32;
+    let d_phi = 2.0 * PI / phi_steps as f32;
+    let mut sum = 0.0;
+    for i in 0..theta_steps {
+        let theta = (i as f32 + 0.5) * d_theta;
+        for j in 0..phi_steps {
+            let phi = (j as f32 + 0.5) * d_phi;
+            let w = Vec3A::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            );
+            sum += function(w) * theta.sin() * d_theta * d_phi;
+        }
+    }
+    sum
+}
+
+/// Centers of the cells of a `count` x `count` grid over the unit square.
+pub fn strata(count: u32) -> impl Iterator<Item = Vec2> {
+    (0..count * count)
+        .map(move |i| Vec2::new((i % count) as f32 + 0.5, (i / count) as f32 + 0.5) / count as f32)
+}
@@ -0,0 +1,165 @@
+use image::ImageError;
+use log::info;
+
+use crate::color::Color;
+
+/// Image stored as linear RGB colors, sampled with texture coordinates in the 0.0 - 1.0 range.
+/// The `v` coordinate grows upwards, so `v = 1.0` is the top row of the image.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    texels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(width: u32, height: u32, texels: Vec<Color>) -> Self {
+        if width == 0 || height == 0 || texels.len() != (width * height) as usize {
+            panic!(
+                "Texture data does not match its dimensions, width: {}, height: {}, texels: {}",
+                width,
+                height,
+                texels.len()
+            );
+        }
+
+        Self {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    /// Loads any image format supported by the `image` crate. HDR and EXR values are kept as they are,
+    /// LDR images are mapped to the 0.0 - 1.0 range. Missing or undecodable files are returned as errors.
+    pub fn load_from_file(image_file_path: &str) -> Result<Self, ImageError> {
+        info!("Loading texture from file: {}", image_file_path);
+        let img = image::open(image_file_path)?.into_rgb32f();
+        let (width, height) = img.dimensions();
+        let texels = img.pixels().map(|p| Color::from_rgb_slice(&p.0)).collect();
+        Ok(Self::new(width, height, texels))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Texel at image coordinates, (0, 0) is the top left corner.
+    pub fn texel_at(&self, x: u32, y: u32) -> Color {
+        if x >= self.width || y >= self.height {
+            panic!(
+                "Texel coordinates are out of bounds, x: {}, y: {}, width: {}, height: {}",
+                x, y, self.width, self.height
+            );
+        }
+        self.texels[(y * self.width + x) as usize]
+    }
+
+    /// Nearest texel lookup, `u` wraps around and `v` is clamped.
+    pub fn sample_nearest(&self, u: f32, v: f32) -> Color {
+        let (x, y) = self.uv_to_image(u, v);
+        let x = (x.round() as i64).rem_euclid(self.width as i64) as u32;
+        let y = (y.round() as i64).clamp(0, self.height as i64 - 1) as u32;
+        self.texel_at(x, y)
+    }
+
+    /// Bilinearly filtered lookup, `u` wraps around and `v` is clamped.
+    pub fn sample_bilinear(&self, u: f32, v: f32) -> Color {
+        let (x, y) = self.uv_to_image(u, v);
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+
+        let wrap_x = |x: f32| (x as i64).rem_euclid(self.width as i64) as u32;
+        let clamp_y = |y: f32| (y as i64).clamp(0, self.height as i64 - 1) as u32;
+
+        let c00 = self.texel_at(wrap_x(x0), clamp_y(y0));
+        let c10 = self.texel_at(wrap_x(x0 + 1.0), clamp_y(y0));
+        let c01 = self.texel_at(wrap_x(x0), clamp_y(y0 + 1.0));
+        let c11 = self.texel_at(wrap_x(x0 + 1.0), clamp_y(y0 + 1.0));
+
+        (c00 * (1.0 - tx) + c10 * tx) * (1.0 - ty) + (c01 * (1.0 - tx) + c11 * tx) * ty
+    }
+
+    fn uv_to_image(&self, u: f32, v: f32) -> (f32, f32) {
+        // Texel centers are at half-integer positions
+        let x = u * self.width as f32 - 0.5;
+        let y = (1.0 - v) * self.height as f32 - 0.5;
+        (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use super::*;
+
+    #[fixture]
+    pub fn texture() -> ImageTexture {
+        // Top row: red, green. Bottom row: blue, white.
+        ImageTexture::new(
+            2,
+            2,
+            vec![
+                Color::new_red(),
+                Color::new_green(),
+                Color::new_blue(),
+                Color::new_white(),
+            ],
+        )
+    }
+
+    #[test]
+    fn loading_a_missing_texture_returns_an_error() {
+        let path = std::env::temp_dir().join("loading_a_missing_texture_returns_an_error.png");
+        let result = ImageTexture::load_from_file(path.to_str().expect("should be a valid path"));
+        assert!(matches!(result, Err(ImageError::IoError(_))));
+    }
+
+    #[rstest]
+    fn can_get_texel_at_image_coordinates(texture: ImageTexture) {
+        assert_eq!(texture.width(), 2);
+        assert_eq!(texture.height(), 2);
+        assert_eq!(texture.texel_at(1, 0), Color::new_green());
+        assert_eq!(texture.texel_at(0, 1), Color::new_blue());
+    }
+
+    #[rstest]
+    #[case::top_left(0.25, 0.75, Color::new_red())]
+    #[case::top_right(0.75, 0.75, Color::new_green())]
+    #[case::bottom_left(0.25, 0.25, Color::new_blue())]
+    #[case::bottom_right(0.75, 0.25, Color::new_white())]
+    #[case::wrapped_u(1.25, 0.75, Color::new_red())]
+    fn can_sample_texture_with_nearest_lookup(
+        texture: ImageTexture,
+        #[case] u: f32,
+        #[case] v: f32,
+        #[case] expected_color: Color,
+    ) {
+        assert_eq!(texture.sample_nearest(u, v), expected_color);
+    }
+
+    #[rstest]
+    fn bilinear_lookup_blends_neighbouring_texels(texture: ImageTexture) {
+        let at_texel_center = texture.sample_bilinear(0.25, 0.75);
+        assert_eq!(at_texel_center, Color::new_red());
+
+        let between_top_texels = texture.sample_bilinear(0.5, 0.75);
+        assert_abs_diff_eq!(between_top_texels.get_red_val(), 0.5);
+        assert_abs_diff_eq!(between_top_texels.get_green_val(), 0.5);
+        assert_abs_diff_eq!(between_top_texels.get_blue_val(), 0.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn creating_texture_with_mismatched_dimensions_panics() {
+        ImageTexture::new(2, 2, vec![Color::new_black()]);
+    }
+}
@@ -0,0 +1,289 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use glam::{Affine3A, Vec2, Vec3A};
+
+use crate::bump_map::TangentFrame;
+use crate::intersection::{Intersections, SingleIntersection, EPSILON};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::shape::Shape;
+
+static TRIANGLE_ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Texture coordinates used when the mesh does not provide any, they match the barycentric coordinates.
+const DEFAULT_UVS: [Vec2; 3] = [
+    Vec2::new(0.0, 0.0),
+    Vec2::new(1.0, 0.0),
+    Vec2::new(0.0, 1.0),
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Triangle {
+    pub id: String,
+    p1: Vec3A,
+    p2: Vec3A,
+    p3: Vec3A,
+    e1: Vec3A,
+    e2: Vec3A,
+    normal: Vec3A,
+    vertex_normals: Option<[Vec3A; 3]>,
+    vertex_uvs: Option<[Vec2; 3]>,
+    transform: Affine3A,
+    material: Material,
+}
+
+impl Triangle {
+    pub fn new(p1: Vec3A, p2: Vec3A, p3: Vec3A) -> Self {
+        let triangle_id = format!(
+            "triangle-{}",
+            TRIANGLE_ID_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+
+        Self {
+            id: triangle_id,
+            p1,
+            p2,
+            p3,
+            e1,
+            e2,
+            normal: e2.cross(e1).normalize(),
+            vertex_normals: None,
+            vertex_uvs: None,
+            transform: Affine3A::default(),
+            material: Material::default(),
+        }
+    }
+
+    pub fn points(&self) -> [Vec3A; 3] {
+        [self.p1, self.p2, self.p3]
+    }
+
+    /// Per-vertex normals from mesh data, interpolated across the triangle for smooth shading.
+    pub fn set_vertex_normals(&mut self, vertex_normals: [Vec3A; 3]) {
+        self.vertex_normals = Some(vertex_normals)
+    }
+
+    /// Per-vertex texture coordinates from mesh data, they also orient the tangent frame.
+    pub fn set_vertex_uvs(&mut self, vertex_uvs: [Vec2; 3]) {
+        self.vertex_uvs = Some(vertex_uvs)
+    }
+
+    pub fn set_transform(&mut self, transform: Affine3A) {
+        self.transform = transform
+    }
+
+    pub fn set_material(&mut self, material: Material) {
+        self.material = material
+    }
+}
+
+impl Shape for Triangle {
+    fn id(&self) -> &String {
+        &self.id
+    }
+
+    fn transform(&self) -> &Affine3A {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    /// Möller–Trumbore algorithm, the barycentric coordinates are stored in the intersection.
+    fn local_intersect(&self, local_ray: Ray) -> Intersections<'_> {
+        let dir_cross_e2 = local_ray.direction_vector.cross(self.e2);
+        let determinant = self.e1.dot(dir_cross_e2);
+        if determinant.abs() < EPSILON {
+            return Intersections::new(vec![]);
+        }
+
+        let f = 1.0 / determinant;
+        let p1_to_origin = local_ray.origin_point - self.p1;
+        let u = f * p1_to_origin.dot(dir_cross_e2);
+        if !(0.0..=1.0).contains(&u) {
+            return Intersections::new(vec![]);
+        }
+
+        let origin_cross_e1 = p1_to_origin.cross(self.e1);
+        let v = f * local_ray.direction_vector.dot(origin_cross_e1);
+        if v < 0.0 || (u + v) > 1.0 {
+            return Intersections::new(vec![]);
+        }
+
+        let t = f * self.e2.dot(origin_cross_e1);
+        Intersections::new(vec![SingleIntersection::new_with_uv(t, &self.id, u, v)])
+    }
+
+    fn local_normal_at(&self, _local_point: Vec3A) -> Vec3A {
+        self.normal
+    }
+
+    fn local_area(&self) -> f32 {
+        self.e1.cross(self.e2).length() / 2.0
+    }
+
+    /// Square root warping keeps the barycentric coordinates uniform over the triangle.
+    fn local_sample_point(&self, u: Vec2) -> Vec3A {
+        let sqrt_u = u.x.sqrt();
+        let b1 = 1.0 - sqrt_u;
+        let b2 = u.y * sqrt_u;
+        self.p1 + self.e1 * b1 + self.e2 * b2
+    }
+
+    fn local_uv_at(&self, _local_point: Vec3A, hit: &SingleIntersection) -> Vec2 {
+        let [uv1, uv2, uv3] = self.vertex_uvs.unwrap_or(DEFAULT_UVS);
+        uv1 * (1.0 - hit.u - hit.v) + uv2 * hit.u + uv3 * hit.v
+    }
+
+    fn local_tangent_frame_at(
+        &self,
+        _local_point: Vec3A,
+        hit: &SingleIntersection,
+    ) -> TangentFrame {
+        let shading_normal = match self.vertex_normals {
+            Some([n1, n2, n3]) => n2 * hit.u + n3 * hit.v + n1 * (1.0 - hit.u - hit.v),
+            None => self.normal,
+        };
+        TangentFrame::from_triangle(
+            self.points(),
+            self.vertex_uvs.unwrap_or(DEFAULT_UVS),
+            shading_normal,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use glam::Vec3;
+    use rstest::*;
+
+    use super::*;
+
+    #[fixture]
+    pub fn triangle() -> Triangle {
+        Triangle::new(
+            Vec3A::new(0.0, 1.0, 0.0),
+            Vec3A::new(-1.0, 0.0, 0.0),
+            Vec3A::new(1.0, 0.0, 0.0),
+        )
+    }
+
+    #[fixture]
+    pub fn smooth_triangle(mut triangle: Triangle) -> Triangle {
+        triangle.set_vertex_normals([
+            Vec3A::new(0.0, 1.0, 0.0),
+            Vec3A::new(-1.0, 0.0, 0.0),
+            Vec3A::new(1.0, 0.0, 0.0),
+        ]);
+        triangle
+    }
+
+    #[rstest]
+    fn constructing_a_triangle(triangle: Triangle) {
+        assert!(triangle.id.starts_with("triangle-"));
+        assert_eq!(triangle.e1, Vec3A::new(-1.0, -1.0, 0.0));
+        assert_eq!(triangle.e2, Vec3A::new(1.0, -1.0, 0.0));
+        assert_eq!(triangle.normal, Vec3A::new(0.0, 0.0, -1.0));
+    }
+
+    #[rstest]
+    fn finding_the_normal_on_a_triangle(triangle: Triangle) {
+        assert_eq!(
+            triangle.local_normal_at(Vec3A::new(0.0, 0.5, 0.0)),
+            triangle.normal
+        );
+        assert_eq!(
+            triangle.local_normal_at(Vec3A::new(-0.5, 0.75, 0.0)),
+            triangle.normal
+        );
+    }
+
+    #[rstest]
+    #[case::parallel_ray(Vec3A::new(0.0, -1.0, -2.0), Vec3A::new(0.0, 1.0, 0.0))]
+    #[case::misses_the_p1_p3_edge(Vec3A::new(1.0, 1.0, -2.0), Vec3A::new(0.0, 0.0, 1.0))]
+    #[case::misses_the_p1_p2_edge(Vec3A::new(-1.0, 1.0, -2.0), Vec3A::new(0.0, 0.0, 1.0))]
+    #[case::misses_the_p2_p3_edge(Vec3A::new(0.0, -1.0, -2.0), Vec3A::new(0.0, 0.0, 1.0))]
+    fn ray_misses_a_triangle(triangle: Triangle, #[case] origin: Vec3A, #[case] direction: Vec3A) {
+        let intersections = triangle.local_intersect(Ray::new(origin, direction));
+        assert!(intersections.i.is_empty());
+    }
+
+    #[rstest]
+    fn ray_strikes_a_triangle(triangle: Triangle) {
+        let ray = Ray::new(Vec3A::new(0.0, 0.5, -2.0), Vec3A::new(0.0, 0.0, 1.0));
+        let intersections = triangle.local_intersect(ray);
+        assert_eq!(intersections.i.len(), 1);
+        assert_abs_diff_eq!(intersections.i[0].t, 2.0);
+    }
+
+    #[rstest]
+    fn intersection_with_a_triangle_stores_u_and_v(smooth_triangle: Triangle) {
+        let ray = Ray::new(Vec3A::new(-0.2, 0.3, -2.0), Vec3A::new(0.0, 0.0, 1.0));
+        let intersections = smooth_triangle.local_intersect(ray);
+        assert_abs_diff_eq!(intersections.i[0].u, 0.45, epsilon = 1e-6);
+        assert_abs_diff_eq!(intersections.i[0].v, 0.25, epsilon = 1e-6);
+    }
+
+    #[rstest]
+    fn smooth_triangle_interpolates_the_shading_normal(smooth_triangle: Triangle) {
+        let hit = SingleIntersection::new_with_uv(1.0, &smooth_triangle.id, 0.45, 0.25);
+        let frame = smooth_triangle.local_tangent_frame_at(Vec3A::ZERO, &hit);
+        assert!(frame
+            .normal
+            .abs_diff_eq(Vec3A::new(-0.5547, 0.83205, 0.0), 1e-4));
+        // Geometric normal is not affected
+        assert_eq!(
+            smooth_triangle.local_normal_at(Vec3A::ZERO),
+            smooth_triangle.normal
+        );
+    }
+
+    #[rstest]
+    fn triangle_interpolates_vertex_texture_coordinates(mut triangle: Triangle) {
+        let triangle_id = triangle.id.clone();
+        let hit = SingleIntersection::new_with_uv(1.0, &triangle_id, 0.25, 0.5);
+        assert!(triangle
+            .local_uv_at(Vec3A::ZERO, &hit)
+            .abs_diff_eq(Vec2::new(0.25, 0.5), 1e-6));
+
+        triangle.set_vertex_uvs([
+            Vec2::new(0.5, 1.0),
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+        ]);
+        assert!(triangle
+            .local_uv_at(Vec3A::ZERO, &hit)
+            .abs_diff_eq(Vec2::new(0.625, 0.25), 1e-6));
+
+        // The texture is mapped without distortion, so the tangent frame follows the x and y axes
+        let frame = triangle.local_tangent_frame_at(Vec3A::ZERO, &hit);
+        assert!(frame.tangent.abs_diff_eq(Vec3A::new(1.0, 0.0, 0.0), 1e-6));
+        assert!(frame.bitangent.abs_diff_eq(Vec3A::new(0.0, 1.0, 0.0), 1e-6));
+    }
+
+    #[rstest]
+    fn sampled_points_are_uniform_over_the_triangle(mut triangle: Triangle) {
+        // Non-uniform scale along the triangle's plane stretches its area
+        triangle.set_transform(Affine3A::from_scale(Vec3::new(2.0, 3.0, 5.0)));
+        let steps = 16;
+        let mut centroid = Vec3A::ZERO;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u = Vec2::new(
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                let (point, pdf) = triangle.sample_point(u);
+                assert_abs_diff_eq!(1.0 / pdf, 6.0, epsilon = 1e-4);
+                assert_abs_diff_eq!(point.z, 0.0);
+                centroid += point;
+            }
+        }
+        centroid /= (steps * steps) as f32;
+        assert!(centroid.abs_diff_eq(Vec3A::new(0.0, 1.0, 0.0), 1e-2));
+    }
+}
@@ -0,0 +1,662 @@
+use std::sync::OnceLock;
+
+use glam::{Affine3A, Vec3, Vec3A};
+
+use crate::{
+    background::Background,
+    color::Color,
+    emitter::Emitter,
+    intersection::{Computations, Intersections},
+    light::{Light, LightSample, PointLight},
+    light_sampler::LightSampler,
+    material::Material,
+    medium::Medium,
+    ray::Ray,
+    sampler::Sampler,
+    shape::Shape,
+    sphere::Sphere,
+};
+
+/// Default limit for recursive reflection rays, prevents infinite recursion between mirrors.
+pub const MAX_REFLECTION_DEPTH: u8 = 5;
+
+#[derive(Debug)]
+pub struct World {
+    objects: Vec<Box<dyn Shape>>,
+    lights: Vec<Light>,
+    background: Background,
+    /// Medium filling the space outside of the objects
+    atmosphere: Option<Medium>,
+    /// Built on first use, adding objects or lights resets it
+    light_sampler: OnceLock<LightSampler>,
+}
+
+impl World {
+    pub fn new_empty() -> Self {
+        Self {
+            objects: vec![],
+            lights: vec![],
+            background: Background::SolidColor(Color::new_black()),
+            atmosphere: None,
+            light_sampler: OnceLock::new(),
+        }
+    }
+
+    /// The default world from the book: two concentric spheres lit by a single point light.
+    pub fn new_default() -> Self {
+        let mut outer_sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        outer_sphere.set_material(Material {
+            color: Color::new_color(0.8, 1.0, 0.6),
+            diffuse: 0.7,
+            specular: 0.2,
+            ..Default::default()
+        });
+        let mut inner_sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        inner_sphere.set_transform(Affine3A::from_scale(Vec3::new(0.5, 0.5, 0.5)));
+
+        let mut world = Self::new_empty();
+        world.add_object(outer_sphere);
+        world.add_object(inner_sphere);
+        world.add_light(PointLight::new(
+            Vec3A::new(-10.0, 10.0, -10.0),
+            Color::new_white(),
+        ));
+        world
+    }
+
+    pub fn add_object(&mut self, object: impl Shape + 'static) {
+        self.objects.push(Box::new(object));
+        self.light_sampler = OnceLock::new();
+    }
+
+    /// Adds the light, environment lights also replace the background.
+    pub fn add_light(&mut self, light: impl Into<Light>) {
+        let light = light.into();
+        if let Light::Environment(environment) = &light {
+            self.background = environment.background().clone();
+        }
+        self.lights.push(light);
+        self.light_sampler = OnceLock::new();
+    }
+
+    /// The environment light, if the world's background lights the scene.
+    pub fn environment_light(&self) -> Option<&Light> {
+        self.lights
+            .iter()
+            .find(|light| matches!(light, Light::Environment(_)))
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    /// Fills the space outside of the objects with the medium, like fog or haze. The atmosphere ends at the last
+    /// surface, rays that leave the world see the background without it.
+    pub fn set_atmosphere(&mut self, atmosphere: Medium) {
+        self.atmosphere = Some(atmosphere);
+    }
+
+    pub fn atmosphere(&self) -> Option<&Medium> {
+        self.atmosphere.as_ref()
+    }
+
+    /// Medium on the other side of the object's surface from the ray that hit it. Media do not nest, leaving
+    /// an object goes back to the atmosphere.
+    pub fn medium_across<'a>(
+        &'a self,
+        object: &'a dyn Shape,
+        comps: &Computations,
+    ) -> Option<&'a Medium> {
+        if comps.inside {
+            self.atmosphere()
+        } else {
+            object.material().medium.as_ref()
+        }
+    }
+
+    pub fn objects(&self) -> &[Box<dyn Shape>] {
+        &self.objects
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// All lights and objects with emissive materials, which are sampled as area lights by the path tracer.
+    pub fn emitters(&self) -> Vec<Emitter<'_>> {
+        self.indexed_emitters()
+            .map(|(_, emitter)| emitter)
+            .collect()
+    }
+
+    /// Chooses one of the emitters with the 1D sample, returns it with the probability of choosing it.
+    pub fn sample_emitter(&self, u: f32) -> Option<(Emitter<'_>, f32)> {
+        let (index, pmf) = self.light_sampler().sample(u)?;
+        let emitter = match self.lights.get(index) {
+            Some(light) => Emitter::Light(light),
+            None => Emitter::Object(self.objects[index - self.lights.len()].as_ref()),
+        };
+        Some((emitter, pmf))
+    }
+
+    /// Probability of `sample_emitter` choosing the emitter.
+    pub fn emitter_pmf(&self, emitter: Emitter) -> f32 {
+        self.light_sampler().pmf(emitter)
+    }
+
+    fn light_sampler(&self) -> &LightSampler {
+        self.light_sampler
+            .get_or_init(|| LightSampler::new(self.indexed_emitters()))
+    }
+
+    /// Emitters with their indices, lights are indexed first and objects after them.
+    fn indexed_emitters(&self) -> impl Iterator<Item = (usize, Emitter<'_>)> {
+        let lights = self.lights.iter().map(Emitter::Light).enumerate();
+        let objects = self
+            .objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| object.material().emissive != Color::new_black())
+            .map(|(i, object)| (self.lights.len() + i, Emitter::Object(object.as_ref())));
+        lights.chain(objects)
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn object_by_id(&self, id: &str) -> Option<&dyn Shape> {
+        self.objects
+            .iter()
+            .find(|object| object.id() == id)
+            .map(|object| object.as_ref())
+    }
+
+    pub fn intersect(&self, ray: Ray) -> Intersections<'_> {
+        let all_intersections = self
+            .objects
+            .iter()
+            .flat_map(|object| object.intersect(ray).i)
+            .collect();
+        Intersections::new(all_intersections)
+    }
+
+    /// Shades the hit with one light chosen by `sample_emitter`, weighted by the probability of choosing it,
+    /// so the cost does not grow with the number of lights. Emitters that Phong shading does not handle
+    /// (emissive objects and environment lights) add nothing when chosen. Area light samples are used both
+    /// for the shadow test and for lighting.
+    pub fn shade_hit(
+        &self,
+        comps: &Computations,
+        remaining: u8,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let object = self
+            .object_by_id(&comps.object_id)
+            .expect("should find the object that was hit in the world");
+        let material = object.material();
+
+        let surface = match self.sample_emitter(sampler.next_1d()) {
+            Some((Emitter::Light(light), pmf)) => {
+                let samples = light.samples(comps.over_point, sampler);
+                if samples.is_empty() {
+                    Color::new_black()
+                } else {
+                    let light_intensity = self.intensity_at(&samples, comps.over_point, comps.time);
+                    material.lighting(
+                        light,
+                        &samples,
+                        comps.eye_vector,
+                        comps.normal_vector,
+                        light_intensity,
+                    ) / pmf
+                }
+            }
+            _ => Color::new_black(),
+        };
+
+        material.emissive + surface + self.reflected_color(comps, remaining, sampler)
+    }
+
+    /// Computations for the closest hit along the ray, `None` if the ray does not hit anything.
+    pub fn hit_computations(&self, ray: Ray) -> Option<Computations> {
+        let intersections = self.intersect(ray);
+        intersections.hit().map(|hit| {
+            let object = self
+                .object_by_id(hit.object_id)
+                .expect("should find the object that was hit in the world");
+            hit.prepare_computations(&ray, object)
+        })
+    }
+
+    /// Color seen along the ray. Rays that do not hit anything sample the world background.
+    pub fn color_at(&self, ray: Ray, remaining: u8, sampler: &mut dyn Sampler) -> Color {
+        match self.hit_computations(ray) {
+            Some(comps) => self.shade_hit(&comps, remaining, sampler),
+            None => self.background.color_for_direction(ray.direction_vector),
+        }
+    }
+
+    pub fn reflected_color(
+        &self,
+        comps: &Computations,
+        remaining: u8,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let reflective = self
+            .object_by_id(&comps.object_id)
+            .expect("should find the object that was hit in the world")
+            .material()
+            .reflective;
+        if remaining == 0 || reflective == 0.0 {
+            return Color::new_black();
+        }
+
+        let reflect_ray = Ray::new_at_time(comps.over_point, comps.reflect_vector, comps.time);
+        self.color_at(reflect_ray, remaining - 1, sampler) * reflective
+    }
+
+    /// Fraction of the light's samples that are visible from the point at the time, 0.0 means full shadow.
+    pub fn intensity_at(&self, samples: &[LightSample], point: Vec3A, time: f32) -> f32 {
+        let visible_samples = samples
+            .iter()
+            .filter(|sample| {
+                let shadow_ray = Ray::new_at_time(point, sample.direction, time);
+                !self.is_occluded(shadow_ray, sample.distance)
+            })
+            .count();
+        visible_samples as f32 / samples.len() as f32
+    }
+
+    pub fn is_shadowed(&self, light_position: Vec3A, point: Vec3A) -> bool {
+        let point_to_light = light_position - point;
+        let distance = point_to_light.length();
+        self.is_occluded(Ray::new(point, point_to_light / distance), distance)
+    }
+
+    /// Checks if any object blocks the shadow ray up to the distance.
+    pub fn is_occluded(&self, shadow_ray: Ray, distance: f32) -> bool {
+        match self.intersect(shadow_ray).hit() {
+            Some(hit) => hit.t < distance,
+            None => false,
+        }
+    }
+
+    /// Fraction of light that gets along the ray to the distance, starting in the medium. Surfaces block
+    /// the light, except for boundaries of media, which it passes through.
+    pub fn transmittance(
+        &self,
+        ray: Ray,
+        distance: f32,
+        medium: Option<&Medium>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut transmittance = Color::new_white();
+        let mut ray = ray;
+        let mut remaining = distance;
+        let mut medium = medium;
+        loop {
+            let hit = self
+                .hit_computations(ray)
+                .filter(|comps| comps.t < remaining);
+            let segment = hit.as_ref().map_or(remaining, |comps| comps.t);
+            if let Some(medium) = medium.filter(|_| segment.is_finite()) {
+                transmittance = transmittance * medium.transmittance(ray, segment, sampler);
+            }
+            let comps = match hit {
+                Some(comps) => comps,
+                None => return transmittance,
+            };
+            let object = self
+                .object_by_id(&comps.object_id)
+                .expect("should find the object that was hit in the world");
+            if !object.material().is_medium_boundary() {
+                return Color::new_black();
+            }
+            medium = self.medium_across(object, &comps);
+            remaining -= comps.t;
+            ray = ray.spawn(comps.under_point, ray.direction_vector);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rstest::*;
+
+    use crate::{
+        bsdf::Bsdf,
+        intersection::SingleIntersection,
+        light::{AreaLight, DirectionalLight, EnvironmentLight},
+        sampler::{IndependentSampler, SobolSampler},
+        test_helpers::assert_color_eq,
+        texture::ImageTexture,
+    };
+
+    use super::*;
+
+    #[fixture]
+    pub fn world() -> World {
+        World::new_default()
+    }
+
+    #[fixture]
+    pub fn sampler() -> IndependentSampler {
+        IndependentSampler::new(1, 0)
+    }
+
+    fn intensity_at(world: &World, light: &Light, point: Vec3A, sampler: &mut dyn Sampler) -> f32 {
+        world.intensity_at(&light.samples(point, sampler), point, 0.0)
+    }
+
+    #[test]
+    fn creating_an_empty_world() {
+        let world = World::new_empty();
+        assert!(world.objects().is_empty());
+        assert!(world.lights().is_empty());
+        assert_eq!(
+            world.background(),
+            &Background::SolidColor(Color::new_black())
+        );
+    }
+
+    #[rstest]
+    fn the_default_world(world: World) {
+        assert_eq!(world.objects().len(), 2);
+        assert_eq!(world.lights().len(), 1);
+        let first_id = world.objects()[0].id().clone();
+        assert!(world.object_by_id(&first_id).is_some());
+        assert!(world.object_by_id("missing-object").is_none());
+    }
+
+    #[rstest]
+    fn intersect_a_world_with_a_ray(world: World) {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let intersections = world.intersect(ray);
+        let ts: Vec<f32> = intersections.i.iter().map(|i| i.t).collect();
+        assert_eq!(ts, vec![4.0, 4.5, 5.5, 6.0]);
+    }
+
+    #[rstest]
+    fn shading_an_intersection(world: World) {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let shape = world.objects()[0].as_ref();
+        let intersection = SingleIntersection::new(4.0, shape.id());
+        let comps = intersection.prepare_computations(&ray, shape);
+        let color = world.shade_hit(&comps, MAX_REFLECTION_DEPTH, &mut sampler());
+        assert_color_eq(color, Color::new_color(0.38066, 0.47583, 0.2855));
+    }
+
+    #[rstest]
+    fn the_color_when_a_ray_hits(world: World) {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let color = world.color_at(ray, MAX_REFLECTION_DEPTH, &mut sampler());
+        assert_color_eq(color, Color::new_color(0.38066, 0.47583, 0.2855));
+    }
+
+    #[rstest]
+    fn the_color_when_a_ray_misses_is_the_background(mut world: World) {
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            world.color_at(ray, MAX_REFLECTION_DEPTH, &mut sampler()),
+            Color::new_black()
+        );
+
+        // Upper half of the panorama is blue sky, lower half is green ground
+        world.set_background(Background::Equirectangular(ImageTexture::new(
+            1,
+            2,
+            vec![Color::new_blue(), Color::new_green()],
+        )));
+        assert_eq!(
+            world.color_at(ray, MAX_REFLECTION_DEPTH, &mut sampler()),
+            Color::new_blue()
+        );
+    }
+
+    #[rstest]
+    fn environment_lights_become_the_background(mut world: World) {
+        assert!(world.environment_light().is_none());
+        world.add_light(EnvironmentLight::new(Background::SolidColor(
+            Color::new_blue(),
+        )));
+        assert!(world.environment_light().is_some());
+        assert_eq!(
+            world.background(),
+            &Background::SolidColor(Color::new_blue())
+        );
+
+        // Whitted shading ignores the environment light, only the point light shades the sphere. Each of them
+        // is chosen by one of the two samples, the point light is weighted up to make up for it.
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let mut sampler = SobolSampler::new(2, 0);
+        let colors: Vec<Color> = (0..2)
+            .map(|sample_index| {
+                sampler.start_pixel_sample(0, 0, sample_index);
+                world.color_at(ray, MAX_REFLECTION_DEPTH, &mut sampler)
+            })
+            .collect();
+        assert_color_eq(
+            (colors[0] + colors[1]) * 0.5,
+            Color::new_color(0.38066, 0.47583, 0.2855),
+        );
+    }
+
+    #[rstest]
+    #[case::nothing_is_collinear_with_point_and_light(Vec3A::new(0.0, 10.0, 0.0), false)]
+    #[case::object_between_point_and_light(Vec3A::new(10.0, -10.0, 10.0), true)]
+    #[case::object_behind_the_light(Vec3A::new(-20.0, 20.0, -20.0), false)]
+    #[case::object_behind_the_point(Vec3A::new(-2.0, 2.0, -2.0), false)]
+    fn shadows_in_the_default_world(
+        world: World,
+        #[case] point: Vec3A,
+        #[case] expected_in_shadow: bool,
+    ) {
+        let light_position = Vec3A::new(-10.0, 10.0, -10.0);
+        assert_eq!(world.is_shadowed(light_position, point), expected_in_shadow);
+    }
+
+    #[rstest]
+    #[case(Vec3A::new(0.0, 1.0001, 0.0), 1.0)]
+    #[case(Vec3A::new(-1.0001, 0.0, 0.0), 1.0)]
+    #[case(Vec3A::new(0.0, 0.0, -1.0001), 1.0)]
+    #[case(Vec3A::new(0.0, 0.0, 1.0001), 0.0)]
+    #[case(Vec3A::new(1.0001, 0.0, 0.0), 0.0)]
+    #[case(Vec3A::new(0.0, -1.0001, 0.0), 0.0)]
+    #[case(Vec3A::new(0.0, 0.0, 0.0), 0.0)]
+    fn point_lights_evaluate_the_light_intensity_at_a_given_point(
+        world: World,
+        #[case] point: Vec3A,
+        #[case] expected_intensity: f32,
+    ) {
+        let light = &world.lights()[0];
+        assert_abs_diff_eq!(
+            intensity_at(&world, light, point, &mut sampler()),
+            expected_intensity
+        );
+    }
+
+    #[rstest]
+    #[case(Vec3A::new(0.0, 0.0, 2.0), 0.0)]
+    #[case(Vec3A::new(1.0, -1.0, 2.0), 0.25)]
+    #[case(Vec3A::new(1.5, 0.0, 2.0), 0.5)]
+    #[case(Vec3A::new(1.25, 1.25, 3.0), 0.75)]
+    #[case(Vec3A::new(0.0, 0.0, -2.0), 1.0)]
+    fn area_lights_evaluate_the_light_intensity_at_a_given_point(
+        world: World,
+        #[case] point: Vec3A,
+        #[case] expected_intensity: f32,
+    ) {
+        let mut area_light = AreaLight::new(
+            Vec3A::new(-0.5, -0.5, -5.0),
+            Vec3A::new(1.0, 0.0, 0.0),
+            2,
+            Vec3A::new(0.0, 1.0, 0.0),
+            2,
+            Color::new_white(),
+        );
+        area_light.set_jitter(false);
+        let light = Light::Area(area_light);
+        assert_abs_diff_eq!(
+            intensity_at(&world, &light, point, &mut sampler()),
+            expected_intensity
+        );
+    }
+
+    #[test]
+    fn jittered_area_light_casts_reproducible_soft_shadows() {
+        let mut world = World::new_default();
+        let area_light = AreaLight::new(
+            Vec3A::new(-0.5, -0.5, -5.0),
+            Vec3A::new(1.0, 0.0, 0.0),
+            4,
+            Vec3A::new(0.0, 1.0, 0.0),
+            4,
+            Color::new_white(),
+        );
+        world.add_light(area_light.clone());
+        let light = Light::Area(area_light);
+        // The point is in the penumbra, so only some of the samples are visible
+        let point = Vec3A::new(1.5, 0.0, 2.0);
+        let mut sampler = sampler();
+        sampler.start_pixel_sample(4, 2, 0);
+        let intensity = intensity_at(&world, &light, point, &mut sampler);
+        assert!(intensity > 0.0 && intensity < 1.0);
+        sampler.start_pixel_sample(4, 2, 0);
+        assert_abs_diff_eq!(intensity_at(&world, &light, point, &mut sampler), intensity);
+    }
+
+    #[test]
+    fn directional_light_is_blocked_by_objects_at_any_distance() {
+        let mut world = World::new_empty();
+        let mut far_away_sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        far_away_sphere.set_transform(Affine3A::from_translation(Vec3::new(0.0, 10000.0, 0.0)));
+        world.add_object(far_away_sphere);
+        let light = Light::from(DirectionalLight::new(
+            Vec3A::new(0.0, -1.0, 0.0),
+            Color::new_white(),
+        ));
+        assert_abs_diff_eq!(
+            intensity_at(&world, &light, Vec3A::ZERO, &mut sampler()),
+            0.0
+        );
+        assert_abs_diff_eq!(
+            intensity_at(&world, &light, Vec3A::new(5.0, 0.0, 0.0), &mut sampler()),
+            1.0
+        );
+    }
+
+    #[test]
+    fn shade_hit_chooses_one_of_the_lights() {
+        let mut world = World::new_default();
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let comps = world
+            .hit_computations(ray)
+            .expect("should hit the outer sphere");
+        let one_light = world.shade_hit(&comps, 0, &mut sampler());
+        world.add_light(PointLight::new(
+            Vec3A::new(10.0, 10.0, -10.0),
+            Color::new_white(),
+        ));
+
+        // Lights of the same power are chosen by one of the two samples each, on average both of them light
+        // the sphere
+        let mut sampler = SobolSampler::new(2, 0);
+        let colors: Vec<Color> = (0..2)
+            .map(|sample_index| {
+                sampler.start_pixel_sample(0, 0, sample_index);
+                world.shade_hit(&comps, 0, &mut sampler)
+            })
+            .collect();
+        assert_color_eq(colors[0], colors[1]);
+        assert_color_eq((colors[0] + colors[1]) * 0.5, one_light * 2.0);
+    }
+
+    #[test]
+    fn shade_hit_is_given_an_intersection_in_shadow() {
+        let mut world = World::new_empty();
+        world.add_light(PointLight::new(
+            Vec3A::new(0.0, 0.0, -10.0),
+            Color::new_white(),
+        ));
+        world.add_object(Sphere::new(Vec3A::new(0.0, 0.0, 0.0)));
+        let mut sphere = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        sphere.set_transform(Affine3A::from_translation(Vec3::new(0.0, 0.0, 10.0)));
+        let intersection = SingleIntersection::new(4.0, &sphere.id);
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, 5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let comps = intersection.prepare_computations(&ray, &sphere);
+        world.add_object(sphere);
+        let color = world.shade_hit(&comps, MAX_REFLECTION_DEPTH, &mut sampler());
+        assert_color_eq(color, Color::new_color(0.1, 0.1, 0.1));
+    }
+
+    #[test]
+    fn reflective_surface_reflects_the_background() {
+        let mut world = World::new_empty();
+        world.set_background(Background::SolidColor(Color::new_red()));
+        let mut mirror = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        mirror.set_material(Material {
+            color: Color::new_black(),
+            ambient: 0.0,
+            reflective: 1.0,
+            ..Default::default()
+        });
+        world.add_object(mirror);
+
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        assert_color_eq(
+            world.color_at(ray, MAX_REFLECTION_DEPTH, &mut sampler()),
+            Color::new_red(),
+        );
+        // Without any reflection budget left the mirror is black
+        assert_color_eq(world.color_at(ray, 0, &mut sampler()), Color::new_black());
+    }
+
+    #[test]
+    fn mutually_reflective_surfaces_terminate() {
+        let mut world = World::new_empty();
+        world.add_light(PointLight::new(Vec3A::ZERO, Color::new_white()));
+        let mirror = Material {
+            reflective: 1.0,
+            ..Default::default()
+        };
+        let mut outer = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        outer.set_transform(Affine3A::from_scale(Vec3::new(10.0, 10.0, 10.0)));
+        outer.set_material(mirror.clone());
+        let mut inner = Sphere::new(Vec3A::new(0.0, 0.0, 0.0));
+        inner.set_material(mirror);
+        world.add_object(outer);
+        world.add_object(inner);
+
+        let ray = Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        world.color_at(ray, MAX_REFLECTION_DEPTH, &mut sampler());
+    }
+
+    #[rstest]
+    #[case::through_a_medium(None, (-1.0_f32).exp())]
+    #[case::blocked_by_a_surface(Some(Bsdf::Lambertian { albedo: Color::new_white() }), 0.0)]
+    fn light_passes_through_boundaries_of_media(#[case] bsdf: Option<Bsdf>, #[case] expected: f32) {
+        let mut world = World::new_empty();
+        let mut sphere = Sphere::new(Vec3A::ZERO);
+        sphere.set_material(Material {
+            bsdf,
+            medium: Some(Medium::new(
+                Color::new_color(0.5, 0.5, 0.5),
+                Color::new_black(),
+                0.0,
+            )),
+            ..Default::default()
+        });
+        world.add_object(sphere);
+
+        // Two units through the medium
+        let transmittance = world.transmittance(
+            Ray::new(Vec3A::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0)),
+            10.0,
+            None,
+            &mut sampler(),
+        );
+        assert_abs_diff_eq!(transmittance.get_red_val(), expected, epsilon = 1e-3);
+    }
+}